@@ -0,0 +1,87 @@
+use crate::pubclient::PUB_DEV;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config at {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to parse config from {path}: {source}")]
+    YamlError {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[error("Invalid PUB_HOSTED_URL {value}: {source}")]
+    InvalidPubHostedUrl {
+        value: String,
+        #[source]
+        source: url::ParseError,
+    },
+}
+
+/// flutter-pub's own settings, from `config.yaml` in the `flutter-pub` config directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Repositories mirroring pub.dev, tried in order when a download from the primary fails
+    #[serde(default)]
+    pub mirrors: Vec<Url>,
+    /// Extra PEM root certificates to trust, e.g. those of an intercepting proxy
+    pub ca_bundle: Option<PathBuf>,
+    /// The Flutter SDK to use when `FLUTTER_ROOT` isn't set
+    pub flutter_root: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("flutter-pub").join("config.yaml"))
+    }
+
+    /// Loads the config from its default location, if there is one
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::from_file(path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref().to_owned();
+        let contents = fs::read_to_string(&path).map_err(|e| ConfigError::IoError {
+            path: path.clone(),
+            source: e,
+        })?;
+
+        serde_yaml::from_str(&contents).map_err(|e| ConfigError::YamlError { path, source: e })
+    }
+
+    /// Where to fetch packages hosted on pub.dev from, in order: the primary (`PUB_HOSTED_URL`,
+    /// or pub.dev itself), then the configured mirrors
+    pub fn pub_dev_sources(&self, pub_hosted_url: Option<Url>) -> Vec<Url> {
+        let primary = pub_hosted_url.unwrap_or_else(pub_dev_url);
+        std::iter::once(primary)
+            .chain(self.mirrors.iter().cloned())
+            .collect()
+    }
+}
+
+pub fn pub_dev_url() -> Url {
+    Url::parse(PUB_DEV).expect("pub.dev is a valid URL")
+}
+
+/// The `PUB_HOSTED_URL` environment variable, which replaces pub.dev as the default host
+pub fn pub_hosted_url() -> Result<Option<Url>, ConfigError> {
+    match env::var("PUB_HOSTED_URL") {
+        Ok(value) if !value.trim().is_empty() => Url::parse(value.trim())
+            .map(Some)
+            .map_err(|e| ConfigError::InvalidPubHostedUrl { value, source: e }),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,411 @@
+use crate::project::Project;
+use crate::pubcache::{PubCache, PubCacheError};
+use crate::pubspec::{Pubspec, PubspecError};
+use crate::pubspecedit::Section;
+use crate::pubspeclock::{PackageDescription, PackageName};
+use crate::sdk::{SdkError, SdkPaths, SdkVersions};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DepsError {
+    #[error("{0} has no pubspec.lock, run flutter-pub on it first")]
+    NoLock(PathBuf),
+    #[error("{name} is not in the pub cache at {path}, run flutter-pub to fetch it")]
+    NotCached { name: PackageName, path: PathBuf },
+    #[error("{0} has no description in pubspec.lock")]
+    NoDescription(PackageName),
+    #[error(transparent)]
+    PubCacheError(#[from] PubCacheError),
+    #[error(transparent)]
+    PubspecError(#[from] PubspecError),
+    #[error(transparent)]
+    SdkError(#[from] SdkError),
+}
+
+/// How the root depends on a package, as `dart pub deps --json` calls it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepsKind {
+    Root,
+    Direct,
+    Dev,
+    Transitive,
+}
+
+/// A package in the graph, serialized like an entry of `dart pub deps --json`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepsPackage {
+    pub name: PackageName,
+    pub version: String,
+    pub kind: DepsKind,
+    pub source: String,
+    /// Everything it depends on, which for the root includes its dev dependencies
+    pub dependencies: Vec<PackageName>,
+    pub direct_dependencies: Vec<PackageName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev_dependencies: Option<Vec<PackageName>>,
+    /// The constraint on each dependency, as written in its pubspec
+    #[serde(skip)]
+    pub constraints: BTreeMap<PackageName, String>,
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+/// One dependency on a path through the graph, as the pubspec of `from` declares it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyEdge {
+    pub from: PackageName,
+    pub to: PackageName,
+    /// The version of `to` that is locked
+    pub version: String,
+    pub constraint: String,
+    pub section: Section,
+    pub pubspec: PathBuf,
+}
+
+/// The resolved dependency graph of a project, read from its pubspec.lock and the pubspecs
+/// of the packages it locks, so it needs nothing but the pub cache
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    pub root: DepsPackage,
+    pub packages: BTreeMap<PackageName, DepsPackage>,
+    pub sdks: SdkVersions,
+}
+
+impl DependencyGraph {
+    pub fn load(project: &Project, cache: &PubCache, sdks: &SdkPaths) -> Result<Self, DepsError> {
+        let lock = project
+            .lock
+            .as_ref()
+            .ok_or_else(|| DepsError::NoLock(project.dir.clone()))?;
+
+        let pubspec = &project.pubspec;
+        let main: Vec<_> = pubspec.dependencies.keys().map(PackageName::new).collect();
+        let dev: Vec<_> = pubspec
+            .dev_dependencies
+            .keys()
+            .map(PackageName::new)
+            .collect();
+        let root = DepsPackage {
+            name: PackageName::new(&pubspec.name),
+            version: pubspec
+                .version
+                .clone()
+                .unwrap_or_else(|| "0.0.0".to_string()),
+            kind: DepsKind::Root,
+            source: "root".to_string(),
+            dependencies: sorted(main.iter().chain(&dev)),
+            direct_dependencies: sorted(&main),
+            dev_dependencies: Some(sorted(&dev)),
+            constraints: constraints(pubspec),
+            dir: project.dir.clone(),
+        };
+
+        let mut packages = BTreeMap::new();
+        for (name, spec) in &lock.packages {
+            let description = spec
+                .description
+                .as_ref()
+                .ok_or_else(|| DepsError::NoDescription(name.clone()))?;
+            let dir = match description {
+                PackageDescription::Hosted(hosted) => {
+                    cache.get_package_path(name, &spec.version, hosted)?
+                }
+                PackageDescription::Git(git) => match &git.resolved_ref {
+                    Some(commit) => cache
+                        .git_checkout_path(name, commit)
+                        .join(git.path.as_deref().unwrap_or(".")),
+                    None => return Err(DepsError::NoDescription(name.clone())),
+                },
+                PackageDescription::Path(path) => project.dir.join(&path.path),
+                PackageDescription::Sdk(sdk) => sdks.package_dir(sdk, name)?,
+            };
+            let pubspec_path = dir.join("pubspec.yaml");
+            if !pubspec_path.is_file() {
+                return Err(DepsError::NotCached {
+                    name: name.clone(),
+                    path: dir,
+                });
+            }
+            let pubspec = Pubspec::from_file(pubspec_path)?;
+
+            let dependencies: Vec<_> = pubspec
+                .dependencies
+                .keys()
+                .map(PackageName::new)
+                .filter(|d| lock.packages.contains_key(d))
+                .collect();
+            let kind = if main.contains(name) {
+                DepsKind::Direct
+            } else if dev.contains(name) {
+                DepsKind::Dev
+            } else {
+                DepsKind::Transitive
+            };
+            let package = DepsPackage {
+                name: name.clone(),
+                version: spec.version.to_string(),
+                kind,
+                source: spec.source.clone(),
+                dependencies: sorted(&dependencies),
+                direct_dependencies: sorted(&dependencies),
+                dev_dependencies: None,
+                constraints: constraints(&pubspec),
+                dir,
+            };
+            packages.insert(name.clone(), package);
+        }
+
+        Ok(DependencyGraph {
+            root,
+            packages,
+            sdks: sdks.versions(),
+        })
+    }
+
+    /// The graph without the root's dev dependencies, or what only they depend on
+    pub fn without_dev(&self) -> DependencyGraph {
+        let mut graph = self.clone();
+        graph.root.dependencies = graph.root.direct_dependencies.clone();
+        graph.root.dev_dependencies = Some(Vec::new());
+
+        let mut reachable = BTreeSet::new();
+        let mut pending = graph.root.direct_dependencies.clone();
+        while let Some(name) = pending.pop() {
+            if let Some(package) = graph.packages.get(&name)
+                && reachable.insert(name)
+            {
+                pending.extend(package.dependencies.iter().cloned());
+            }
+        }
+        graph.packages.retain(|name, _| reachable.contains(name));
+        graph
+    }
+
+    /// Every path from the root to `name` that doesn't go through a package twice
+    pub fn paths_to(&self, name: &PackageName) -> Vec<Vec<DependencyEdge>> {
+        let mut paths = Vec::new();
+        self.walk(&self.root, name, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn walk(
+        &self,
+        from: &DepsPackage,
+        target: &PackageName,
+        path: &mut Vec<DependencyEdge>,
+        paths: &mut Vec<Vec<DependencyEdge>>,
+    ) {
+        for to in &from.dependencies {
+            let Some(package) = self.packages.get(to) else {
+                continue;
+            };
+            if to == &self.root.name || path.iter().any(|edge| &edge.from == to) {
+                continue;
+            }
+            let section = match from.kind {
+                DepsKind::Root if !from.direct_dependencies.contains(to) => {
+                    Section::DevDependencies
+                }
+                _ => Section::Dependencies,
+            };
+            path.push(DependencyEdge {
+                from: from.name.clone(),
+                to: to.clone(),
+                version: package.version.clone(),
+                constraint: from
+                    .constraints
+                    .get(to)
+                    .cloned()
+                    .unwrap_or_else(|| "any".to_string()),
+                section,
+                pubspec: from.dir.join("pubspec.yaml"),
+            });
+            match to == target {
+                true => paths.push(path.clone()),
+                false => self.walk(package, target, path, paths),
+            }
+            path.pop();
+        }
+    }
+
+    /// The output of `dart pub deps --json`
+    pub fn to_json(&self) -> serde_json::Value {
+        let packages: Vec<_> = std::iter::once(&self.root)
+            .chain(self.packages.values())
+            .collect();
+        let sdks: Vec<_> = self
+            .sdk_versions()
+            .into_iter()
+            .map(|(name, version)| serde_json::json!({ "name": name, "version": version }))
+            .collect();
+        serde_json::json!({
+            "root": self.root.name,
+            "packages": packages,
+            "sdks": sdks,
+            "executables": self.executables(),
+        })
+    }
+
+    /// The graph as a tree from the root, where packages already shown end in `...`
+    pub fn tree(&self) -> String {
+        let mut out = self.header();
+        out.push_str(&format!("{} {}\n", self.root.name, self.root.version));
+        let mut shown = BTreeSet::new();
+        self.subtree(&self.root.dependencies, "", &mut shown, &mut out);
+        out
+    }
+
+    fn subtree(
+        &self,
+        names: &[PackageName],
+        prefix: &str,
+        shown: &mut BTreeSet<PackageName>,
+        out: &mut String,
+    ) {
+        for (i, name) in names.iter().enumerate() {
+            let last = i == names.len() - 1;
+            let (branch, indent) = match last {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            let Some(package) = self.packages.get(name) else {
+                continue;
+            };
+            if !shown.insert(name.clone()) {
+                out.push_str(&format!("{}{}{}...\n", prefix, branch, name));
+                continue;
+            }
+            out.push_str(&format!(
+                "{}{}{} {}\n",
+                prefix, branch, name, package.version
+            ));
+            let prefix = format!("{}{}", prefix, indent);
+            self.subtree(&package.dependencies, &prefix, shown, out);
+        }
+    }
+
+    /// Every package by kind, with the constraints on what it depends on
+    pub fn list(&self) -> String {
+        self.sections(|package, out| {
+            for dependency in &package.dependencies {
+                let constraint = package
+                    .constraints
+                    .get(dependency)
+                    .map(String::as_str)
+                    .unwrap_or("any");
+                out.push_str(&format!("  - {} {}\n", dependency, constraint));
+            }
+        })
+    }
+
+    /// Every package by kind, on one line each with the names of what it depends on
+    pub fn compact(&self) -> String {
+        self.sections(|package, out| {
+            if !package.dependencies.is_empty() {
+                let names: Vec<_> = package.dependencies.iter().map(|d| d.as_ref()).collect();
+                out.pop();
+                out.push_str(&format!(" [{}]\n", names.join(" ")));
+            }
+        })
+    }
+
+    fn sections(&self, details: impl Fn(&DepsPackage, &mut String)) -> String {
+        let mut out = self.header();
+        out.push_str(&format!("{} {}\n", self.root.name, self.root.version));
+        for (kind, heading) in [
+            (DepsKind::Direct, "dependencies"),
+            (DepsKind::Dev, "dev dependencies"),
+            (DepsKind::Transitive, "transitive dependencies"),
+        ] {
+            let packages: Vec<_> = self.packages.values().filter(|p| p.kind == kind).collect();
+            if packages.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n{}:\n", heading));
+            for package in packages {
+                out.push_str(&format!("- {} {}\n", package.name, package.version));
+                details(package, &mut out);
+            }
+        }
+        out
+    }
+
+    /// The executables of the root and the packages it depends on directly, as they would
+    /// be run with `dart run`: `package` for `bin/<package>.dart`, `package:script` otherwise
+    pub fn executables(&self) -> Vec<String> {
+        let direct = self
+            .root
+            .dependencies
+            .iter()
+            .filter_map(|name| self.packages.get(name));
+        std::iter::once(&self.root)
+            .chain(direct)
+            .flat_map(|package| {
+                bin_scripts(&package.dir).into_iter().map(move |script| {
+                    match script == package.name.as_ref() {
+                        true => script,
+                        false => format!("{}:{}", package.name, script),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn sdk_versions(&self) -> Vec<(&'static str, String)> {
+        let dart = self.sdks.dart.as_ref().map(|v| ("Dart", v.to_string()));
+        let flutter = self
+            .sdks
+            .flutter
+            .as_ref()
+            .map(|v| ("Flutter", v.to_string()));
+        dart.into_iter().chain(flutter).collect()
+    }
+
+    fn header(&self) -> String {
+        self.sdk_versions()
+            .into_iter()
+            .map(|(name, version)| format!("{} SDK {}\n", name, version))
+            .collect()
+    }
+}
+
+fn sorted<'a>(names: impl IntoIterator<Item = &'a PackageName>) -> Vec<PackageName> {
+    let names: BTreeSet<_> = names.into_iter().cloned().collect();
+    names.into_iter().collect()
+}
+
+fn constraints(pubspec: &Pubspec) -> BTreeMap<PackageName, String> {
+    pubspec
+        .dependencies
+        .iter()
+        .chain(&pubspec.dev_dependencies)
+        .map(|(name, spec)| {
+            let constraint = spec.hosted_constraint().unwrap_or("any");
+            (PackageName::new(name), constraint.to_string())
+        })
+        .collect()
+}
+
+/// The names of the scripts directly in a package's `bin` directory
+fn bin_scripts(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir.join("bin")) else {
+        return Vec::new();
+    };
+    let mut scripts: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            match path.extension()?.to_str()? {
+                "dart" if path.is_file() => Some(path.file_stem()?.to_str()?.to_string()),
+                _ => None,
+            }
+        })
+        .collect();
+    scripts.sort();
+    scripts
+}
@@ -3,22 +3,43 @@ use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
-use crate::pubspeclock::{PackageName, PackageVersion};
+use crate::http::HttpError;
+use crate::pubcache::hosted_dir_name;
+use crate::pubclient::{PubClient, PubClientError};
+use crate::pubpackage::PubPackageVersion;
+use crate::pubspeclock::{HostedPackage, PackageName, PackageVersion, Sha256};
+use sha2::Digest;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
 
 use threadpool::ThreadPool;
+use url::Url;
 
 #[derive(Error, Debug)]
 pub enum DownloadError {
-    #[error("HTTP error: {0}")]
-    HttpError(#[from] ureq::Error),
+    #[error(transparent)]
+    HttpError(#[from] HttpError),
     #[error("IO error: {0}")]
     IoError(#[from] io::Error),
+    #[error("Invalid URL: {0}")]
+    UrlParseError(#[from] url::ParseError),
+    #[error(transparent)]
+    ListingError(#[from] PubClientError),
     #[error("Package not found: {name} {version}")]
     PackageNotFound { name: String, version: String },
+    #[error(
+        "Authentication to {hosted_url} failed with HTTP {status}. \
+         Add a token with `flutter-pub token add {hosted_url}`"
+    )]
+    AuthenticationFailed { hosted_url: Url, status: u16 },
     #[error("Invalid package archive")]
     InvalidArchive,
+    #[error("Archive for {package} has sha256 {actual}, expected {expected}")]
+    HashMismatch {
+        package: String,
+        expected: Sha256,
+        actual: Sha256,
+    },
 }
 
 #[derive(Debug)]
@@ -41,15 +62,78 @@ pub enum DownloadEvent {
     AllCompleted,
 }
 
+/// A package version to download, and the hosted repository it belongs to
+#[derive(Debug, Clone)]
+pub struct PackageDownload {
+    pub name: PackageName,
+    pub version: PackageVersion,
+    pub hosted_url: Url,
+    /// The archive hash recorded in a lockfile, which the download must match whatever its source
+    pub sha256: Option<Sha256>,
+}
+
+impl PackageDownload {
+    pub fn new(name: PackageName, version: PackageVersion, hosted_url: Url) -> Self {
+        PackageDownload {
+            name,
+            version,
+            hosted_url,
+            sha256: None,
+        }
+    }
+
+    /// A download for a package locked with the given hosted description
+    pub fn hosted(version: &PackageVersion, hosted: &HostedPackage) -> Self {
+        PackageDownload {
+            sha256: hosted.sha256.clone(),
+            ..Self::new(hosted.name.clone(), version.clone(), hosted.url.clone())
+        }
+    }
+
+    pub fn with_sha256(mut self, sha256: Sha256) -> Self {
+        self.sha256 = Some(sha256);
+        self
+    }
+}
+
+#[derive(Clone)]
 pub struct PackageDownloader {
     cache_dir: PathBuf,
+    client: PubClient,
+    mirrors: Vec<Url>,
 }
 
 impl PackageDownloader {
     pub fn new(cache_dir: impl AsRef<Path>) -> io::Result<Self> {
         let cache_dir = cache_dir.as_ref().to_path_buf();
         fs::create_dir_all(&cache_dir)?;
-        Ok(Self { cache_dir })
+        Ok(Self {
+            cache_dir,
+            client: PubClient::pub_dev(),
+            mirrors: Vec::new(),
+        })
+    }
+
+    /// Use the given client's hosted URL and agent for packages that don't name a repository
+    pub fn with_client(mut self, client: PubClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fetch packages belonging to the default hosted URL from these repositories instead, trying
+    /// each in turn until one succeeds. Packages keep the default hosted URL as their identity.
+    pub fn with_mirrors(mut self, mirrors: Vec<Url>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// The repositories to try, in order, for a package belonging to `hosted_url`
+    fn sources(&self, hosted_url: &Url) -> Vec<Url> {
+        if hosted_url == self.client.hosted_url() && !self.mirrors.is_empty() {
+            self.mirrors.clone()
+        } else {
+            vec![hosted_url.clone()]
+        }
     }
 
     pub fn download_package(
@@ -58,12 +142,42 @@ impl PackageDownloader {
         version: &PackageVersion,
         progress_tx: &Sender<DownloadEvent>,
     ) -> Result<PathBuf, DownloadError> {
-        let archive_path = self.cache_dir.join(format!("{}-{}.tar.gz", name, version));
-        if archive_path.exists() {
-            return Ok(archive_path);
-        }
+        let download = PackageDownload::new(
+            name.clone(),
+            version.clone(),
+            self.client.hosted_url().clone(),
+        );
+        self.download_package_from(&download, progress_tx)
+    }
+
+    /// Where the archive of a package version is kept. Archives are kept per hosted repository,
+    /// whichever mirror they came from, so that one repository's can't stand in for another's.
+    pub fn archive_path(&self, download: &PackageDownload) -> Result<PathBuf, DownloadError> {
+        let dir = hosted_dir_name(&download.hosted_url).ok_or(url::ParseError::EmptyHost)?;
+        Ok(self
+            .cache_dir
+            .join(dir)
+            .join(format!("{}-{}.tar.gz", download.name, download.version)))
+    }
 
+    /// Downloads a package from its hosted repository, or that repository's mirrors, using the
+    /// `archive_url` and `archive_sha256` from the listing of the package. An archive that is
+    /// already cached is used if it still has the listed hash, and fetched again if not.
+    pub fn download_package_from(
+        &self,
+        download: &PackageDownload,
+        progress_tx: &Sender<DownloadEvent>,
+    ) -> Result<PathBuf, DownloadError> {
+        let PackageDownload { name, version, .. } = download;
         let package_name = format!("{}-{}", name, version);
+        let archive_path = self.archive_path(download)?;
+        if archive_path.exists() {
+            match self.verify_cached(&archive_path, download, &package_name) {
+                Ok(()) => return Ok(archive_path),
+                Err(DownloadError::HashMismatch { .. }) => fs::remove_file(&archive_path)?,
+                Err(e) => return Err(e),
+            }
+        }
 
         progress_tx
             .send(DownloadEvent::Started {
@@ -71,18 +185,108 @@ impl PackageDownloader {
             })
             .unwrap();
 
-        let url = format!(
-            "https://pub.dev/packages/{}/versions/{}.tar.gz",
-            name, version
-        );
+        // Each source is tried in turn, so the error reported is that of the last one
+        let mut result = Ok(());
+        for source in self.sources(&download.hosted_url) {
+            result = self
+                .fetch_archive(&source, download, &package_name, progress_tx)
+                .and_then(|bytes| self.store_archive(&archive_path, &bytes));
+            if result.is_ok() {
+                break;
+            }
+        }
 
-        let response = ureq::get(&url).call().inspect_err(|e| {
+        result.inspect_err(|e| {
             let _ = progress_tx.send(DownloadEvent::Failed {
                 package: package_name.clone(),
                 error: e.to_string(),
             });
         })?;
 
+        let _ = progress_tx.send(DownloadEvent::Completed {
+            package: package_name.clone(),
+        });
+
+        Ok(archive_path)
+    }
+
+    /// Checks a cached archive against the listing's sha256 and the locked sha256, if any
+    fn verify_cached(
+        &self,
+        archive_path: &Path,
+        download: &PackageDownload,
+        package_name: &str,
+    ) -> Result<(), DownloadError> {
+        let bytes = fs::read(archive_path)?;
+        let mut result = Ok(());
+        for source in self.sources(&download.hosted_url) {
+            result = self
+                .listed_version(&self.source_client(&source), download)
+                .and_then(|listed| {
+                    verify_sha256(package_name, &bytes, &listed.archive_sha256, download)
+                });
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn source_client(&self, source: &Url) -> PubClient {
+        PubClient::with_agent(source.clone(), self.client.http().clone())
+    }
+
+    /// The listing of the version to download, from one repository
+    fn listed_version(
+        &self,
+        client: &PubClient,
+        download: &PackageDownload,
+    ) -> Result<PubPackageVersion, DownloadError> {
+        let PackageDownload { name, version, .. } = download;
+        let not_found = || DownloadError::PackageNotFound {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let listing = client.listing(name).map_err(|e| match e {
+            PubClientError::PackageNotFound { .. } => not_found(),
+            PubClientError::AuthenticationFailed { hosted_url, status } => {
+                DownloadError::AuthenticationFailed { hosted_url, status }
+            }
+            e => e.into(),
+        })?;
+
+        listing
+            .versions
+            .into_iter()
+            .find(|v| &v.version == version)
+            .ok_or_else(not_found)
+    }
+
+    /// Fetches the archive for a package version from one repository, verified against the
+    /// listing's sha256 and the locked sha256, if any
+    fn fetch_archive(
+        &self,
+        hosted_url: &Url,
+        download: &PackageDownload,
+        package_name: &str,
+        progress_tx: &Sender<DownloadEvent>,
+    ) -> Result<Vec<u8>, DownloadError> {
+        let client = self.source_client(hosted_url);
+        let listed = self.listed_version(&client, download)?;
+
+        // Relative archive URLs are resolved against the listing they came from
+        let url = client
+            .listing_url(&download.name)?
+            .join(&listed.archive_url)?;
+
+        let response = client.http().get(&url).call().map_err(|e| match e {
+            ureq::Error::Status(status @ (401 | 403), _) => DownloadError::AuthenticationFailed {
+                hosted_url: hosted_url.clone(),
+                status,
+            },
+            e => client.http().error(&url, e).into(),
+        })?;
+
         let total_size = response
             .header("Content-Length")
             .and_then(|s| s.parse().ok())
@@ -100,7 +304,7 @@ impl PackageDownloader {
                     bytes.extend_from_slice(&buffer[..n]);
                     downloaded += n as u64;
                     let _ = progress_tx.send(DownloadEvent::Progress {
-                        package: package_name.clone(),
+                        package: package_name.to_string(),
                         total_size,
                         bytes: downloaded,
                     });
@@ -109,53 +313,43 @@ impl PackageDownloader {
             }
         }
 
-        // Rest of the download_package implementation...
+        verify_sha256(package_name, &bytes, &listed.archive_sha256, download)?;
+        Ok(bytes)
+    }
+
+    fn store_archive(&self, archive_path: &Path, bytes: &[u8]) -> Result<(), DownloadError> {
+        if let Some(dir) = archive_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
         let temp_path = archive_path.with_extension("tmp");
         let mut temp_file = File::create(&temp_path)?;
-        temp_file.write_all(&bytes)?;
+        temp_file.write_all(bytes)?;
         temp_file.flush()?;
 
-        self.verify_archive(&temp_path)?;
-        fs::rename(temp_path, &archive_path)?;
-
-        let _ = progress_tx.send(DownloadEvent::Completed {
-            package: package_name.clone(),
-        });
-
-        Ok(archive_path)
+        self.verify_archive(&temp_path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })?;
+        fs::rename(temp_path, archive_path)?;
+        Ok(())
     }
 
     pub fn download_packages_with_pool(
         &self,
-        packages: &[(PackageName, PackageVersion)],
+        packages: &[PackageDownload],
         pool: &ThreadPool,
         progress_tx: &Sender<DownloadEvent>,
     ) -> Vec<Result<PathBuf, DownloadError>> {
         let (tx, rx) = mpsc::channel();
         let total_packages = packages.len();
 
-        for (name, version) in packages {
+        for download in packages {
             let tx = tx.clone();
-            let name = name.clone();
-            let version = version.clone();
-            let cache_dir = self.cache_dir.clone();
+            let download = download.clone();
+            let downloader = self.clone();
             let progress_tx = progress_tx.clone();
 
             pool.execute(move || {
-                let downloader = match PackageDownloader::new(cache_dir) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        let error = DownloadError::IoError(e);
-                        let _ = progress_tx.send(DownloadEvent::Failed {
-                            package: name.to_string(),
-                            error: error.to_string(),
-                        });
-                        tx.send(Err(error)).unwrap();
-                        return;
-                    }
-                };
-
-                let result = downloader.download_package(&name, &version, &progress_tx);
+                let result = downloader.download_package_from(&download, &progress_tx);
                 tx.send(result).unwrap();
             });
         }
@@ -194,3 +388,23 @@ impl PackageDownloader {
         Ok(())
     }
 }
+
+/// Checks archive bytes against the listed sha256 and the one the download was locked to
+fn verify_sha256(
+    package_name: &str,
+    bytes: &[u8],
+    listed: &Option<Sha256>,
+    download: &PackageDownload,
+) -> Result<(), DownloadError> {
+    let actual = Sha256::new(hex::encode(sha2::Sha256::digest(bytes)));
+    for expected in listed.iter().chain(&download.sha256) {
+        if !actual.as_ref().eq_ignore_ascii_case(expected.as_ref()) {
+            return Err(DownloadError::HashMismatch {
+                package: package_name.to_string(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
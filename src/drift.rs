@@ -0,0 +1,182 @@
+use crate::project::Project;
+use crate::pubspecedit::Section;
+use crate::pubspeclock::{PackageName, PackageVersion};
+use crate::version::parse_version;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// A project locked at one version of a package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DriftProject {
+    pub directory: PathBuf,
+    /// Where its pubspec declares the package, or `None` when it is transitive
+    pub section: Option<&'static str>,
+    /// The version constraint it declares, for hosted dependencies
+    pub constraint: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DriftVersion {
+    pub version: PackageVersion,
+    pub projects: Vec<DriftProject>,
+}
+
+/// A package the projects have locked at more than one version, oldest first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DriftedPackage {
+    pub package: PackageName,
+    pub versions: Vec<DriftVersion>,
+}
+
+/// The packages whose locked versions differ across projects
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DriftReport {
+    pub packages: Vec<DriftedPackage>,
+}
+
+impl DriftReport {
+    /// Compares the pubspec.lock of every project, those without one being left out
+    pub fn for_projects(projects: &[Project]) -> DriftReport {
+        let mut locked: BTreeMap<PackageName, BTreeMap<PackageVersion, Vec<DriftProject>>> =
+            BTreeMap::new();
+        for project in projects {
+            let Some(lock) = &project.lock else {
+                continue;
+            };
+            for (name, spec) in &lock.packages {
+                // An override is what decided the version, so it is the one to show
+                let declared = Section::ALL.iter().rev().find_map(|section| {
+                    Some((section, section.of(&project.pubspec).get(name.as_ref())?))
+                });
+                let drift_project = DriftProject {
+                    directory: project.dir.clone(),
+                    section: declared.map(|(section, _)| section.key()),
+                    constraint: declared
+                        .and_then(|(_, spec)| spec.hosted_constraint())
+                        .map(String::from),
+                };
+                locked
+                    .entry(name.clone())
+                    .or_default()
+                    .entry(spec.version.clone())
+                    .or_default()
+                    .push(drift_project);
+            }
+        }
+
+        let packages = locked
+            .into_iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .map(|(package, versions)| {
+                let mut versions: Vec<_> = versions
+                    .into_iter()
+                    .map(|(version, projects)| DriftVersion { version, projects })
+                    .collect();
+                versions.sort_by_key(|v| parse_version(v.version.as_ref()).ok());
+                DriftedPackage { package, versions }
+            })
+            .collect();
+        DriftReport { packages }
+    }
+
+    /// The packages and projects, one line per version
+    pub fn to_table(&self) -> String {
+        let mut table = String::new();
+        for package in &self.packages {
+            table.push_str(&format!("{}\n", package.package));
+            let width = package
+                .versions
+                .iter()
+                .map(|v| v.version.as_ref().len())
+                .max()
+                .unwrap_or(0);
+            for version in &package.versions {
+                let projects: Vec<_> = version
+                    .projects
+                    .iter()
+                    .map(|project| {
+                        let declared = match (&project.constraint, project.section) {
+                            (Some(constraint), _) => constraint.as_str(),
+                            (None, Some(section)) => section,
+                            (None, None) => "transitive",
+                        };
+                        format!("{} ({})", project.directory.display(), declared)
+                    })
+                    .collect();
+                table.push_str(&format!(
+                    "  {:width$}  {}\n",
+                    version.version.as_ref(),
+                    projects.join(", "),
+                    width = width
+                ));
+            }
+        }
+        table
+    }
+}
+
+/// How much drift is acceptable, so CI can fail when there is more
+#[derive(Debug, Clone, Default)]
+pub struct DriftPolicy {
+    max_versions: Option<usize>,
+    max_drifted: Option<usize>,
+    ignored: BTreeSet<PackageName>,
+}
+
+impl DriftPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most versions any one package may be locked at
+    pub fn with_max_versions(mut self, max: usize) -> Self {
+        self.max_versions = Some(max);
+        self
+    }
+
+    /// The most packages that may be locked at more than one version
+    pub fn with_max_drifted(mut self, max: usize) -> Self {
+        self.max_drifted = Some(max);
+        self
+    }
+
+    /// Packages whose drift doesn't count
+    pub fn with_ignored(mut self, names: impl IntoIterator<Item = PackageName>) -> Self {
+        self.ignored.extend(names);
+        self
+    }
+
+    /// What in the report breaks the policy, empty when nothing does
+    pub fn violations(&self, report: &DriftReport) -> Vec<String> {
+        let drifted: Vec<_> = report
+            .packages
+            .iter()
+            .filter(|p| !self.ignored.contains(&p.package))
+            .collect();
+
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_versions {
+            for package in &drifted {
+                if package.versions.len() > max {
+                    violations.push(format!(
+                        "{} is locked at {} versions, more than {}",
+                        package.package,
+                        package.versions.len(),
+                        max
+                    ));
+                }
+            }
+        }
+        if let Some(max) = self.max_drifted
+            && drifted.len() > max
+        {
+            violations.push(format!(
+                "{} packages are locked at more than one version, more than {}",
+                drifted.len(),
+                max
+            ));
+        }
+        violations
+    }
+}
@@ -0,0 +1,278 @@
+use crate::pubcache::PubCache;
+use crate::pubspeclock::{GitPackage, PackageName};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fs, io};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum GitError {
+    #[error("Failed to run git, is it installed? {0}")]
+    SpawnError(#[source] io::Error),
+    #[error("`git {command}` failed: {stderr}")]
+    CommandFailed { command: String, stderr: String },
+    #[error("Could not find {reference} in {url}")]
+    UnknownRef { url: Url, reference: String },
+    #[error("IO error at {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Invalid path {path} in git repository {url}")]
+    InvalidPath { url: Url, path: String },
+    #[error("No pubspec.yaml at {path} in {url} at {commit}")]
+    NoPubspec {
+        url: Box<Url>,
+        commit: String,
+        path: String,
+    },
+}
+
+/// A git package checked out in the pub cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitCheckout {
+    /// The commit the package's ref resolved to
+    pub commit: String,
+    /// The package's directory, which is below the checkout when the package has a `path`
+    pub path: PathBuf,
+}
+
+/// Git repositories in the pub cache, laid out like dart's: bare mirrors in `git/cache`,
+/// and a checkout per package and commit in `git/<name>-<commit>`
+pub struct GitCache<'a> {
+    cache: &'a PubCache,
+}
+
+impl<'a> GitCache<'a> {
+    pub fn new(cache: &'a PubCache) -> Self {
+        GitCache { cache }
+    }
+
+    /// Checks out a git package at its locked `resolved-ref`, or if it has none, at the commit
+    /// its ref currently points to
+    pub fn install(
+        &self,
+        name: &PackageName,
+        package: &GitPackage,
+    ) -> Result<GitCheckout, GitError> {
+        match &package.resolved_ref {
+            Some(commit) => self.install_commit(name, package, commit),
+            None => self.upgrade(name, package),
+        }
+    }
+
+    /// Checks out a git package at the commit its ref currently points to, ignoring any lock
+    pub fn upgrade(
+        &self,
+        name: &PackageName,
+        package: &GitPackage,
+    ) -> Result<GitCheckout, GitError> {
+        let commit = self.resolve(&package.url, package.ref_.as_deref())?;
+        self.install_commit(name, package, &commit)
+    }
+
+    /// Checks out a git package at exactly `commit`
+    pub fn install_commit(
+        &self,
+        name: &PackageName,
+        package: &GitPackage,
+        commit: &str,
+    ) -> Result<GitCheckout, GitError> {
+        let checkout = self.checkout(name, &package.url, commit)?;
+
+        let subdir = package.path.as_deref().unwrap_or(".");
+        if Path::new(subdir).is_absolute() || subdir.split(['/', '\\']).any(|s| s == "..") {
+            return Err(GitError::InvalidPath {
+                url: package.url.clone(),
+                path: subdir.to_string(),
+            });
+        }
+
+        let path = checkout.join(subdir);
+        if !path.join("pubspec.yaml").is_file() {
+            return Err(GitError::NoPubspec {
+                url: Box::new(package.url.clone()),
+                commit: commit.to_string(),
+                path: subdir.to_string(),
+            });
+        }
+
+        Ok(GitCheckout {
+            commit: commit.to_string(),
+            path: clean(path),
+        })
+    }
+
+    /// The commit that `reference` (by default HEAD) names in the repository at `url`,
+    /// fetching first unless it is a commit the mirror already has
+    pub fn resolve(&self, url: &Url, reference: Option<&str>) -> Result<String, GitError> {
+        let reference = reference.unwrap_or("HEAD");
+        let mirror = self.cache.git_mirror_path(url);
+
+        if mirror.exists() {
+            if is_commit_hash(reference)
+                && let Ok(commit) = rev_parse(&mirror, reference)
+            {
+                return Ok(commit);
+            }
+            git(Some(&mirror), ["fetch", "--prune", "origin"])?;
+        } else {
+            self.clone_mirror(url, &mirror)?;
+        }
+
+        rev_parse(&mirror, reference).map_err(|_| GitError::UnknownRef {
+            url: url.clone(),
+            reference: reference.to_string(),
+        })
+    }
+
+    /// The working tree of `url` at `commit`, creating it from the mirror if needed
+    pub fn checkout(
+        &self,
+        name: &PackageName,
+        url: &Url,
+        commit: &str,
+    ) -> Result<PathBuf, GitError> {
+        let path = self.cache.git_checkout_path(name, commit);
+        if path.exists() {
+            return Ok(path);
+        }
+
+        let mirror = self.cache.git_mirror_path(url);
+        if !mirror.exists() || rev_parse(&mirror, commit).is_err() {
+            self.resolve(url, Some(commit))?;
+        }
+
+        // Check out next to the destination, and move it into place once complete
+        let parent = path.parent().expect("checkouts are below the cache root");
+        fs::create_dir_all(parent).map_err(|e| GitError::IoError {
+            path: parent.to_path_buf(),
+            source: e,
+        })?;
+        let temp = tempfile::Builder::new()
+            .prefix(".checkout-")
+            .tempdir_in(parent)
+            .map_err(|e| GitError::IoError {
+                path: parent.to_path_buf(),
+                source: e,
+            })?;
+        let work_tree = temp.path().join("repo");
+
+        git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--no-checkout"),
+                mirror.as_os_str(),
+                work_tree.as_os_str(),
+            ],
+        )?;
+        git(
+            Some(&work_tree),
+            ["checkout", "--quiet", "--detach", commit],
+        )?;
+
+        match fs::rename(&work_tree, &path) {
+            Ok(()) => Ok(path),
+            // Someone else checked out the same commit in the meantime
+            Err(_) if path.exists() => Ok(path),
+            Err(e) => Err(GitError::IoError { path, source: e }),
+        }
+    }
+
+    fn clone_mirror(&self, url: &Url, mirror: &Path) -> Result<(), GitError> {
+        let parent = mirror.parent().expect("mirrors are below the cache root");
+        fs::create_dir_all(parent).map_err(|e| GitError::IoError {
+            path: parent.to_path_buf(),
+            source: e,
+        })?;
+
+        let temp = tempfile::Builder::new()
+            .prefix(".mirror-")
+            .tempdir_in(parent)
+            .map_err(|e| GitError::IoError {
+                path: parent.to_path_buf(),
+                source: e,
+            })?;
+        let bare = temp.path().join("repo.git");
+
+        git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--mirror"),
+                OsStr::new(url.as_str()),
+                bare.as_os_str(),
+            ],
+        )?;
+
+        match fs::rename(&bare, mirror) {
+            Ok(()) => Ok(()),
+            Err(_) if mirror.exists() => Ok(()),
+            Err(e) => Err(GitError::IoError {
+                path: mirror.to_path_buf(),
+                source: e,
+            }),
+        }
+    }
+}
+
+fn is_commit_hash(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn rev_parse(repo: &Path, reference: &str) -> Result<String, GitError> {
+    git(
+        Some(repo),
+        [
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", reference),
+        ],
+    )
+    .map(|out| out.trim().to_string())
+}
+
+/// Runs git, in `dir` if given, returning its stdout
+fn git<I, S>(dir: Option<&Path>, args: I) -> Result<String, GitError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<_> = args.into_iter().map(|a| a.as_ref().to_owned()).collect();
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .args(&args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(GitError::SpawnError)?;
+
+    if !output.status.success() {
+        return Err(GitError::CommandFailed {
+            command: args
+                .iter()
+                .map(|a| a.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Drops `.` components, so a package at the root of its repository has the checkout's path
+fn clean(path: PathBuf) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
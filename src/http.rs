@@ -0,0 +1,292 @@
+use crate::pubclient::agent_builder;
+use crate::tokens::TokenStore;
+use rustls_pki_types::CertificateDer;
+use rustls_pki_types::pem::PemObject;
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, io};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("Request via proxy {proxy} failed: {source}")]
+    Proxy {
+        proxy: String,
+        #[source]
+        source: Box<ureq::Error>,
+    },
+    #[error(
+        "The TLS certificate of {host} is not trusted: {source}. \
+         If a proxy intercepts TLS, add its root CA with SSL_CERT_FILE or `ca_bundle` in the config"
+    )]
+    UntrustedCertificate {
+        host: String,
+        #[source]
+        source: Box<ureq::Error>,
+    },
+    #[error("HTTP error: {0}")]
+    Other(Box<ureq::Error>),
+}
+
+impl From<ureq::Error> for HttpError {
+    fn from(error: ureq::Error) -> Self {
+        HttpError::Other(Box::new(error))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HttpSetupError {
+    #[error("Invalid proxy {proxy}: {source}")]
+    InvalidProxy {
+        proxy: String,
+        #[source]
+        source: Box<ureq::Error>,
+    },
+    #[error("Failed to read CA bundle {path}: {source}")]
+    CaBundle {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("CA bundle {path} contains no usable certificates")]
+    EmptyCaBundle { path: PathBuf },
+}
+
+/// Network settings, taken from the standard environment variables and the config file
+#[derive(Debug, Clone, Default)]
+pub struct HttpSettings {
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    pub no_proxy: Vec<String>,
+    /// PEM certificates trusted in addition to the built in roots
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl HttpSettings {
+    /// Reads `HTTP_PROXY`, `HTTPS_PROXY`, `NO_PROXY` and `SSL_CERT_FILE`, in either case.
+    /// `SSL_CERT_FILE` takes precedence over a `ca_bundle` from the config.
+    pub fn from_env(ca_bundle: Option<PathBuf>) -> Self {
+        HttpSettings {
+            http_proxy: env_var("HTTP_PROXY"),
+            https_proxy: env_var("HTTPS_PROXY"),
+            no_proxy: env_var("NO_PROXY")
+                .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default(),
+            ca_bundle: env_var("SSL_CERT_FILE").map(PathBuf::from).or(ca_bundle),
+        }
+    }
+
+    /// Whether `NO_PROXY` says to connect to `url` directly
+    pub fn bypasses_proxy(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return true;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default();
+
+        self.no_proxy.iter().any(|entry| {
+            let (pattern, entry_port) = split_port(entry);
+            let pattern = pattern.trim_start_matches("*.").trim_start_matches('.');
+            let port_matches = entry_port.is_none() || entry_port == port;
+
+            let host_matches = host.eq_ignore_ascii_case(pattern)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", pattern.to_ascii_lowercase()));
+
+            entry == "*" || (port_matches && !pattern.is_empty() && host_matches)
+        })
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn split_port(entry: &str) -> (&str, Option<u16>) {
+    match entry.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') => match port.parse() {
+            Ok(port) => (
+                host.trim_start_matches('[').trim_end_matches(']'),
+                Some(port),
+            ),
+            Err(_) => (entry, None),
+        },
+        _ => (entry, None),
+    }
+}
+
+/// Agents for talking to pub repositories, choosing a proxy (or none) for each URL
+#[derive(Clone)]
+pub struct HttpClient {
+    direct: ureq::Agent,
+    http: Option<(String, ureq::Agent)>,
+    https: Option<(String, ureq::Agent)>,
+    settings: HttpSettings,
+}
+
+impl HttpClient {
+    pub fn new(settings: HttpSettings, tokens: Option<TokenStore>) -> Result<Self, HttpSetupError> {
+        let tls_config = match &settings.ca_bundle {
+            Some(path) => Some(tls_config_with(path)?),
+            None => None,
+        };
+
+        let agent = |proxy: Option<&str>| -> Result<ureq::Agent, HttpSetupError> {
+            let mut builder = agent_builder();
+            if let Some(proxy) = proxy {
+                let parsed = ureq::Proxy::new(proxy).map_err(|e| HttpSetupError::InvalidProxy {
+                    proxy: proxy.to_string(),
+                    source: Box::new(e),
+                })?;
+                builder = builder.proxy(parsed);
+            }
+            if let Some(tls_config) = &tls_config {
+                builder = builder.tls_config(tls_config.clone());
+            }
+            if let Some(tokens) = &tokens {
+                builder = builder.middleware(tokens.clone());
+            }
+            Ok(builder.build())
+        };
+
+        let proxied = |proxy: &Option<String>| {
+            proxy
+                .as_ref()
+                .map(|p| agent(Some(p)).map(|a| (p.clone(), a)))
+                .transpose()
+        };
+
+        Ok(HttpClient {
+            direct: agent(None)?,
+            http: proxied(&settings.http_proxy)?,
+            https: proxied(&settings.https_proxy)?,
+            settings,
+        })
+    }
+
+    /// The proxy requests to `url` go through, if any
+    pub fn proxy_for(&self, url: &Url) -> Option<&str> {
+        if self.settings.bypasses_proxy(url) {
+            return None;
+        }
+        match url.scheme() {
+            "https" => self.https.as_ref().map(|(proxy, _)| proxy.as_str()),
+            "http" => self.http.as_ref().map(|(proxy, _)| proxy.as_str()),
+            _ => None,
+        }
+    }
+
+    fn agent_for(&self, url: &Url) -> &ureq::Agent {
+        let proxied = match url.scheme() {
+            "https" => &self.https,
+            _ => &self.http,
+        };
+        match proxied {
+            Some((_, agent)) if !self.settings.bypasses_proxy(url) => agent,
+            _ => &self.direct,
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> ureq::Request {
+        self.agent_for(url).request_url("GET", url)
+    }
+
+    /// Explains a transport failure for `url`, telling proxy and TLS trust problems apart
+    pub fn error(&self, url: &Url, error: ureq::Error) -> HttpError {
+        let ureq::Error::Transport(transport) = &error else {
+            return error.into();
+        };
+
+        if is_untrusted_certificate(&error) {
+            return HttpError::UntrustedCertificate {
+                host: url.host_str().unwrap_or_default().to_string(),
+                source: Box::new(error),
+            };
+        }
+
+        // Behind a proxy, every connection is to the proxy, so failing to connect is its fault
+        match (self.proxy_for(url), transport.kind()) {
+            (
+                Some(proxy),
+                ureq::ErrorKind::ProxyConnect
+                | ureq::ErrorKind::ProxyUnauthorized
+                | ureq::ErrorKind::InvalidProxyUrl
+                | ureq::ErrorKind::ConnectionFailed
+                | ureq::ErrorKind::Dns,
+            ) => HttpError::Proxy {
+                proxy: proxy.to_string(),
+                source: Box::new(error),
+            },
+            _ => error.into(),
+        }
+    }
+}
+
+impl From<ureq::Agent> for HttpClient {
+    fn from(agent: ureq::Agent) -> Self {
+        HttpClient {
+            direct: agent,
+            http: None,
+            https: None,
+            settings: HttpSettings::default(),
+        }
+    }
+}
+
+/// The built in roots, plus the certificates in the PEM bundle at `path`
+fn tls_config_with(path: &Path) -> Result<Arc<rustls::ClientConfig>, HttpSetupError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| HttpSetupError::CaBundle {
+            path: path.to_path_buf(),
+            source: io::Error::new(io::ErrorKind::InvalidData, e),
+        })?;
+
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let (added, _) = roots.add_parsable_certificates(certificates);
+    if added == 0 {
+        return Err(HttpSetupError::EmptyCaBundle {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(
+        rustls::crypto::ring::default_provider().into(),
+    )
+    .with_protocol_versions(&[&rustls::version::TLS12, &rustls::version::TLS13])
+    .expect("ring supports TLS 1.2 and 1.3")
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+/// Whether a TLS handshake failed because the server's certificate chain isn't trusted
+fn is_untrusted_certificate(error: &ureq::Error) -> bool {
+    let mut source: Option<&(dyn StdError + 'static)> = Some(error);
+    while let Some(err) = source {
+        // io::Error hides the error it wraps from source(), so look inside explicitly
+        let inner = err
+            .downcast_ref::<io::Error>()
+            .and_then(|e| e.get_ref())
+            .map(|e| e as &(dyn StdError + 'static));
+
+        for candidate in [Some(err), inner].into_iter().flatten() {
+            if let Some(rustls::Error::InvalidCertificate(_)) =
+                candidate.downcast_ref::<rustls::Error>()
+            {
+                return true;
+            }
+        }
+        source = inner.or_else(|| err.source());
+    }
+    false
+}
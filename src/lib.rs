@@ -1,10 +1,25 @@
+pub mod config;
+pub mod deps;
 pub mod downloader;
+pub mod drift;
 pub mod extensions;
+pub mod git;
+pub mod http;
+pub mod link;
+pub mod outdated;
 pub mod packageconfig;
+pub mod project;
 pub mod pubcache;
+pub mod pubclient;
 pub mod pubpackage;
 pub mod pubspec;
+pub mod pubspecedit;
 pub mod pubspeclock;
+pub mod resolver;
 pub mod scanner;
 pub mod scopeyscope;
+pub mod sdk;
+pub mod server;
+pub mod tokens;
 pub mod types;
+pub mod version;
@@ -0,0 +1,157 @@
+use crate::project::{Project, relative_path};
+use crate::pubspec::{DependencySpec, PathDependency, PubspecOverrides};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("IO error at {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{0} wasn't written by flutter-pub link, so is left alone")]
+    NotGenerated(PathBuf),
+}
+
+/// The first line of every pubspec_overrides.yaml `link` writes, which is how `unlink` knows
+/// which ones to remove
+const GENERATED_HEADER: &str = "# Generated by flutter-pub link, remove with flutter-pub unlink\n";
+
+/// The pubspec_overrides.yaml that points one project at the local copies of packages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub path: PathBuf,
+    /// The local packages, by name, and their paths relative to the project
+    pub linked: BTreeMap<String, String>,
+    pub overrides: PubspecOverrides,
+}
+
+impl Link {
+    /// The links each project needs to use the other projects rather than their hosted
+    /// versions. Packages it only gets through other local packages are linked too, as only
+    /// its own overrides count. When several projects have the same name, the first is used.
+    pub fn plan(projects: &[Project]) -> Vec<Link> {
+        let mut local: BTreeMap<&str, &Project> = BTreeMap::new();
+        for project in projects.iter().flat_map(|p| p.workspace_packages()) {
+            local
+                .entry(project.pubspec.name.as_str())
+                .or_insert(project);
+        }
+
+        let mut links = Vec::new();
+        for project in projects {
+            let own: BTreeSet<_> = project
+                .workspace_packages()
+                .iter()
+                .map(|p| p.pubspec.name.as_str())
+                .collect();
+            let mut pending: Vec<(&str, &DependencySpec)> = project
+                .workspace_packages()
+                .iter()
+                .flat_map(|p| {
+                    p.pubspec
+                        .dependencies
+                        .iter()
+                        .chain(&p.pubspec.dev_dependencies)
+                })
+                .map(|(name, spec)| (name.as_str(), spec))
+                .collect();
+
+            let mut visited = BTreeSet::new();
+            let mut linked = BTreeMap::new();
+            while let Some((name, spec)) = pending.pop() {
+                let Some(package) = local.get(name) else {
+                    continue;
+                };
+                if own.contains(name) || !visited.insert(name) {
+                    continue;
+                }
+                match spec {
+                    DependencySpec::Simple(_) | DependencySpec::Detailed(_) => {
+                        linked.insert(name.to_string(), relative_dir(&package.dir, &project.dir));
+                    }
+                    // Already local, but what it depends on might not be
+                    DependencySpec::Path(_) => {}
+                    _ => continue,
+                }
+                pending.extend(
+                    package
+                        .pubspec
+                        .dependencies
+                        .iter()
+                        .map(|(name, spec)| (name.as_str(), spec)),
+                );
+            }
+            if linked.is_empty() {
+                continue;
+            }
+
+            // The file replaces the overrides in pubspec.yaml, so they have to be kept
+            let mut overrides = PubspecOverrides::default();
+            for (name, spec) in &project.pubspec.dependency_overrides {
+                if !linked.contains_key(name) {
+                    overrides
+                        .dependency_overrides
+                        .insert(name.clone(), spec.clone());
+                }
+            }
+            for (name, path) in &linked {
+                let spec = DependencySpec::Path(PathDependency {
+                    path: path.clone(),
+                    version: None,
+                });
+                overrides.dependency_overrides.insert(name.clone(), spec);
+            }
+            links.push(Link {
+                path: project.overrides_path(),
+                linked,
+                overrides,
+            });
+        }
+        links
+    }
+
+    pub fn contents(&self) -> String {
+        format!("{}{}", GENERATED_HEADER, self.overrides.to_yaml())
+    }
+
+    /// Writes the file, unless there is already one that `link` didn't write
+    pub fn write(&self) -> Result<(), LinkError> {
+        if self.path.exists() && !is_generated(&self.path)? {
+            return Err(LinkError::NotGenerated(self.path.clone()));
+        }
+        fs::write(&self.path, self.contents()).map_err(|e| LinkError::IoError {
+            path: self.path.clone(),
+            source: e,
+        })
+    }
+}
+
+/// Removes the pubspec_overrides.yaml `link` wrote for a project, if there is one
+pub fn unlink(project: &Project) -> Result<bool, LinkError> {
+    let path = project.overrides_path();
+    if !path.exists() || !is_generated(&path)? {
+        return Ok(false);
+    }
+    fs::remove_file(&path).map_err(|e| LinkError::IoError { path, source: e })?;
+    Ok(true)
+}
+
+fn is_generated(path: &Path) -> Result<bool, LinkError> {
+    let contents = fs::read_to_string(path).map_err(|e| LinkError::IoError {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    Ok(contents.starts_with(GENERATED_HEADER))
+}
+
+/// `dir` relative to `base`, however either was reached
+fn relative_dir(dir: &Path, base: &Path) -> String {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    relative_path(&canonical(dir), &canonical(base))
+}
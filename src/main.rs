@@ -1,40 +1,557 @@
-use clap::Parser;
-use flutter_pub::downloader::{DownloadEvent, PackageDownloader};
+use clap::{Parser, Subcommand, ValueEnum};
+use flutter_pub::config::{self, Config};
+use flutter_pub::deps::{DependencyEdge, DependencyGraph};
+use flutter_pub::downloader::{DownloadEvent, PackageDownload, PackageDownloader};
+use flutter_pub::drift::{DriftPolicy, DriftReport};
 use flutter_pub::extensions::FilterNotIterator;
+use flutter_pub::git::GitCache;
+use flutter_pub::http::{HttpClient, HttpSettings};
+use flutter_pub::link::{self, Link};
+use flutter_pub::outdated::{MonorepoPackage, OutdatedReport};
+use flutter_pub::project::{Project, ProjectError, lock_file_of};
 use flutter_pub::pubcache::PubCache;
-use flutter_pub::pubspeclock::{HostedPackage, PackageDescription, PackageName, PackageVersion};
-use flutter_pub::scanner::{PubspecInfo, Scanner, ScannerError};
+use flutter_pub::pubclient::PubClient;
+use flutter_pub::pubspec::DependencySpec;
+use flutter_pub::pubspecedit::{DependencyRequest, PubspecEditor, Section};
+use flutter_pub::pubspeclock::{HostedPackage, PackageName, PackageVersion, PubspecLock};
+use flutter_pub::resolver::{
+    DependencyKind, PackageSources, Preference, Resolution, ResolvedSource, Resolver, RootPackage,
+};
+use flutter_pub::scanner::Scanner;
+use flutter_pub::sdk::SdkPaths;
+use flutter_pub::server::{CacheRepository, Server};
+use flutter_pub::tokens::{Credential, TokenStore};
+use flutter_pub::version::{VersionConstraint, parse_version};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::{BTreeMap, HashMap};
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Receiver;
+use std::sync::{Arc, mpsc};
 use std::thread;
 use threadpool::ThreadPool;
+use url::Url;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
     #[arg(short, long = "dir", required = true, num_args = 1.., value_name = "DIRECTORY")]
     dirs: Vec<PathBuf>,
+    /// Resolve all the packages together, so that they agree on the versions they share
+    #[arg(long)]
+    shared: bool,
+    /// Write one lockfile here for all of them, rather than a pubspec.lock for each
+    #[arg(long, value_name = "FILE", requires = "shared")]
+    shared_lock: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Add dependencies to pubspec.yaml, e.g. `foo`, `dev:foo:^1.2.0` or `'foo:{git: <url>}'`
+    Add {
+        #[arg(required = true, value_name = "PACKAGE")]
+        packages: Vec<String>,
+        /// The package whose pubspec.yaml is edited
+        #[arg(short = 'C', long, default_value = ".")]
+        directory: PathBuf,
+    },
+    /// Upgrade dependencies to their newest versions, all of them unless packages are named
+    Upgrade {
+        #[arg(value_name = "PACKAGE")]
+        packages: Vec<String>,
+        /// Directories to scan for packages to upgrade
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+        /// Also unlock the dependencies of the named packages
+        #[arg(long)]
+        unlock_transitive: bool,
+        /// Change constraints in pubspec.yaml to allow the newest major versions
+        #[arg(long)]
+        major_versions: bool,
+        /// Raise the lower bounds of constraints in pubspec.yaml to the versions resolved
+        #[arg(long)]
+        tighten: bool,
+    },
+    /// Resolve packages to the lowest versions their constraints allow, to check lower bounds
+    Downgrade {
+        /// Directories to scan for packages to downgrade
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+        /// Write pubspec.lock and package_config.json rather than separate downgrade files
+        #[arg(long)]
+        in_place: bool,
+    },
+    /// Show which dependencies have newer versions, and which should be replaced
+    Outdated {
+        /// Directories to scan for packages to check
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+        /// Write JSON like `dart pub outdated --json`, or `{"projects": [...]}` of it for
+        /// more than one package
+        #[arg(long)]
+        json: bool,
+        /// Show each package once, with the versions in use across all the projects
+        #[arg(long)]
+        monorepo: bool,
+        /// Include packages that are up to date
+        #[arg(long)]
+        show_all: bool,
+    },
+    /// Show the resolved dependency graph, from pubspec.lock and the pub cache
+    Deps {
+        /// The package whose dependencies are shown
+        #[arg(short = 'C', long, default_value = ".")]
+        directory: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = DepsStyle::Tree)]
+        style: DepsStyle,
+        /// Write JSON like `dart pub deps --json`
+        #[arg(long)]
+        json: bool,
+        /// Leave out dev dependencies
+        #[arg(long)]
+        no_dev: bool,
+        /// List the executables of the package and its direct dependencies instead
+        #[arg(long)]
+        executables: bool,
+    },
+    /// Show every path by which the scanned projects depend on a package
+    Why {
+        package: String,
+        /// Directories to scan for packages that might depend on it
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+    },
+    /// Show the packages that the scanned projects have locked at different versions
+    Drift {
+        /// Directories to scan for packages to compare
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+        #[arg(long)]
+        json: bool,
+        /// Fail when any package is locked at more than this many versions
+        #[arg(long, value_name = "COUNT")]
+        max_versions: Option<usize>,
+        /// Fail when more than this many packages are locked at more than one version
+        #[arg(long, value_name = "COUNT")]
+        max_drifted: Option<usize>,
+        /// Packages whose drift doesn't count towards the limits
+        #[arg(long, value_name = "PACKAGE")]
+        ignore: Vec<String>,
+    },
+    /// Set the same constraint on a package in every scanned pubspec.yaml that depends on it
+    Align {
+        package: String,
+        /// The constraint to use, by default `^` the newest version any project has locked
+        #[arg(long, value_name = "CONSTRAINT")]
+        to: Option<String>,
+        /// Directories to scan for packages to align
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+    },
+    /// Point projects that depend on others in the scanned directories at the local copies,
+    /// with a generated pubspec_overrides.yaml, then resolve them
+    Link {
+        /// Directories to scan for packages to link
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+    },
+    /// Remove the pubspec_overrides.yaml files that link generated
+    Unlink {
+        /// Directories to scan for linked packages
+        #[arg(short, long = "dir", num_args = 1.., value_name = "DIRECTORY", default_value = ".")]
+        dirs: Vec<PathBuf>,
+    },
+    /// Remove dependencies from pubspec.yaml
+    Remove {
+        #[arg(required = true, value_name = "PACKAGE")]
+        packages: Vec<String>,
+        /// The package whose pubspec.yaml is edited
+        #[arg(short = 'C', long, default_value = ".")]
+        directory: PathBuf,
+    },
+    /// Manage authentication tokens for hosted pub repositories
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+    /// Serve the packages in the pub cache as a hosted pub repository
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0")]
+        address: IpAddr,
+        /// Host whose cached packages are served, by default that of the upstream, or pub.dev
+        #[arg(long)]
+        host: Option<String>,
+        /// Act as a caching proxy, fetching what isn't cached from this hosted repository
+        #[arg(long, value_name = "HOSTED_URL")]
+        upstream: Option<Url>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DepsStyle {
+    Tree,
+    List,
+    Compact,
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Add a token for a hosted repository, read from stdin unless --env-var is given
+    Add {
+        hosted_url: Url,
+        /// Read the token from this environment variable when it is needed
+        #[arg(long, value_name = "VARIABLE")]
+        env_var: Option<String>,
+    },
+    /// List the hosted repositories that have tokens
+    List,
+    /// Remove the token for a hosted repository
+    Remove {
+        #[arg(required_unless_present = "all")]
+        hosted_url: Option<Url>,
+        /// Remove all tokens
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// Where `downgrade` writes its lockfile, next to pubspec.lock
+const DOWNGRADE_LOCK: &str = "pubspec_downgrade.lock";
+/// Where `downgrade` writes its package config, next to package_config.json
+const DOWNGRADE_PACKAGE_CONFIG: &str = "package_config_downgrade.json";
+
+/// What `upgrade` should upgrade, and how
+struct UpgradeOptions {
+    /// The packages to unlock, or all of them when empty
+    packages: Vec<PackageName>,
+    unlock_transitive: bool,
+    major_versions: bool,
+    tighten: bool,
 }
 
 struct HostedDependency {
     name: PackageName,
     version: PackageVersion,
     hosted: HostedPackage,
+    direct: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let user_home = dirs::home_dir().expect("Could not find home directory");
+    match cli.command {
+        None if cli.shared => get_shared(cli.dirs, cli.shared_lock),
+        None => get(cli.dirs),
+        Some(Command::Add {
+            packages,
+            directory,
+        }) => add(directory, packages),
+        Some(Command::Remove {
+            packages,
+            directory,
+        }) => remove(directory, packages),
+        Some(Command::Downgrade { dirs, in_place }) => downgrade(dirs, in_place),
+        Some(Command::Outdated {
+            dirs,
+            json,
+            monorepo,
+            show_all,
+        }) => outdated(dirs, json, monorepo, show_all),
+        Some(Command::Deps {
+            directory,
+            style,
+            json,
+            no_dev,
+            executables,
+        }) => deps(directory, style, json, no_dev, executables),
+        Some(Command::Drift {
+            dirs,
+            json,
+            max_versions,
+            max_drifted,
+            ignore,
+        }) => {
+            let mut policy =
+                DriftPolicy::new().with_ignored(ignore.into_iter().map(PackageName::new));
+            if let Some(max) = max_versions {
+                policy = policy.with_max_versions(max);
+            }
+            if let Some(max) = max_drifted {
+                policy = policy.with_max_drifted(max);
+            }
+            drift(dirs, json, policy)
+        }
+        Some(Command::Align { package, to, dirs }) => align(dirs, PackageName::new(package), to),
+        Some(Command::Why { package, dirs }) => why(dirs, PackageName::new(package)),
+        Some(Command::Link { dirs }) => link(dirs),
+        Some(Command::Unlink { dirs }) => unlink(dirs),
+        Some(Command::Upgrade {
+            packages,
+            dirs,
+            unlock_transitive,
+            major_versions,
+            tighten,
+        }) => upgrade(
+            dirs,
+            UpgradeOptions {
+                packages: packages.into_iter().map(PackageName::new).collect(),
+                unlock_transitive,
+                major_versions,
+                tighten,
+            },
+        ),
+        Some(Command::Token { command }) => token(command),
+        Some(Command::Serve {
+            port,
+            address,
+            host,
+            upstream,
+        }) => serve(address, port, host, upstream),
+    }
+}
+
+fn pub_cache_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("Could not find home directory")
+        .join(".pub-cache-2")
+}
+
+/// The configuration, HTTP client and pub cache shared by the commands that resolve packages
+struct Context {
+    config: Config,
+    pub_dev_sources: Vec<Url>,
+    http: HttpClient,
+    pub_cache: PubCache,
+}
+
+impl Context {
+    fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let config = Config::load_default()?;
+        let pub_dev_sources = config.pub_dev_sources(config::pub_hosted_url()?);
+        let http = HttpClient::new(
+            HttpSettings::from_env(config.ca_bundle.clone()),
+            Some(TokenStore::load_default()?),
+        )?;
+        let pub_cache = PubCache::new(pub_cache_path())?;
+
+        Ok(Context {
+            config,
+            pub_dev_sources,
+            http,
+            pub_cache,
+        })
+    }
+
+    fn sources(&self) -> PackageSources<'_> {
+        PackageSources::new(self.http.clone(), config::pub_dev_url())
+            .with_default_source(self.pub_dev_sources[0].clone())
+            .with_git(GitCache::new(&self.pub_cache))
+            .with_sdks(SdkPaths::from_env(self.config.flutter_root.clone()))
+    }
+
+    /// Downloads what the resolutions need, then writes their lockfiles and package configs,
+    /// writing nothing if any download fails
+    fn install(
+        &self,
+        resolved: &[(Project, Resolution)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.install_edited(resolved, &[])
+    }
+
+    /// Like `install`, also writing the edited pubspec.yaml files once the downloads are done
+    fn install_edited(
+        &self,
+        resolved: &[(Project, Resolution)],
+        edited: &[(PathBuf, PubspecEditor)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.download(resolved)?;
+
+        for (path, editor) in edited {
+            editor.write_to_file(path)?;
+        }
+        for (project, resolution) in resolved {
+            project.write(resolution, &self.pub_cache)?;
+            println!(
+                "Resolved {} packages for {}",
+                resolution.packages.len(),
+                project.dir.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the hosted packages of the resolutions that aren't cached yet
+    fn download(
+        &self,
+        resolved: &[(Project, Resolution)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let pub_cache = &self.pub_cache;
+        let downloader = PackageDownloader::new(pub_cache.download_path())?
+            .with_client(PubClient::with_agent(
+                config::pub_dev_url(),
+                self.http.clone(),
+            ))
+            .with_mirrors(self.pub_dev_sources.clone());
+
+        let hosted_packages = hosted_packages_from(resolved);
+        warn_discontinued(&self.http, &self.pub_dev_sources[0], &hosted_packages);
+
+        let missing_packages = packages_missing_in_cache(pub_cache, &hosted_packages);
+
+        let mut failed = false;
+        if missing_packages.is_empty() {
+            println!("All packages are cached");
+        } else {
+            let threadpool = ThreadPool::new(8);
+
+            println!("Downloading {} packages...", missing_packages.len());
+
+            let things = missing_packages
+                .iter()
+                .map(|hp| PackageDownload::hosted(&hp.version, &hp.hosted))
+                .collect::<Vec<_>>();
 
-    let pub_cache = PubCache::new(&user_home.join(".pub-cache-2"))?;
-    let downloader = PackageDownloader::new(pub_cache.download_path())?;
+            let (tx, rx) = mpsc::channel();
+
+            let count = things.len() as u64;
+
+            let progress = thread::spawn(move || {
+                display_progress_ind(count, rx);
+            });
+
+            let results = downloader.download_packages_with_pool(&things, &threadpool, &tx);
+            let _ = progress.join();
+
+            for (package, result) in missing_packages.iter().zip(results) {
+                if let Err(e) = result
+                    .map_err(|e| e.to_string())
+                    .and_then(|archive| extract_package(pub_cache, &downloader, package, &archive))
+                {
+                    eprintln!(
+                        "Error installing {} {}: {}",
+                        package.name, package.version, e
+                    );
+                    failed = true;
+                }
+            }
+        }
+
+        if failed {
+            return Err("Some packages could not be installed".into());
+        }
+
+        Ok(())
+    }
+}
+
+fn get(dirs: Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+
+    let projects = scan_projects(dirs)?;
+    let sources = context.sources();
+
+    let mut failed = false;
+    let mut resolved = Vec::new();
+    for project in projects {
+        match resolve(&sources, &project) {
+            Ok(resolution) => resolved.push((project, resolution)),
+            Err(e) => {
+                eprintln!("Error resolving {}: {}", project.dir.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    context.install(&resolved)?;
+
+    if failed {
+        return Err("Some projects could not be resolved".into());
+    }
+
+    Ok(())
+}
+
+/// Resolves every project together, then writes a lockfile for each, or one for all of them
+fn get_shared(
+    dirs: Vec<PathBuf>,
+    shared_lock: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+    let sources = context.sources();
+
+    let roots = projects
+        .iter()
+        .map(|project| project.root_package(sources.default_url()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut resolver = Resolver::new(&sources);
+    for lock in projects.iter().filter_map(|p| p.lock.as_ref()) {
+        resolver = resolver.with_lock(lock);
+    }
+    let shared_lock_file = match &shared_lock {
+        Some(path) if path.exists() => Some(PubspecLock::from_file(path)?),
+        _ => None,
+    };
+    if let Some(lock) = &shared_lock_file {
+        resolver = resolver.with_lock(lock);
+    }
+    let shared = resolver
+        .resolve_together(&roots)
+        .map_err(|e| format!("The packages can't agree on versions: {}", e))?;
+
+    let resolved: Vec<_> = projects
+        .into_iter()
+        .zip(shared.resolutions.clone())
+        .collect();
+    let Some(path) = shared_lock else {
+        return context.install(&resolved);
+    };
+
+    context.download(&resolved)?;
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+    let root = RootPackage {
+        name: PackageName::new(dir.file_name().unwrap_or_default().to_string_lossy()),
+        dir,
+        version: semver::Version::new(0, 0, 0),
+        dependencies: Vec::new(),
+        dev_dependencies: Vec::new(),
+        overrides: Vec::new(),
+        language_version: None,
+        sdk_constraints: Default::default(),
+    };
+    let combined = shared.combined(root);
+    lock_file_of(&combined).write_to_file(&path)?;
+    println!(
+        "Resolved {} packages into {}",
+        combined.packages.len(),
+        path.display()
+    );
+    for (project, resolution) in &resolved {
+        project.write_package_config(
+            resolution,
+            &context.pub_cache,
+            project.package_config_path(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The packages found in `dirs`, failing if any of their pubspecs can't be read
+fn scan_projects(dirs: Vec<PathBuf>) -> Result<Vec<Project>, Box<dyn std::error::Error>> {
+    let pub_specs = Scanner::new(dirs).scan();
 
-    let pub_specs = Scanner::new(cli.dirs).scan();
-    
     let had_errors = pub_specs
         .iter()
         .filter_map(|r| r.as_ref().err())
@@ -45,63 +562,870 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .is_some();
 
     if had_errors {
-        panic!("Problems with pubspecs...");
+        return Err("Problems with pubspecs...".into());
+    }
+
+    Ok(pub_specs.into_iter().flatten().map(Project::from).collect())
+}
+
+fn add(directory: PathBuf, packages: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let requests = packages
+        .iter()
+        .map(|arg| DependencyRequest::parse(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let context = Context::load()?;
+    let sources = context.sources();
+    let mut project = Project::load(&directory)?;
+    let mut editor = PubspecEditor::from_file(project.pubspec_path())?;
+
+    for request in &requests {
+        // A package is either a regular or a dev dependency, never both
+        if request.section != Section::DependencyOverrides {
+            for other in [Section::Dependencies, Section::DevDependencies] {
+                if other != request.section && editor.remove_dependency(other, &request.name)? {
+                    println!(
+                        "Moving {} from {} to {}",
+                        request.name,
+                        other.key(),
+                        request.section.key()
+                    );
+                }
+            }
+        }
+        let spec = request
+            .spec
+            .clone()
+            .unwrap_or_else(|| DependencySpec::Simple("any".to_string()));
+        editor.set_dependency(request.section, &request.name, &spec)?;
+    }
+
+    project.pubspec = editor.pubspec()?;
+    let resolution = resolve(&sources, &project)?;
+
+    // Like dart, hosted packages added without a constraint get one compatible with the
+    // version that was resolved
+    for request in requests.iter().filter(|r| r.spec.is_none()) {
+        let resolved = resolution
+            .packages
+            .get(&PackageName::new(&request.name))
+            .filter(|p| matches!(p.source, ResolvedSource::Hosted { .. }));
+        if let Some(package) = resolved {
+            let constraint = VersionConstraint::compatible_with(package.version.clone());
+            let spec = DependencySpec::Simple(constraint.to_string());
+            editor.set_dependency(request.section, &request.name, &spec)?;
+            println!("Added {}: {}", request.name, constraint);
+        }
+    }
+
+    project.pubspec = editor.pubspec()?;
+    editor.write_to_file(project.pubspec_path())?;
+    context.install(&[(project, resolution)])
+}
+
+fn remove(directory: PathBuf, packages: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let sources = context.sources();
+    let mut project = Project::load(&directory)?;
+    let mut editor = PubspecEditor::from_file(project.pubspec_path())?;
+
+    for name in &packages {
+        let mut removed = false;
+        for section in Section::ALL {
+            removed |= editor.remove_dependency(section, name)?;
+        }
+        if !removed {
+            eprintln!(
+                "Warning: {} is not in {}",
+                name,
+                project.pubspec_path().display()
+            );
+        }
+    }
+
+    project.pubspec = editor.pubspec()?;
+    let resolution = resolve(&sources, &project)?;
+    editor.write_to_file(project.pubspec_path())?;
+    context.install(&[(project, resolution)])
+}
+
+fn upgrade(dirs: Vec<PathBuf>, options: UpgradeOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+    let sources = context.sources();
+
+    let mut failed = false;
+    let mut resolved = Vec::new();
+    for project in projects {
+        let dir = project.dir.clone();
+        match upgrade_project(&sources, project, &options) {
+            Ok((project, resolution)) => {
+                report_changes(&project, &resolution);
+                resolved.push((project, resolution));
+            }
+            Err(e) => {
+                eprintln!("Error upgrading {}: {}", dir.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    context.install(&resolved)?;
+
+    if failed {
+        return Err("Some projects could not be upgraded".into());
+    }
+
+    Ok(())
+}
+
+fn downgrade(dirs: Vec<PathBuf>, in_place: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+    let sources = context.sources();
+
+    let mut resolved = Vec::new();
+    let mut unresolved = Vec::new();
+    for project in projects {
+        let resolution = project
+            .root_package(sources.default_url())
+            .and_then(|root| {
+                Resolver::new(&sources)
+                    .with_preference(Preference::Oldest)
+                    .resolve(&root)
+            });
+        match resolution {
+            Ok(resolution) => resolved.push((project, resolution)),
+            Err(e) => unresolved.push((project.dir, e)),
+        }
+    }
+
+    context.download(&resolved)?;
+
+    for (project, resolution) in &resolved {
+        match in_place {
+            true => project.write(resolution, &context.pub_cache)?,
+            false => project.write_to(
+                resolution,
+                &context.pub_cache,
+                project.dir.join(DOWNGRADE_LOCK),
+                project
+                    .dir
+                    .join(".dart_tool")
+                    .join(DOWNGRADE_PACKAGE_CONFIG),
+            )?,
+        }
+        println!(
+            "Resolved {} packages at their lowest versions for {}",
+            resolution.packages.len(),
+            project.dir.display()
+        );
     }
 
-    let hosted_packages = hosted_packages_from(pub_specs);
-    let missing_packages = packages_missing_in_cache(&pub_cache, &hosted_packages);
+    if !unresolved.is_empty() {
+        eprintln!(
+            "Could not resolve the lowest versions of {} projects:",
+            unresolved.len()
+        );
+        for (dir, e) in &unresolved {
+            eprintln!("  {}: {}", dir.display(), e);
+        }
+        return Err("Some projects could not be downgraded".into());
+    }
 
-    if missing_packages.is_empty() {
-        println!("All packages are cached");
+    Ok(())
+}
+
+fn outdated(
+    dirs: Vec<PathBuf>,
+    json: bool,
+    monorepo: bool,
+    show_all: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+    let sources = context.sources();
+
+    let mut failed = false;
+    let mut reports = Vec::new();
+    for project in &projects {
+        match OutdatedReport::for_project(&sources, project) {
+            Ok(report) => reports.push((project.dir.clone(), report)),
+            Err(e) => {
+                eprintln!("Error checking {}: {}", project.dir.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    if monorepo {
+        let packages: Vec<_> = MonorepoPackage::aggregate(&reports)
+            .into_iter()
+            .filter(|p| show_all || p.is_outdated())
+            .collect();
+        match json {
+            true => println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "packages": packages }))?
+            ),
+            false => print_monorepo_table(&packages),
+        }
     } else {
-        let threadpool = ThreadPool::new(8);
+        let reports: Vec<_> = reports
+            .into_iter()
+            .map(|(dir, report)| match show_all {
+                true => (dir, report),
+                false => (dir, report.only_outdated()),
+            })
+            .collect();
+        match (json, reports.as_slice()) {
+            (true, [(_, report)]) => println!("{}", serde_json::to_string_pretty(report)?),
+            (true, _) => {
+                let projects: Vec<_> = reports
+                    .iter()
+                    .map(|(dir, report)| {
+                        serde_json::json!({ "directory": dir, "packages": report.packages })
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({ "projects": projects }))?
+                );
+            }
+            (false, _) => {
+                for (dir, report) in &reports {
+                    println!("{}:", dir.display());
+                    match report.packages.is_empty() {
+                        true => println!("Found no outdated packages\n"),
+                        false => println!("{}", report.to_table()),
+                    }
+                }
+            }
+        }
+    }
 
-        println!("Downloading {} packages...", missing_packages.len());
+    if failed {
+        return Err("Some projects could not be checked".into());
+    }
 
-        let things = missing_packages
-            .iter()
-            .map(|hp| (hp.name.clone(), hp.version.clone()))
-            .collect::<Vec<_>>();
+    Ok(())
+}
 
-        let (tx, rx) = mpsc::channel();
+/// Reads everything from pubspec.lock and the pub cache, so works offline once `get` has run
+fn deps(
+    directory: PathBuf,
+    style: DepsStyle,
+    json: bool,
+    no_dev: bool,
+    executables: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (pub_cache, sdks) = offline_context()?;
+    let project = Project::load(&directory)?;
 
-        let count = things.len() as u64;
+    let mut graph = DependencyGraph::load(&project, &pub_cache, &sdks)?;
+    if no_dev {
+        graph = graph.without_dev();
+    }
 
-        thread::spawn(move || {
-            display_progress_ind(count, rx);
-        });
+    if json {
+        println!("{}", serde_json::to_string_pretty(&graph.to_json())?);
+    } else if executables {
+        for executable in graph.executables() {
+            println!("{}", executable);
+        }
+    } else {
+        let output = match style {
+            DepsStyle::Tree => graph.tree(),
+            DepsStyle::List => graph.list(),
+            DepsStyle::Compact => graph.compact(),
+        };
+        print!("{}", output);
+    }
+    Ok(())
+}
+
+fn drift(
+    dirs: Vec<PathBuf>,
+    json: bool,
+    policy: DriftPolicy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let projects = scan_projects(dirs)?;
+    for project in projects.iter().filter(|p| p.lock.is_none()) {
+        eprintln!(
+            "Skipping {}, which has no pubspec.lock",
+            project.dir.display()
+        );
+    }
+
+    let report = DriftReport::for_projects(&projects);
+    match (json, report.packages.is_empty()) {
+        (true, _) => println!("{}", serde_json::to_string_pretty(&report)?),
+        (false, true) => println!("Every package is locked at the same version"),
+        (false, false) => print!("{}", report.to_table()),
+    }
+
+    let violations = policy.violations(&report);
+    for violation in &violations {
+        eprintln!("Error: {}", violation);
+    }
+    if !violations.is_empty() {
+        return Err("Drift exceeds the policy".into());
+    }
+    Ok(())
+}
+
+fn align(
+    dirs: Vec<PathBuf>,
+    package: PackageName,
+    to: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+
+    let constraint = match to {
+        Some(constraint) => {
+            VersionConstraint::parse(&constraint)?;
+            constraint
+        }
+        None => {
+            let newest = projects
+                .iter()
+                .filter_map(|p| p.lock.as_ref()?.packages.get(&package))
+                .filter_map(|spec| parse_version(spec.version.as_ref()).ok())
+                .max()
+                .ok_or_else(|| format!("No project has {} locked, so give --to", package))?;
+            format!("^{}", newest)
+        }
+    };
+
+    let mut failed = false;
+    let mut aligned = Vec::new();
+    let mut editors = HashMap::new();
+    for mut project in projects {
+        let mut editor = PubspecEditor::from_file(project.pubspec_path())?;
+        let sections = match editor.align(package.as_ref(), &constraint) {
+            Ok(sections) => sections,
+            Err(e) => {
+                eprintln!("Error editing {}: {}", project.pubspec_path().display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        if sections.is_empty() {
+            continue;
+        }
+        let keys: Vec<_> = sections.iter().map(Section::key).collect();
+        println!(
+            "{}: {} {} in {}",
+            project.dir.display(),
+            package,
+            constraint,
+            keys.join(", ")
+        );
+        project.pubspec = editor.pubspec()?;
+        editors.insert(project.pubspec_path(), editor);
+        aligned.push(project);
+    }
+
+    if aligned.is_empty() && !failed {
+        println!("Every project already has {} {}", package, constraint);
+        return Ok(());
+    }
 
-        downloader.download_packages_with_pool(&things, &threadpool, &tx);
+    // Only the pubspec.yaml of projects that still resolve is written
+    let sources = context.sources();
+    let mut resolved = Vec::new();
+    let mut edited = Vec::new();
+    for (project, resolution) in resolve_in_parallel(&sources, aligned, &package) {
+        match resolution {
+            Ok(resolution) => {
+                let path = project.pubspec_path();
+                edited.extend(editors.remove(&path).map(|editor| (path, editor)));
+                resolved.push((project, resolution));
+            }
+            Err(e) => {
+                eprintln!("Error resolving {}: {}", project.dir.display(), e);
+                failed = true;
+            }
+        }
     }
 
+    context.install_edited(&resolved, &edited)?;
+
+    if failed {
+        return Err("Some projects could not be aligned".into());
+    }
     Ok(())
 }
 
-fn hosted_packages_from(
-    results: Vec<Result<PubspecInfo, ScannerError>>,
-) -> Vec<HostedDependency> {
-    let hosted_packages: Vec<HostedDependency> = results
+fn link(dirs: Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Context::load()?;
+    let projects = scan_projects(dirs)?;
+    let links = Link::plan(&projects);
+
+    let mut failed = false;
+    let mut linked = Vec::new();
+    for mut project in projects {
+        let Some(link) = links.iter().find(|l| l.path == project.overrides_path()) else {
+            continue;
+        };
+        if let Err(e) = link.write() {
+            eprintln!("Error linking {}: {}", project.dir.display(), e);
+            failed = true;
+            continue;
+        }
+        let names: Vec<_> = link.linked.keys().map(String::as_str).collect();
+        println!("{}: linked {}", project.dir.display(), names.join(", "));
+        project.overrides = Some(link.overrides.clone());
+        linked.push(project);
+    }
+
+    if linked.is_empty() && !failed {
+        println!("No project depends on another");
+        return Ok(());
+    }
+
+    let sources = context.sources();
+    let mut resolved = Vec::new();
+    for project in linked {
+        match resolve(&sources, &project) {
+            Ok(resolution) => resolved.push((project, resolution)),
+            Err(e) => {
+                eprintln!("Error resolving {}: {}", project.dir.display(), e);
+                failed = true;
+            }
+        }
+    }
+
+    context.install(&resolved)?;
+
+    if failed {
+        return Err("Some projects could not be linked".into());
+    }
+    Ok(())
+}
+
+fn unlink(dirs: Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    let projects = scan_projects(dirs)?;
+    let mut unlinked = 0;
+    for project in &projects {
+        if link::unlink(project)? {
+            println!("{}: unlinked", project.dir.display());
+            unlinked += 1;
+        }
+    }
+    if unlinked > 0 {
+        println!("Run flutter-pub to resolve them against hosted versions again");
+    }
+    Ok(())
+}
+
+/// Resolves the projects on a few threads at once, keeping their locked versions apart from
+/// those of `unlocked`
+fn resolve_in_parallel(
+    sources: &PackageSources,
+    projects: Vec<Project>,
+    unlocked: &PackageName,
+) -> Vec<(Project, Result<Resolution, ProjectError>)> {
+    let pending = std::sync::Mutex::new(projects.into_iter());
+    let done = std::sync::Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                loop {
+                    let Some(project) = pending.lock().unwrap().next() else {
+                        break;
+                    };
+                    let resolution = project
+                        .root_package(sources.default_url())
+                        .map_err(ProjectError::from)
+                        .and_then(|root| {
+                            let mut resolver = Resolver::new(sources);
+                            if let Some(lock) = &project.lock {
+                                resolver = resolver.with_lock(lock);
+                            }
+                            Ok(resolver.with_unlocked([unlocked]).resolve(&root)?)
+                        });
+                    done.lock().unwrap().push((project, resolution));
+                }
+            });
+        }
+    });
+    let mut done = done.into_inner().unwrap();
+    done.sort_by(|(a, _), (b, _)| a.dir.cmp(&b.dir));
+    done
+}
+
+/// What `deps` and `why` need to read the dependency graphs of projects without the network
+fn offline_context() -> Result<(PubCache, SdkPaths), Box<dyn std::error::Error>> {
+    let config = Config::load_default()?;
+    let pub_cache = PubCache::new(pub_cache_path())?;
+    Ok((pub_cache, SdkPaths::from_env(config.flutter_root)))
+}
+
+fn why(dirs: Vec<PathBuf>, package: PackageName) -> Result<(), Box<dyn std::error::Error>> {
+    let (pub_cache, sdks) = offline_context()?;
+    let projects = scan_projects(dirs)?;
+
+    let mut failed = false;
+    let mut found = false;
+    for project in &projects {
+        let graph = match DependencyGraph::load(project, &pub_cache, &sdks) {
+            Ok(graph) => graph,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", project.dir.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        let paths = graph.paths_to(&package);
+        if paths.is_empty() {
+            continue;
+        }
+        found = true;
+        println!("{} {}:", graph.root.name, project.dir.display());
+        for path in &paths {
+            print_dependency_path(path);
+        }
+        println!();
+    }
+
+    if !found {
+        println!("No project depends on {}", package);
+    }
+    if failed {
+        return Err("Some projects could not be read".into());
+    }
+    Ok(())
+}
+
+/// Prints a path as `app -> foo ^1.0.0 (1.2.0) -> bar any (0.3.1)`, then where it starts
+fn print_dependency_path(path: &[DependencyEdge]) {
+    let Some(first) = path.first() else {
+        return;
+    };
+    let mut line = first.from.to_string();
+    for edge in path {
+        line.push_str(&format!(
+            " -> {} {} ({})",
+            edge.to, edge.constraint, edge.version
+        ));
+    }
+    println!("  {}", line);
+    println!(
+        "    from {} in {}",
+        first.section.key(),
+        first.pubspec.display()
+    );
+}
+
+fn print_monorepo_table(packages: &[MonorepoPackage]) {
+    let width = packages
         .iter()
-        .filter_map(|r| r.as_ref().ok())
-        .filter_map(|info| info.lock_file.as_ref())
-        .flat_map(|lockfile| &lockfile.packages)
-        .filter_map(|(name, spec)| {
-            spec.description.as_ref().and_then(|desc| match desc {
-                PackageDescription::Hosted(hosted) => Some(HostedDependency {
-                    name: name.clone(),
-                    version: spec.version.clone(),
-                    hosted: hosted.clone(),
-                }),
+        .map(|p| p.package.as_ref().len())
+        .max()
+        .unwrap_or(0)
+        .max("Package Name".len());
+    println!("{:width$}  Latest  Current", "Package Name", width = width);
+    for package in packages {
+        let latest = package
+            .latest
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        for (i, (version, dirs)) in package.current.iter().enumerate() {
+            let name = if i == 0 { package.package.as_ref() } else { "" };
+            let latest = if i == 0 { latest.as_str() } else { "" };
+            let projects = match dirs.len() {
+                1 => "1 project".to_string(),
+                n => format!("{} projects", n),
+            };
+            println!(
+                "{:width$}  {:6}  {} in {}",
+                name,
+                latest,
+                version,
+                projects,
+                width = width
+            );
+        }
+    }
+}
+
+/// Resolves a project again with the packages being upgraded unlocked, then rewrites the
+/// constraints in its pubspec.yaml as `--major-versions` and `--tighten` ask
+fn upgrade_project(
+    sources: &PackageSources,
+    mut project: Project,
+    options: &UpgradeOptions,
+) -> Result<(Project, Resolution), Box<dyn std::error::Error>> {
+    let mut editor = PubspecEditor::from_file(project.pubspec_path())?;
+    let original = project.pubspec.clone();
+
+    let unlocked: Option<BTreeSet<PackageName>> = match options.packages.is_empty() {
+        true => None,
+        false if options.unlock_transitive => {
+            Some(resolve(sources, &project)?.with_dependencies(&options.packages))
+        }
+        false => Some(options.packages.iter().cloned().collect()),
+    };
+    let upgrading = |name: &PackageName| unlocked.as_ref().is_none_or(|u| u.contains(name));
+    let direct = [Section::Dependencies, Section::DevDependencies];
+
+    // Constraints would keep new major versions out while resolving
+    if options.major_versions {
+        let mut relaxed = editor.clone();
+        for section in direct {
+            for (name, spec) in section.of(&original) {
+                if spec.hosted_constraint().is_some() && upgrading(&PackageName::new(name)) {
+                    relaxed.set_dependency(section, name, &spec.with_constraint("any"))?;
+                }
+            }
+        }
+        project.pubspec = relaxed.pubspec()?;
+    }
+
+    let mut resolver = Resolver::new(sources);
+    if let (Some(lock), Some(unlocked)) = (&project.lock, &unlocked) {
+        resolver = resolver.with_lock(lock).with_unlocked(unlocked);
+    }
+    let resolution = resolver.resolve(&project.root_package(sources.default_url())?)?;
+
+    let mut changed = false;
+    for section in direct {
+        for (name, spec) in section.of(&original) {
+            let package_name = PackageName::new(name);
+            let Some(package) = resolution
+                .packages
+                .get(&package_name)
+                .filter(|p| p.kind != DependencyKind::DirectOverridden)
+            else {
+                continue;
+            };
+            let Some(current) = spec.hosted_constraint() else {
+                continue;
+            };
+            if !upgrading(&package_name) {
+                continue;
+            }
+
+            let current = VersionConstraint::parse(current)?;
+            let constraint = match current.allows(&package.version) {
+                false if options.major_versions => {
+                    Some(VersionConstraint::compatible_with(package.version.clone()))
+                }
+                true if options.tighten => current.tightened_to(&package.version),
                 _ => None,
-            })
+            };
+            if let Some(constraint) = constraint {
+                println!("Changed {}: {} to {}", name, current, constraint);
+                editor.set_dependency(
+                    section,
+                    name,
+                    &spec.with_constraint(constraint.to_string()),
+                )?;
+                changed = true;
+            }
+        }
+    }
+
+    project.pubspec = editor.pubspec()?;
+    if changed {
+        editor.write_to_file(project.pubspec_path())?;
+    }
+    Ok((project, resolution))
+}
+
+/// Prints the packages whose versions differ from those in the project's pubspec.lock
+fn report_changes(project: &Project, resolution: &Resolution) {
+    let locked = |name: &PackageName| {
+        let lock = project.lock.as_ref()?;
+        Some(lock.packages.get(name)?.version.to_string())
+    };
+    for package in resolution.packages.values() {
+        let version = package.version.to_string();
+        match locked(&package.name) {
+            Some(was) if was == version => {}
+            Some(was) => println!("> {} {} (was {})", package.name, version, was),
+            None => println!("+ {} {}", package.name, version),
+        }
+    }
+    if let Some(lock) = &project.lock {
+        let removed: BTreeSet<_> = lock
+            .packages
+            .keys()
+            .filter(|name| !resolution.packages.contains_key(*name))
+            .collect();
+        for name in removed {
+            println!("- {}", name);
+        }
+    }
+}
+
+fn resolve(sources: &PackageSources, project: &Project) -> Result<Resolution, ProjectError> {
+    let mut resolver = Resolver::new(sources);
+    if let Some(lock) = &project.lock {
+        resolver = resolver.with_lock(lock);
+    }
+    if project.pubspec.is_workspace() {
+        let roots = project
+            .workspace_packages()
+            .iter()
+            .map(|p| p.root_package(sources.default_url()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(resolver.resolve_workspace(&roots)?.workspace());
+    }
+    let root = project.root_package(sources.default_url())?;
+    Ok(resolver.resolve(&root)?)
+}
+
+/// Unpacks a downloaded archive into the pub cache, recording its hash like dart does
+fn extract_package(
+    cache: &PubCache,
+    downloader: &PackageDownloader,
+    package: &HostedDependency,
+    archive: &Path,
+) -> Result<(), String> {
+    let path = cache
+        .get_package_path(&package.name, &package.version, &package.hosted)
+        .map_err(|e| e.to_string())?;
+    downloader
+        .extract_package(archive, &path)
+        .map_err(|e| e.to_string())?;
+    if let Some(host) = package.hosted.url.host_str()
+        && let Some(sha256) = &package.hosted.sha256
+    {
+        cache
+            .write_package_hash(host, &package.name, &package.version, sha256)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn token(command: TokenCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tokens = TokenStore::load_default()?;
+
+    match command {
+        TokenCommand::Add {
+            hosted_url,
+            env_var,
+        } => {
+            let credential = match env_var {
+                Some(var) => Credential::with_env_var(hosted_url.clone(), var),
+                None => {
+                    eprint!("Enter secret token: ");
+                    let mut token = String::new();
+                    io::stdin().read_line(&mut token)?;
+                    let token = token.trim();
+                    if token.is_empty() {
+                        return Err("No token given".into());
+                    }
+                    Credential::with_token(hosted_url.clone(), token)
+                }
+            };
+            tokens.add(credential)?;
+            tokens.save()?;
+            println!("Token added for {}", hosted_url);
+        }
+        TokenCommand::List => {
+            if tokens.credentials().is_empty() {
+                println!("No tokens are stored in {}", tokens.path().display());
+            }
+            for credential in tokens.credentials() {
+                match &credential.env {
+                    Some(var) => println!("{} (from ${})", credential.url, var),
+                    None => println!("{}", credential.url),
+                }
+            }
+        }
+        TokenCommand::Remove { hosted_url, all } => {
+            if all {
+                tokens.remove_all();
+                println!("Removed all tokens");
+            } else if let Some(hosted_url) = hosted_url {
+                if tokens.remove(&hosted_url)? {
+                    println!("Removed token for {}", hosted_url);
+                } else {
+                    println!("No token found for {}", hosted_url);
+                }
+            }
+            tokens.save()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn serve(
+    address: IpAddr,
+    port: u16,
+    host: Option<String>,
+    upstream: Option<Url>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let host = host
+        .or_else(|| {
+            upstream
+                .as_ref()
+                .and_then(|u| u.host_str().map(String::from))
+        })
+        .unwrap_or_else(|| "pub.dev".to_string());
+
+    let pub_cache = PubCache::new(pub_cache_path())?;
+    println!(
+        "Serving packages from {} in {}",
+        host,
+        pub_cache.root_path().display()
+    );
+
+    let mut repository = CacheRepository::new(pub_cache, host);
+    if let Some(upstream) = upstream {
+        let config = Config::load_default()?;
+        let http = HttpClient::new(
+            HttpSettings::from_env(config.ca_bundle.clone()),
+            Some(TokenStore::load_default()?),
+        )?;
+        println!("Fetching packages missing from the cache from {}", upstream);
+        repository = repository.with_upstream(PubClient::with_agent(upstream, http))?;
+    }
+    let server = Arc::new(Server::bind((address, port), repository)?);
+    println!("Listening on http://{}", SocketAddr::new(address, port));
+
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let server = server.clone();
+            thread::spawn(move || server.run())
         })
         .collect();
-    hosted_packages
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    Ok(())
+}
+
+fn hosted_packages_from(resolved: &[(Project, Resolution)]) -> Vec<HostedDependency> {
+    resolved
+        .iter()
+        .flat_map(|(_, resolution)| resolution.packages.values())
+        .filter_map(|package| match &package.source {
+            ResolvedSource::Hosted { url, sha256 } => Some(HostedDependency {
+                name: package.name.clone(),
+                version: PackageVersion::new(package.version.to_string()),
+                hosted: HostedPackage {
+                    name: package.name.clone(),
+                    url: url.clone(),
+                    sha256: sha256.clone(),
+                },
+                direct: package.kind != DependencyKind::Transitive,
+            }),
+            _ => None,
+        })
+        .collect()
 }
 
 fn packages_missing_in_cache<'a>(
     cache: &PubCache,
-    hosted_packages: &'a Vec<HostedDependency>,
+    hosted_packages: &'a [HostedDependency],
 ) -> Vec<&'a HostedDependency> {
     let missing_packages: Vec<_> = hosted_packages
         .iter()
@@ -111,8 +1435,10 @@ fn packages_missing_in_cache<'a>(
                 .map(|path| path.exists())
                 .unwrap_or(false)
         })
+        // Projects may resolve different versions, or the same name from different hosts
         .fold(BTreeMap::new(), |mut map, package| {
-            map.entry(&package.name).or_insert(package);
+            let key = (&package.name, &package.version, package.hosted.url.as_str());
+            map.entry(key).or_insert(package);
             map
         })
         .into_values()
@@ -120,6 +1446,53 @@ fn packages_missing_in_cache<'a>(
     missing_packages
 }
 
+/// Warns about direct dependencies that pub.dev (or their hosted repository) has marked as discontinued
+fn warn_discontinued(
+    http: &HttpClient,
+    pub_dev_source: &Url,
+    hosted_packages: &[HostedDependency],
+) {
+    let pub_dev = config::pub_dev_url();
+    let direct: BTreeSet<_> = hosted_packages
+        .iter()
+        .filter(|d| d.direct)
+        .map(|d| match d.hosted.url == pub_dev {
+            true => (d.name.clone(), pub_dev_source.clone()),
+            false => (d.name.clone(), d.hosted.url.clone()),
+        })
+        .collect();
+
+    let threadpool = ThreadPool::new(8);
+    let (tx, rx) = mpsc::channel();
+
+    for (name, url) in direct {
+        let tx = tx.clone();
+        let http = http.clone();
+        threadpool.execute(move || {
+            let listing = PubClient::with_agent(url, http).listing(&name);
+            let _ = tx.send(listing);
+        });
+    }
+    drop(tx);
+
+    let mut discontinued: Vec<_> = rx
+        .iter()
+        .filter_map(|listing| listing.ok())
+        .filter(|listing| listing.is_discontinued)
+        .collect();
+    discontinued.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for listing in discontinued {
+        match &listing.replaced_by {
+            Some(replacement) => eprintln!(
+                "Warning: {} is discontinued, replaced by {}",
+                listing.name, replacement
+            ),
+            None => eprintln!("Warning: {} is discontinued", listing.name),
+        }
+    }
+}
+
 fn display_progress_ind(expected: u64, rx: Receiver<DownloadEvent>) {
     let multi = MultiProgress::new();
     let main_style = ProgressStyle::default_bar()
@@ -163,7 +1536,7 @@ fn display_progress_ind(expected: u64, rx: Receiver<DownloadEvent>) {
                 if let Some(pb) = active_downloads.get(&package) {
                     pb.finish_with_message(format!("{} downloaded successfully", package));
                     overall.inc(1);
-                    multi.remove(&pb);
+                    multi.remove(pb);
                     active_downloads.remove(&package);
                 }
             }
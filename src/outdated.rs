@@ -0,0 +1,355 @@
+use crate::project::Project;
+use crate::pubspeclock::{PackageDescription, PackageName};
+use crate::resolver::{
+    Dependency, HostedVersion, PackageSource, PackageSources, Resolution, ResolveError,
+    ResolvedSource, Resolver, RootPackage,
+};
+use crate::version::{VersionConstraint, parse_version};
+use semver::Version;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use url::Url;
+
+/// How a project depends on a package, as `dart pub outdated` groups them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutdatedKind {
+    Direct,
+    Dev,
+    Transitive,
+}
+
+impl OutdatedKind {
+    fn heading(&self) -> &'static str {
+        match self {
+            OutdatedKind::Direct => "direct dependencies",
+            OutdatedKind::Dev => "dev_dependencies",
+            OutdatedKind::Transitive => "transitive dependencies",
+        }
+    }
+}
+
+/// One package of a project, serialized like an entry of `dart pub outdated --json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutdatedPackage {
+    pub package: PackageName,
+    pub kind: OutdatedKind,
+    pub is_discontinued: bool,
+    #[serde(skip)]
+    pub replaced_by: Option<PackageName>,
+    pub is_current_retracted: bool,
+    pub is_current_affected_by_advisory: bool,
+    /// The version in pubspec.lock
+    #[serde(serialize_with = "version_object")]
+    pub current: Option<Version>,
+    /// The newest version the pubspec's constraints allow
+    #[serde(serialize_with = "version_object")]
+    pub upgradable: Option<Version>,
+    /// The newest version that could be resolved if the constraints allowed it
+    #[serde(serialize_with = "version_object")]
+    pub resolvable: Option<Version>,
+    /// The newest version published
+    #[serde(serialize_with = "version_object")]
+    pub latest: Option<Version>,
+}
+
+impl OutdatedPackage {
+    /// Whether anything newer is available, or the current version should be replaced
+    pub fn is_outdated(&self) -> bool {
+        self.is_discontinued
+            || self.is_current_retracted
+            || self.is_current_affected_by_advisory
+            || [&self.upgradable, &self.resolvable, &self.latest]
+                .into_iter()
+                .any(|v| v.is_some() && v != &self.current)
+    }
+}
+
+/// dart writes versions as `{"version": "1.2.3"}`, or `null` when there isn't one
+fn version_object<S: Serializer>(
+    version: &Option<Version>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct VersionObject {
+        version: String,
+    }
+    version
+        .as_ref()
+        .map(|v| VersionObject {
+            version: v.to_string(),
+        })
+        .serialize(serializer)
+}
+
+/// The packages of a project, serialized like `dart pub outdated --json`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OutdatedReport {
+    pub packages: Vec<OutdatedPackage>,
+}
+
+impl OutdatedReport {
+    /// Compares a project's locked versions with the newest its constraints allow, the newest
+    /// it could have if they were relaxed, and the newest published
+    pub fn for_project(
+        sources: &PackageSources,
+        project: &Project,
+    ) -> Result<OutdatedReport, ResolveError> {
+        let root = project.root_package(sources.default_url())?;
+        // A project that can't be resolved has nothing to upgrade to, which dart shows as `-`
+        let upgradable = Resolver::new(sources).resolve(&root).ok();
+        let resolvable = Resolver::new(sources).resolve(&relaxed(&root)).ok();
+
+        let current: BTreeMap<_, _> = project
+            .lock
+            .iter()
+            .flat_map(|lock| &lock.packages)
+            .filter_map(|(name, spec)| {
+                let version = parse_version(spec.version.as_ref()).ok()?;
+                let url = match &spec.description {
+                    Some(PackageDescription::Hosted(hosted)) => Some(hosted.url.clone()),
+                    _ => None,
+                };
+                Some((name.clone(), (version, url)))
+            })
+            .collect();
+
+        let resolved = |resolution: &Option<Resolution>, name: &PackageName| {
+            let package = resolution.as_ref()?.packages.get(name)?;
+            let url = match &package.source {
+                ResolvedSource::Hosted { url, .. } => Some(url.clone()),
+                _ => None,
+            };
+            Some((package.version.clone(), url))
+        };
+
+        let names: BTreeSet<_> = current
+            .keys()
+            .chain(upgradable.iter().flat_map(|r| r.packages.keys()))
+            .chain(resolvable.iter().flat_map(|r| r.packages.keys()))
+            .cloned()
+            .collect();
+
+        let mut packages = Vec::new();
+        for name in names {
+            let current = current.get(&name).cloned();
+            let upgradable = resolved(&upgradable, &name);
+            let resolvable = resolved(&resolvable, &name);
+            let url = [&current, &upgradable, &resolvable]
+                .into_iter()
+                .find_map(|v| v.as_ref()?.1.clone());
+            let current = current.map(|(version, _)| version);
+
+            let mut package = OutdatedPackage {
+                kind: kind(&root, &name),
+                is_discontinued: false,
+                replaced_by: None,
+                is_current_retracted: false,
+                is_current_affected_by_advisory: false,
+                current,
+                upgradable: upgradable.map(|(version, _)| version),
+                resolvable: resolvable.map(|(version, _)| version),
+                latest: None,
+                package: name,
+            };
+            if let Some(url) = url {
+                hosted_details(sources, &url, &mut package)?;
+            }
+            packages.push(package);
+        }
+        packages.sort_by(|a, b| (a.kind, &a.package).cmp(&(b.kind, &b.package)));
+
+        Ok(OutdatedReport { packages })
+    }
+
+    /// The report without the packages that are up to date
+    pub fn only_outdated(self) -> OutdatedReport {
+        OutdatedReport {
+            packages: self
+                .packages
+                .into_iter()
+                .filter(|p| p.is_outdated())
+                .collect(),
+        }
+    }
+
+    /// A table like the one `dart pub outdated` prints, where `*` marks versions that aren't
+    /// the latest
+    pub fn to_table(&self) -> String {
+        let cell = |version: &Option<Version>, latest: &Option<Version>| match version {
+            Some(v) if latest.as_ref().is_none_or(|latest| latest == v) => v.to_string(),
+            Some(v) => format!("*{}", v),
+            None => "-".to_string(),
+        };
+        let mut rows = vec![(
+            None,
+            [
+                "Package Name".to_string(),
+                "Current".to_string(),
+                "Upgradable".to_string(),
+                "Resolvable".to_string(),
+                "Latest".to_string(),
+            ],
+        )];
+        for package in &self.packages {
+            let mut name = package.package.to_string();
+            if package.is_discontinued {
+                match &package.replaced_by {
+                    Some(replacement) => {
+                        name.push_str(&format!(" (discontinued, replaced by {})", replacement))
+                    }
+                    None => name.push_str(" (discontinued)"),
+                }
+            }
+            let mut current = cell(&package.current, &package.latest);
+            if package.is_current_retracted {
+                current.push_str(" (retracted)");
+            }
+            if package.is_current_affected_by_advisory {
+                current.push_str(" (advisory)");
+            }
+            rows.push((
+                Some(package.kind),
+                [
+                    name,
+                    current,
+                    cell(&package.upgradable, &package.latest),
+                    cell(&package.resolvable, &package.latest),
+                    cell(&package.latest, &package.latest),
+                ],
+            ));
+        }
+
+        let mut widths = [0; 5];
+        for (_, row) in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+        let mut kind = None;
+        for (row_kind, row) in &rows {
+            if row_kind.is_some() && *row_kind != kind {
+                kind = *row_kind;
+                table.push_str(&format!("\n{}:\n", row_kind.unwrap().heading()));
+            }
+            let line: Vec<_> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            table.push_str(line.join("  ").trim_end());
+            table.push('\n');
+        }
+        table
+    }
+}
+
+/// The root with the constraints of its hosted dependencies removed, to find what would be
+/// resolved if they allowed anything
+fn relaxed(root: &RootPackage) -> RootPackage {
+    let mut relaxed = root.clone();
+    for dependency in relaxed
+        .dependencies
+        .iter_mut()
+        .chain(relaxed.dev_dependencies.iter_mut())
+    {
+        if matches!(dependency.source, PackageSource::Hosted(_)) {
+            dependency.constraint = VersionConstraint::any();
+        }
+    }
+    relaxed
+}
+
+fn kind(root: &RootPackage, name: &PackageName) -> OutdatedKind {
+    let declared = |dependencies: &[Dependency]| dependencies.iter().any(|d| &d.name == name);
+    if declared(&root.dependencies) {
+        OutdatedKind::Direct
+    } else if declared(&root.dev_dependencies) {
+        OutdatedKind::Dev
+    } else {
+        OutdatedKind::Transitive
+    }
+}
+
+/// Fills in what the repository says about a hosted package
+fn hosted_details(
+    sources: &PackageSources,
+    url: &Url,
+    package: &mut OutdatedPackage,
+) -> Result<(), ResolveError> {
+    let name = &package.package;
+    let versions = sources.hosted_versions(name, url)?;
+    let status = sources.hosted_status(name, url)?;
+
+    package.latest = latest(&versions, package.current.as_ref());
+    package.is_discontinued = status.is_discontinued;
+    package.replaced_by = status.replaced_by;
+    if let Some(current) = &package.current {
+        package.is_current_retracted = versions
+            .iter()
+            .any(|v| &v.version == current && v.retracted);
+        package.is_current_affected_by_advisory = sources
+            .advisories(name, url)?
+            .iter()
+            .any(|advisory| advisory.affects(name, &current.to_string()));
+    }
+    Ok(())
+}
+
+/// The newest version that isn't retracted, only a prerelease if that's what is in use or
+/// there is nothing else
+fn latest(versions: &[HostedVersion], current: Option<&Version>) -> Option<Version> {
+    let available = || versions.iter().filter(|v| !v.retracted).map(|v| &v.version);
+    let prerelease = current.is_some_and(|c| !c.pre.is_empty());
+    available()
+        .filter(|v| prerelease || v.pre.is_empty())
+        .max()
+        .or_else(|| available().max())
+        .cloned()
+}
+
+/// One package across a monorepo: which projects use which versions of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MonorepoPackage {
+    pub package: PackageName,
+    #[serde(serialize_with = "version_object")]
+    pub latest: Option<Version>,
+    /// The projects at each version, by their directories
+    pub current: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl MonorepoPackage {
+    /// Combines the reports of many projects into one entry per package
+    pub fn aggregate(reports: &[(PathBuf, OutdatedReport)]) -> Vec<MonorepoPackage> {
+        let mut packages: BTreeMap<PackageName, MonorepoPackage> = BTreeMap::new();
+        for (dir, report) in reports {
+            for package in &report.packages {
+                let entry =
+                    packages
+                        .entry(package.package.clone())
+                        .or_insert_with(|| MonorepoPackage {
+                            package: package.package.clone(),
+                            latest: None,
+                            current: BTreeMap::new(),
+                        });
+                entry.latest = entry.latest.clone().max(package.latest.clone());
+                let current = match &package.current {
+                    Some(version) => version.to_string(),
+                    None => "-".to_string(),
+                };
+                entry.current.entry(current).or_default().push(dir.clone());
+            }
+        }
+        packages.into_values().collect()
+    }
+
+    /// Whether the projects use different versions, or any of them isn't the latest
+    pub fn is_outdated(&self) -> bool {
+        let latest = self.latest.as_ref().map(Version::to_string);
+        self.current.len() > 1 || latest.is_some_and(|latest| !self.current.contains_key(&latest))
+    }
+}
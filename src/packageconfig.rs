@@ -74,6 +74,12 @@ impl Package {
     }
 }
 
+impl Default for PackageConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PackageConfig {
     /// Load package configuration from a file
     pub fn from_file<P: AsRef<std::path::Path>>(
@@ -0,0 +1,303 @@
+use crate::packageconfig::{Package, PackageConfig};
+use crate::pubcache::PubCache;
+use crate::pubspec::{Pubspec, PubspecError, PubspecOverrides};
+use crate::pubspeclock::{
+    GitPackage, HostedPackage, PackageDescription, PackageName, PackageSpec, PackageVersion,
+    PathPackage, PubspecLock, PubspecLockError, Sdks,
+};
+use crate::resolver::{
+    Resolution, ResolveError, ResolvedSource, RootPackage, dependencies_in, git_path, git_ref,
+};
+use crate::scanner::{PubspecInfo, Scanner, ScannerError};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum ProjectError {
+    #[error(transparent)]
+    PubspecError(#[from] PubspecError),
+    #[error(transparent)]
+    PubspecLockError(#[from] PubspecLockError),
+    #[error(transparent)]
+    ResolveError(#[from] ResolveError),
+    #[error("IO error at {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to write {path}: {message}")]
+    PackageConfigError { path: PathBuf, message: String },
+    #[error(transparent)]
+    ScannerError(#[from] ScannerError),
+}
+
+/// A package being worked on: a directory with a pubspec.yaml, and perhaps a pubspec.lock
+#[derive(Debug)]
+pub struct Project {
+    pub dir: PathBuf,
+    pub pubspec: Pubspec,
+    pub lock: Option<PubspecLock>,
+    /// What pubspec_overrides.yaml says, if there is one
+    pub overrides: Option<PubspecOverrides>,
+    /// The packages of the workspace, when this is the root of one
+    pub members: Vec<Project>,
+}
+
+impl From<PubspecInfo> for Project {
+    fn from(info: PubspecInfo) -> Self {
+        Project {
+            dir: info
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            pubspec: info.pubspec,
+            lock: info.lock_file,
+            overrides: info.overrides,
+            members: info.members.into_iter().map(Project::from).collect(),
+        }
+    }
+}
+
+impl Project {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let info = Scanner::load_pubspec_files(&dir.as_ref().join("pubspec.yaml"))?;
+        Ok(Project::from(info))
+    }
+
+    /// The root of the workspace and every package in it, nested workspaces included, or just
+    /// this project when it isn't one
+    pub fn workspace_packages(&self) -> Vec<&Project> {
+        std::iter::once(self)
+            .chain(self.members.iter().flat_map(|m| m.workspace_packages()))
+            .collect()
+    }
+
+    pub fn pubspec_path(&self) -> PathBuf {
+        self.dir.join("pubspec.yaml")
+    }
+
+    pub fn lock_path(&self) -> PathBuf {
+        self.dir.join("pubspec.lock")
+    }
+
+    pub fn package_config_path(&self) -> PathBuf {
+        self.dir.join(".dart_tool").join("package_config.json")
+    }
+
+    pub fn overrides_path(&self) -> PathBuf {
+        self.dir.join("pubspec_overrides.yaml")
+    }
+
+    /// The project as the resolver sees it, where the overrides in pubspec_overrides.yaml
+    /// replace those in pubspec.yaml, as they do for dart
+    pub fn root_package(&self, default_url: &Url) -> Result<RootPackage, ResolveError> {
+        let mut root = RootPackage::from_pubspec(&self.pubspec, &self.dir, default_url)?;
+        if let Some(overrides) = &self.overrides {
+            root.overrides =
+                dependencies_in(&overrides.dependency_overrides, &root.dir, default_url)?;
+        }
+        Ok(root)
+    }
+
+    /// The pubspec.lock describing a resolution of this project
+    pub fn lock_file(&self, resolution: &Resolution) -> PubspecLock {
+        // Like dart, leave a workspace's own packages out of its lockfile
+        let mut lock = lock_file_of(resolution);
+        for member in self.workspace_packages() {
+            lock.packages
+                .remove(&PackageName::new(&member.pubspec.name));
+        }
+        lock
+    }
+
+    /// The .dart_tool/package_config.json for a resolution of this project, with local
+    /// packages relative to it and everything else in the pub cache
+    pub fn package_config(&self, resolution: &Resolution, cache: &PubCache) -> PackageConfig {
+        let config_dir = resolution.root.dir.join(".dart_tool");
+        let mut config = PackageConfig::default();
+
+        for package in resolution.packages.values() {
+            let root_uri = match &package.source {
+                ResolvedSource::Hosted { url, .. } => url.host_str().and_then(|host| {
+                    let version = PackageVersion::new(package.version.to_string());
+                    let dir = cache.hosted_package_path(host, &package.name, &version);
+                    Url::from_directory_path(dir).ok().map(String::from)
+                }),
+                ResolvedSource::Path {
+                    path,
+                    relative: true,
+                } => Some(relative_path(path, &config_dir)),
+                ResolvedSource::Path { path: dir, .. }
+                | ResolvedSource::Git { dir, .. }
+                | ResolvedSource::Sdk { dir, .. } => {
+                    Url::from_directory_path(dir).ok().map(String::from)
+                }
+            };
+            let Some(root_uri) = root_uri else {
+                continue;
+            };
+
+            let mut entry =
+                Package::new(package.name.to_string(), "lib/".to_string()).with_root_uri(root_uri);
+            if let Some(version) = &package.language_version {
+                entry = entry.with_language_version(version.clone());
+            }
+            config.add_package(entry);
+        }
+
+        let mut root = Package::new(resolution.root.name.to_string(), "lib/".to_string())
+            .with_root_uri("../".to_string());
+        if let Some(version) = &resolution.root.language_version {
+            root = root.with_language_version(version.clone());
+        }
+        config.add_package(root);
+        config.packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        config
+    }
+
+    /// Writes pubspec.lock and .dart_tool/package_config.json for a resolution of this project
+    pub fn write(&self, resolution: &Resolution, cache: &PubCache) -> Result<(), ProjectError> {
+        self.write_to(
+            resolution,
+            cache,
+            self.lock_path(),
+            self.package_config_path(),
+        )
+    }
+
+    /// Writes a resolution somewhere other than the usual files, e.g. to try it out without
+    /// touching pubspec.lock. The package config's paths are relative to .dart_tool, so it
+    /// has to be written there.
+    pub fn write_to(
+        &self,
+        resolution: &Resolution,
+        cache: &PubCache,
+        lock_path: PathBuf,
+        package_config_path: PathBuf,
+    ) -> Result<(), ProjectError> {
+        self.lock_file(resolution).write_to_file(lock_path)?;
+        self.write_package_config(resolution, cache, package_config_path)?;
+        self.write_workspace_refs()
+    }
+
+    /// Writes .dart_tool/pub/workspace_ref.json in each member of a workspace, pointing back
+    /// at its root, as dart does
+    fn write_workspace_refs(&self) -> Result<(), ProjectError> {
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| ProjectError::IoError { path, source }
+        };
+        let root = self.dir.canonicalize().map_err(io_error(&self.dir))?;
+        for member in self.workspace_packages().into_iter().skip(1) {
+            let dir = member.dir.canonicalize().map_err(io_error(&member.dir))?;
+            let ref_dir = dir.join(".dart_tool").join("pub");
+            fs::create_dir_all(&ref_dir).map_err(io_error(&ref_dir))?;
+            let contents = serde_json::json!({ "workspaceRoot": relative_path(&root, &ref_dir) });
+            let path = ref_dir.join("workspace_ref.json");
+            let contents = format!("{}\n", serde_json::to_string_pretty(&contents).unwrap());
+            fs::write(&path, contents).map_err(io_error(&path))?;
+        }
+        Ok(())
+    }
+
+    /// Writes only the package config, for a project whose lockfile is elsewhere
+    pub fn write_package_config(
+        &self,
+        resolution: &Resolution,
+        cache: &PubCache,
+        path: PathBuf,
+    ) -> Result<(), ProjectError> {
+        let config_dir = path.parent().expect("package_config.json is in .dart_tool");
+        fs::create_dir_all(config_dir).map_err(|e| ProjectError::IoError {
+            path: config_dir.to_path_buf(),
+            source: e,
+        })?;
+        self.package_config(resolution, cache)
+            .write_to_file(&path)
+            .map_err(|e| ProjectError::PackageConfigError {
+                message: e.to_string(),
+                path,
+            })
+    }
+}
+
+/// The pubspec.lock describing a resolution, with local packages relative to its root
+pub fn lock_file_of(resolution: &Resolution) -> PubspecLock {
+    let root = &resolution.root.dir;
+    let packages = resolution
+        .packages
+        .values()
+        .map(|package| {
+            let (source, description) = match &package.source {
+                ResolvedSource::Hosted { url, sha256 } => (
+                    "hosted",
+                    PackageDescription::Hosted(HostedPackage {
+                        name: package.name.clone(),
+                        url: url.clone(),
+                        sha256: sha256.clone(),
+                    }),
+                ),
+                ResolvedSource::Path { path, relative } => (
+                    "path",
+                    PackageDescription::Path(match relative {
+                        true => PathPackage {
+                            path: relative_path(path, root),
+                            relative: true,
+                        },
+                        false => PathPackage {
+                            path: path.to_string_lossy().into_owned(),
+                            relative: false,
+                        },
+                    }),
+                ),
+                // dart writes the ref and path even when the pubspec leaves them out
+                ResolvedSource::Git { package, .. } => (
+                    "git",
+                    PackageDescription::Git(GitPackage {
+                        ref_: Some(git_ref(package).to_string()),
+                        path: Some(git_path(package).to_string()),
+                        ..package.clone()
+                    }),
+                ),
+                ResolvedSource::Sdk { sdk, .. } => ("sdk", PackageDescription::Sdk(sdk.clone())),
+            };
+            let spec = PackageSpec {
+                version: PackageVersion::new(package.version.to_string()),
+                source: source.to_string(),
+                dependency: package.kind.to_string(),
+                description: Some(description),
+            };
+            (package.name.clone(), spec)
+        })
+        .collect::<HashMap<_, _>>();
+
+    // Like dart, record the SDK versions that every package in the resolution supports
+    let sdks = resolution.sdk_constraints();
+    PubspecLock {
+        sdks: Some(Sdks {
+            dart: sdks.dart.map(|c| c.to_range_string()),
+            flutter: sdks.flutter.map(|c| c.to_range_string()),
+        }),
+        packages,
+    }
+}
+
+/// `path` relative to `base`, with forward slashes as in lockfiles, pubspecs and package configs
+pub(crate) fn relative_path(path: &Path, base: &Path) -> String {
+    let relative = pathdiff::diff_paths(path, base).unwrap_or_else(|| path.to_path_buf());
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    match parts.is_empty() {
+        true => ".".to_string(),
+        false => parts.join("/"),
+    }
+}
@@ -1,8 +1,10 @@
 use crate::pubspeclock::{HostedPackage, PackageName, PackageVersion, Sha256};
+use sha2::Digest;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 use thiserror::Error;
+use url::Url;
 
 #[derive(Error, Debug)]
 pub enum PubCacheError {
@@ -19,6 +21,17 @@ impl From<io::Error> for PubCacheError {
     }
 }
 
+/// The directory name for what comes from a hosted repository: its host, followed by any port
+/// and path, with the characters that can't be in a file name escaped the way dart does
+pub fn hosted_dir_name(url: &Url) -> Option<String> {
+    let mut name = url.host_str()?.to_string();
+    if let Some(port) = url.port() {
+        name = format!("{}:{}", name, port);
+    }
+    name.push_str(url.path().trim_end_matches('/'));
+    Some(name.replace(':', "%58").replace('/', "%47"))
+}
+
 pub struct PubCache {
     root: PathBuf,
 }
@@ -35,6 +48,22 @@ impl PubCache {
         self.root.join("hosted").join("downloads")
     }
 
+    /// Where the downloader keeps the archives of packages from `host`, a `hosted_dir_name`
+    pub fn host_download_path(&self, host: &str) -> PathBuf {
+        self.download_path().as_ref().join(host)
+    }
+
+    /// Where the downloader keeps the archive of a package version from `host`
+    pub fn archive_path(
+        &self,
+        host: &str,
+        name: &PackageName,
+        version: &PackageVersion,
+    ) -> PathBuf {
+        self.host_download_path(host)
+            .join(format!("{}-{}.tar.gz", name, version))
+    }
+
     fn hosted_path(&self, host: &str) -> PathBuf {
         self.root.join("hosted").join(host)
     }
@@ -48,7 +77,17 @@ impl PubCache {
         desc.url
             .host_str()
             .ok_or(PubCacheError::UnsupportedSource)
-            .map(|host| self.hosted_path(host).join(format!("{}-{}", name, version)))
+            .map(|host| self.hosted_package_path(host, name, version))
+    }
+
+    /// Where a package version from `host` is extracted
+    pub fn hosted_package_path(
+        &self,
+        host: &str,
+        name: &PackageName,
+        version: &PackageVersion,
+    ) -> PathBuf {
+        self.hosted_path(host).join(format!("{}-{}", name, version))
     }
 
     pub fn create_package_dir(
@@ -62,6 +101,25 @@ impl PubCache {
         Ok(path)
     }
 
+    /// The bare mirror of a git repository, named after the repository and a hash of its URL
+    pub fn git_mirror_path(&self, url: &Url) -> PathBuf {
+        let repo = url
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+            .unwrap_or("repo")
+            .trim_end_matches(".git");
+        let hash = hex::encode(sha2::Sha256::digest(url.as_str().as_bytes()));
+        self.root
+            .join("git")
+            .join("cache")
+            .join(format!("{}-{}", repo, hash))
+    }
+
+    /// Where a git package is checked out at a commit
+    pub fn git_checkout_path(&self, name: &PackageName, commit: &str) -> PathBuf {
+        self.root.join("git").join(format!("{}-{}", name, commit))
+    }
+
     fn get_hash_file_path(
         &self,
         host: &str,
@@ -0,0 +1,146 @@
+use crate::http::{HttpClient, HttpError};
+use crate::pubpackage::{PubPackageAdvisories, PubPackageListing};
+use crate::pubspeclock::PackageName;
+use crate::tokens::TokenStore;
+use std::io;
+use std::io::Read;
+use thiserror::Error;
+use url::Url;
+
+pub const PUB_DEV: &str = "https://pub.dev";
+
+#[derive(Error, Debug)]
+pub enum PubClientError {
+    #[error(transparent)]
+    HttpError(#[from] HttpError),
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Invalid URL: {0}")]
+    UrlParseError(#[from] url::ParseError),
+    #[error("Package not found: {name}")]
+    PackageNotFound { name: String },
+    #[error(
+        "Authentication to {hosted_url} failed with HTTP {status}. \
+         Add a token with `flutter-pub token add {hosted_url}`"
+    )]
+    AuthenticationFailed { hosted_url: Url, status: u16 },
+    #[error("Invalid package listing for {name}: {source}")]
+    InvalidListing {
+        name: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Builder for agents suitable for talking to pub repositories.
+///
+/// Archive URLs commonly redirect to blob storage on another host, so redirects are followed,
+/// but credentials are only forwarded while the redirect stays on the same host.
+pub fn agent_builder() -> ureq::AgentBuilder {
+    ureq::AgentBuilder::new()
+        .redirects(10)
+        .redirect_auth_headers(ureq::RedirectAuthHeaders::SameHost)
+        .user_agent(concat!("flutter-pub/", env!("CARGO_PKG_VERSION")))
+}
+
+pub fn default_agent() -> ureq::Agent {
+    agent_builder().build()
+}
+
+/// An agent that sends the matching token from `tokens` with every request
+pub fn authenticated_agent(tokens: TokenStore) -> ureq::Agent {
+    agent_builder().middleware(tokens).build()
+}
+
+/// Client for a repository implementing the Hosted Pub Repository v2 API
+#[derive(Clone)]
+pub struct PubClient {
+    hosted_url: Url,
+    http: HttpClient,
+}
+
+impl PubClient {
+    pub fn new(hosted_url: Url) -> Self {
+        Self::with_agent(hosted_url, default_agent())
+    }
+
+    pub fn with_agent(hosted_url: Url, http: impl Into<HttpClient>) -> Self {
+        Self {
+            hosted_url,
+            http: http.into(),
+        }
+    }
+
+    pub fn pub_dev() -> Self {
+        Self::new(Url::parse(PUB_DEV).expect("pub.dev is a valid URL"))
+    }
+
+    pub fn hosted_url(&self) -> &Url {
+        &self.hosted_url
+    }
+
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// The URL of the listing for a package, respecting any path in the hosted URL
+    pub fn listing_url(&self, name: &PackageName) -> Result<Url, PubClientError> {
+        let base = self.hosted_url.as_str().trim_end_matches('/');
+        Ok(Url::parse(&format!("{}/api/packages/{}", base, name))?)
+    }
+
+    pub fn listing(&self, name: &PackageName) -> Result<PubPackageListing, PubClientError> {
+        let body = self.fetch_listing(name)?;
+        PubPackageListing::from_json(&body).map_err(|e| PubClientError::InvalidListing {
+            name: name.to_string(),
+            source: e,
+        })
+    }
+
+    /// The listing exactly as the repository sent it, including any fields we don't model
+    pub fn raw_listing(&self, name: &PackageName) -> Result<serde_json::Value, PubClientError> {
+        let body = self.fetch_listing(name)?;
+        serde_json::from_str(&body).map_err(|e| PubClientError::InvalidListing {
+            name: name.to_string(),
+            source: e,
+        })
+    }
+
+    /// The security advisories affecting a package, in the OSV format pub.dev uses
+    pub fn advisories(&self, name: &PackageName) -> Result<PubPackageAdvisories, PubClientError> {
+        let url = Url::parse(&format!("{}/advisories", self.listing_url(name)?))?;
+        let body = self.fetch(&url, name)?;
+        serde_json::from_str(&body).map_err(|e| PubClientError::InvalidListing {
+            name: name.to_string(),
+            source: e,
+        })
+    }
+
+    fn fetch_listing(&self, name: &PackageName) -> Result<String, PubClientError> {
+        self.fetch(&self.listing_url(name)?, name)
+    }
+
+    fn fetch(&self, url: &Url, name: &PackageName) -> Result<String, PubClientError> {
+        let response = self
+            .http
+            .get(url)
+            .set("Accept", "application/vnd.pub.v2+json")
+            .call()
+            .map_err(|e| match e {
+                ureq::Error::Status(404, _) => PubClientError::PackageNotFound {
+                    name: name.to_string(),
+                },
+                ureq::Error::Status(status @ (401 | 403), _) => {
+                    PubClientError::AuthenticationFailed {
+                        hosted_url: self.hosted_url.clone(),
+                        status,
+                    }
+                }
+                e => self.http.error(url, e).into(),
+            })?;
+
+        let mut body = String::new();
+        response.into_reader().read_to_string(&mut body)?;
+        Ok(body)
+    }
+}
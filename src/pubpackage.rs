@@ -1,17 +1,102 @@
-use crate::pubspeclock::{PackageVersion, Sha256};
+use crate::pubspec::Pubspec;
+use crate::pubspeclock::{PackageName, PackageVersion, Sha256};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PubPackageVersion {
     pub version: PackageVersion,
+    /// Whether the publisher has retracted this version
+    #[serde(default)]
+    pub retracted: bool,
     pub archive_url: String,
-    pub archive_sha256: Sha256,
+    /// Left out by repositories that don't publish the hashes of their archives
+    pub archive_sha256: Option<Sha256>,
     pub published: DateTime<Utc>,
+    /// The pubspec of this version, as published. Kept as it came, so that one version with a
+    /// pubspec we can't parse doesn't spoil the whole listing.
+    pub pubspec: Option<serde_json::Value>,
 }
 
 impl PubPackageVersion {
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
         serde_json::from_str(json)
     }
+
+    /// The published pubspec, parsed
+    pub fn parse_pubspec(&self) -> Option<Result<Pubspec, serde_json::Error>> {
+        self.pubspec.clone().map(serde_json::from_value)
+    }
+}
+
+/// The response of `GET <hosted-url>/api/packages/<name>` in the Hosted Pub Repository v2 API
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PubPackageListing {
+    pub name: PackageName,
+    pub latest: PubPackageVersion,
+    pub versions: Vec<PubPackageVersion>,
+    /// Whether the package has been marked as discontinued
+    #[serde(default)]
+    pub is_discontinued: bool,
+    /// The package suggested as a replacement for a discontinued package
+    pub replaced_by: Option<PackageName>,
+    /// When the security advisories for this package last changed
+    pub advisories_updated: Option<DateTime<Utc>>,
+}
+
+impl PubPackageListing {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn version(&self, version: &PackageVersion) -> Option<&PubPackageVersion> {
+        self.versions.iter().find(|v| &v.version == version)
+    }
+
+    /// Versions that may be picked during resolution, i.e. those that are not retracted
+    pub fn available_versions(&self) -> impl Iterator<Item = &PubPackageVersion> {
+        self.versions.iter().filter(|v| !v.retracted)
+    }
+}
+
+/// The response of `GET <hosted-url>/api/packages/<name>/advisories`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PubPackageAdvisories {
+    pub advisories: Vec<Advisory>,
+    pub advisories_updated: Option<DateTime<Utc>>,
+}
+
+/// A security advisory in the OSV format
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Advisory {
+    pub id: String,
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub affected: Vec<AffectedPackage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AffectedPackage {
+    pub package: AffectedPackageName,
+    /// Every affected version, which pub.dev always lists
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AffectedPackageName {
+    pub ecosystem: String,
+    pub name: String,
+}
+
+impl Advisory {
+    pub fn affects(&self, name: &PackageName, version: &str) -> bool {
+        self.affected.iter().any(|affected| {
+            affected.package.ecosystem == "Pub"
+                && affected.package.name == name.as_ref()
+                && affected.versions.iter().any(|v| v == version)
+        })
+    }
 }
@@ -1,67 +1,172 @@
-
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
 use std::{fs, io};
-use std::path::PathBuf;
-use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A pubspec.yaml, with every field dart knows about, and anything else kept in `extra`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Pubspec {
     /// Package name
     pub name: String,
     /// Package description
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     /// Package version
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
     /// Package homepage
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub homepage: Option<String>,
     /// Package repository URL
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub repository: Option<String>,
+    /// Where to report issues
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue_tracker: Option<String>,
     /// Package documentation URL
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub documentation: Option<String>,
+    /// Where the package is published, or `none` to never publish it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publish_to: Option<String>,
+    /// URLs where users can sponsor the package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub funding: Option<Vec<String>>,
+    /// Files that look like they contain secrets, but don't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub false_secrets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshots: Option<Vec<Screenshot>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topics: Option<Vec<String>>,
+    /// Security advisories that pub shouldn't warn about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored_advisories: Option<Vec<String>>,
+    /// Scripts made available by `dart pub global activate`, by executable name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executables: Option<IndexMap<String, Option<String>>>,
+    /// The platforms the package supports, when it doesn't support all of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platforms: Option<IndexMap<String, Value>>,
     /// Package environment constraints
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub environment: Option<Environment>,
+    /// The packages of a workspace, relative to its root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<Vec<String>>,
+    /// `workspace` when the package is part of the workspace in a parent directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
     /// Package dependencies
-    #[serde(default)]
-    pub dependencies: HashMap<String, DependencySpec>,
+    #[serde(
+        default,
+        deserialize_with = "dependency_section",
+        skip_serializing_if = "IndexMap::is_empty"
+    )]
+    pub dependencies: IndexMap<String, DependencySpec>,
     /// Development dependencies
-    #[serde(default)]
-    pub dev_dependencies: HashMap<String, DependencySpec>,
+    #[serde(
+        default,
+        deserialize_with = "dependency_section",
+        skip_serializing_if = "IndexMap::is_empty"
+    )]
+    pub dev_dependencies: IndexMap<String, DependencySpec>,
     /// Dependencies that are not included in the app
-    #[serde(default)]
-    pub dependency_overrides: HashMap<String, DependencySpec>,
+    #[serde(
+        default,
+        deserialize_with = "dependency_section",
+        skip_serializing_if = "IndexMap::is_empty"
+    )]
+    pub dependency_overrides: IndexMap<String, DependencySpec>,
     /// Flutter-specific configuration
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub flutter: Option<FlutterConfig>,
+    /// Keys flutter-pub doesn't know about, in the order they were written
+    #[serde(flatten)]
+    pub extra: IndexMap<String, Value>,
+    /// The document as it was read, so that writing it back keeps keys in place
+    #[serde(skip)]
+    original: Value,
+}
+
+/// A pubspec_overrides.yaml, whose overrides replace those of the pubspec.yaml beside it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PubspecOverrides {
+    #[serde(
+        default,
+        deserialize_with = "dependency_section",
+        skip_serializing_if = "IndexMap::is_empty"
+    )]
+    pub dependency_overrides: IndexMap<String, DependencySpec>,
+}
+
+impl PubspecOverrides {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PubspecError> {
+        let path = path.as_ref().to_owned();
+        let contents = fs::read_to_string(&path).map_err(|e| PubspecError::IoError {
+            path: path.clone(),
+            source: e,
+        })?;
+        // dart accepts a file with nothing in it
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(&contents).map_err(|e| PubspecError::YamlError { path, source: e })
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("overrides are always valid YAML")
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Environment {
     pub sdk: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub flutter: Option<String>,
+    #[serde(flatten)]
+    pub extra: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Screenshot {
+    pub description: String,
+    pub path: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A dependency as written in a pubspec, in any of the forms dart accepts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(untagged)]
 pub enum DependencySpec {
+    /// `foo: ^1.0.0`, or `foo:` for any version
     Simple(String),
-    Detailed(DetailedDependency),
     Git(GitDependency),
     Path(PathDependency),
+    Sdk(SdkDependency),
+    /// A hosted package, with a version and/or the repository it is hosted in
+    Detailed(DetailedDependency),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DetailedDependency {
-    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub hosted: Option<HostedDependency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GitDependency {
     pub git: GitRepo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A git repository, written as `git: <url>` or as a map
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct GitRepo {
     pub url: String,
     #[serde(rename = "ref")]
@@ -69,44 +174,336 @@ pub struct GitRepo {
     pub path: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PathDependency {
     pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdkDependency {
+    pub sdk: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// The repository of a hosted dependency, written as `hosted: <url>` or as a map
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct HostedDependency {
-    pub name: String,
+    /// The package's name in the repository, which older pubspecs had to repeat
+    pub name: Option<String>,
     pub url: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Serialize for GitRepo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = Mapping::new();
+        map.insert("url".into(), self.url.as_str().into());
+        if let Some(ref_) = &self.ref_ {
+            map.insert("ref".into(), ref_.as_str().into());
+        }
+        if let Some(path) = &self.path {
+            map.insert("path".into(), path.as_str().into());
+        }
+        match map.len() {
+            1 => serializer.serialize_str(&self.url),
+            _ => map.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for HostedDependency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.name {
+            None => serializer.serialize_str(&self.url),
+            Some(name) => {
+                let mut map = Mapping::new();
+                map.insert("name".into(), name.as_str().into());
+                map.insert("url".into(), self.url.as_str().into());
+                map.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DependencySpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        DependencySpec::from_value(&value).map_err(de::Error::custom)
+    }
+}
+
+/// `value` with the keys of every map in the same order as in `original`
+fn ordered_like(value: Value, original: &Value) -> Value {
+    match (value, original) {
+        (Value::Mapping(map), Value::Mapping(original)) => {
+            let position = |key: &Value| original.keys().position(|k| k == key);
+            let mut entries: Vec<_> = map.into_iter().collect();
+            // A stable sort leaves keys that weren't in the original in their usual order
+            entries.sort_by_key(|(key, _)| position(key).unwrap_or(usize::MAX));
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match original.get(&key) {
+                        Some(original) => ordered_like(value, original),
+                        None => value,
+                    };
+                    (key, value)
+                })
+                .collect::<Mapping>()
+                .into()
+        }
+        (Value::Sequence(items), Value::Sequence(original)) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| match original.get(i) {
+                Some(original) => ordered_like(item, original),
+                None => item,
+            })
+            .collect::<Vec<_>>()
+            .into(),
+        (value, _) => value,
+    }
+}
+
+/// A map of dependencies, whose errors say which dependency is wrong
+fn dependency_section<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<IndexMap<String, DependencySpec>, D::Error> {
+    let section = Option::<IndexMap<String, Value>>::deserialize(deserializer)?;
+    section
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| {
+            DependencySpec::from_value(&value)
+                .map(|spec| (name.clone(), spec))
+                .map_err(|e| de::Error::custom(format!("invalid dependency `{}`: {}", name, e)))
+        })
+        .collect()
+}
+
+impl DependencySpec {
+    /// The version constraint of a hosted dependency, which `upgrade` may rewrite
+    pub fn hosted_constraint(&self) -> Option<&str> {
+        match self {
+            DependencySpec::Simple(constraint) => Some(constraint),
+            DependencySpec::Detailed(detailed) => {
+                Some(detailed.version.as_deref().unwrap_or("any"))
+            }
+            _ => None,
+        }
+    }
+
+    /// The same dependency with another version constraint
+    pub fn with_constraint(&self, constraint: impl Into<String>) -> Self {
+        let constraint = constraint.into();
+        match self {
+            DependencySpec::Simple(_) => DependencySpec::Simple(constraint),
+            DependencySpec::Detailed(detailed) => DependencySpec::Detailed(DetailedDependency {
+                version: Some(constraint),
+                ..detailed.clone()
+            }),
+            DependencySpec::Git(git) => DependencySpec::Git(GitDependency {
+                version: Some(constraint),
+                ..git.clone()
+            }),
+            DependencySpec::Path(path) => DependencySpec::Path(PathDependency {
+                version: Some(constraint),
+                ..path.clone()
+            }),
+            DependencySpec::Sdk(sdk) => DependencySpec::Sdk(SdkDependency {
+                version: Some(constraint),
+                ..sdk.clone()
+            }),
+        }
+    }
+
+    /// Interprets the YAML value of one dependency, with an error naming the key that is wrong
+    pub fn from_value(value: &Value) -> Result<Self, String> {
+        let map = match value {
+            Value::Null => return Ok(DependencySpec::Simple("any".to_string())),
+            Value::String(constraint) => return Ok(DependencySpec::Simple(constraint.clone())),
+            Value::Mapping(map) => map,
+            _ => {
+                return Err(
+                    "a dependency must be a version constraint or a map with a source".to_string(),
+                );
+            }
+        };
+
+        let mut keys = Vec::new();
+        for key in map.keys() {
+            match key.as_str() {
+                Some(k @ ("version" | "hosted" | "git" | "path" | "sdk")) => keys.push(k),
+                Some(other) => {
+                    return Err(format!(
+                        "unknown key `{}`, expected `version`, `hosted`, `git`, `path` or `sdk`",
+                        other
+                    ));
+                }
+                None => return Err("dependency keys must be strings".to_string()),
+            }
+        }
+
+        let sources: Vec<_> = keys.iter().filter(|k| **k != "version").collect();
+        if let [first, second, ..] = sources.as_slice() {
+            return Err(format!(
+                "a dependency can't have both `{}` and `{}`",
+                first, second
+            ));
+        }
+
+        let version = optional_string(map, "", "version")?;
+        let spec = match sources.first().map(|k| **k) {
+            Some("git") => DependencySpec::Git(GitDependency {
+                git: git_repo(&map["git"])?,
+                version,
+            }),
+            Some("path") => DependencySpec::Path(PathDependency {
+                path: required_string(map, "", "path")?,
+                version,
+            }),
+            Some("sdk") => DependencySpec::Sdk(SdkDependency {
+                sdk: required_string(map, "", "sdk")?,
+                version,
+            }),
+            Some("hosted") => DependencySpec::Detailed(DetailedDependency {
+                hosted: Some(hosted(&map["hosted"])?),
+                version,
+            }),
+            _ => DependencySpec::Detailed(DetailedDependency {
+                hosted: None,
+                version,
+            }),
+        };
+        Ok(spec)
+    }
+}
+
+fn git_repo(value: &Value) -> Result<GitRepo, String> {
+    let map = match value {
+        Value::String(url) => {
+            return Ok(GitRepo {
+                url: url.clone(),
+                ref_: None,
+                path: None,
+            });
+        }
+        Value::Mapping(map) => map,
+        _ => return Err("`git` must be a URL, or a map with `url`, `ref` and `path`".to_string()),
+    };
+    if let Some(key) = unknown_key(map, &["url", "ref", "path"]) {
+        return Err(format!(
+            "unknown key `git.{}`, expected `url`, `ref` or `path`",
+            key
+        ));
+    }
+    Ok(GitRepo {
+        url: required_string(map, "git", "url")?,
+        ref_: optional_string(map, "git", "ref")?,
+        path: optional_string(map, "git", "path")?,
+    })
+}
+
+fn hosted(value: &Value) -> Result<HostedDependency, String> {
+    let map = match value {
+        Value::String(url) => {
+            return Ok(HostedDependency {
+                name: None,
+                url: url.clone(),
+            });
+        }
+        Value::Mapping(map) => map,
+        _ => return Err("`hosted` must be a URL, or a map with `url` and `name`".to_string()),
+    };
+    if let Some(key) = unknown_key(map, &["url", "name"]) {
+        return Err(format!(
+            "unknown key `hosted.{}`, expected `url` or `name`",
+            key
+        ));
+    }
+    Ok(HostedDependency {
+        name: optional_string(map, "hosted", "name")?,
+        url: required_string(map, "hosted", "url")?,
+    })
+}
+
+fn unknown_key<'a>(map: &'a Mapping, known: &[&str]) -> Option<&'a str> {
+    map.keys()
+        .map(|k| k.as_str().unwrap_or("?"))
+        .find(|k| !known.contains(k))
+}
+
+/// A string in `map`, where `parent` is the key of the map itself, if it is nested
+fn required_string(map: &Mapping, parent: &str, key: &str) -> Result<String, String> {
+    optional_string(map, parent, key)?
+        .ok_or_else(|| format!("`{}` is missing", qualified(parent, key)))
+}
+
+fn optional_string(map: &Mapping, parent: &str, key: &str) -> Result<Option<String>, String> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}` must be a string", qualified(parent, key))),
+    }
+}
+
+fn qualified(parent: &str, key: &str) -> String {
+    match parent.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", parent, key),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlutterConfig {
     /// Flutter SDK version constraint
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sdk: Option<String>,
+    /// Uses-material-design flag
+    #[serde(
+        rename = "uses-material-design",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub uses_material_design: Option<bool>,
     /// Assets to include in the app
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub assets: Option<Vec<String>>,
     /// Fonts to include in the app
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fonts: Option<Vec<FontFamily>>,
-    /// Uses-material-design flag
-    #[serde(rename = "uses-material-design")]
-    pub uses_material_design: Option<bool>,
+    /// Everything else, such as `generate` and `plugin`
+    #[serde(flatten)]
+    pub extra: IndexMap<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FontFamily {
     pub family: String,
     pub fonts: Vec<FontFile>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FontFile {
     pub asset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub weight: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub style: Option<String>,
 }
 
 impl Pubspec {
+    /// Whether this is the root of a pub workspace
+    pub fn is_workspace(&self) -> bool {
+        self.workspace.is_some()
+    }
+
+    /// Whether this is resolved as part of the workspace in a parent directory
+    pub fn is_workspace_member(&self) -> bool {
+        self.resolution.as_deref() == Some("workspace")
+    }
+
     pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, PubspecError> {
         let path = path.as_ref().to_owned();
         let contents = fs::read_to_string(&path).map_err(|e| PubspecError::IoError {
@@ -114,10 +511,25 @@ impl Pubspec {
             source: e,
         })?;
 
-        serde_yaml::from_str(&contents).map_err(|e| PubspecError::YamlError {
-            path,
-            source: e,
-        })
+        Self::from_yaml(&contents).map_err(|e| PubspecError::YamlError { path, source: e })
+    }
+
+    pub fn from_yaml(contents: &str) -> Result<Self, serde_yaml::Error> {
+        let mut pubspec: Pubspec = serde_yaml::from_str(contents)?;
+        pubspec.original = serde_yaml::from_str(contents)?;
+        Ok(pubspec)
+    }
+
+    /// The pubspec as YAML, with keys in the order they were read, and new ones last
+    pub fn to_yaml(&self) -> String {
+        let document = serde_yaml::to_value(self).expect("a pubspec is always valid YAML");
+        serde_yaml::to_string(&ordered_like(document, &self.original))
+            .expect("a pubspec can always be written")
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PubspecError> {
+        let path = path.as_ref().to_owned();
+        fs::write(&path, self.to_yaml()).map_err(|e| PubspecError::IoError { path, source: e })
     }
 }
 
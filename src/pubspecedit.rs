@@ -0,0 +1,417 @@
+use crate::pubspec::{DependencySpec, Pubspec};
+use crate::version::VersionConstraint;
+use indexmap::IndexMap;
+use serde_yaml::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EditError {
+    #[error("IO error at {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Invalid dependency `{arg}`: {message}")]
+    InvalidRequest { arg: String, message: String },
+    #[error("`{0}` in pubspec.yaml isn't written as an indented map, so can't be edited")]
+    UnsupportedLayout(String),
+    #[error("The edited pubspec.yaml is invalid: {0}")]
+    InvalidResult(#[source] serde_yaml::Error),
+}
+
+/// The sections of a pubspec that list dependencies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Section {
+    Dependencies,
+    DevDependencies,
+    DependencyOverrides,
+}
+
+impl Section {
+    pub const ALL: [Section; 3] = [
+        Section::Dependencies,
+        Section::DevDependencies,
+        Section::DependencyOverrides,
+    ];
+
+    /// The dependencies `pubspec` lists in this section
+    pub fn of<'a>(&self, pubspec: &'a Pubspec) -> &'a IndexMap<String, DependencySpec> {
+        match self {
+            Section::Dependencies => &pubspec.dependencies,
+            Section::DevDependencies => &pubspec.dev_dependencies,
+            Section::DependencyOverrides => &pubspec.dependency_overrides,
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Section::Dependencies => "dependencies",
+            Section::DevDependencies => "dev_dependencies",
+            Section::DependencyOverrides => "dependency_overrides",
+        }
+    }
+}
+
+/// A dependency to add, written like dart's `[dev:|override:]name[:descriptor]`, where the
+/// descriptor is a version constraint or a YAML map such as `{git: https://...}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyRequest {
+    pub section: Section,
+    pub name: String,
+    /// What to write in the pubspec, or `None` to choose a constraint from the resolution
+    pub spec: Option<DependencySpec>,
+}
+
+impl DependencyRequest {
+    pub fn parse(arg: &str) -> Result<Self, EditError> {
+        let invalid = |message: String| EditError::InvalidRequest {
+            arg: arg.to_string(),
+            message,
+        };
+        let (section, rest) = match arg.split_once(':') {
+            Some(("dev", rest)) => (Section::DevDependencies, rest),
+            Some(("override", rest)) => (Section::DependencyOverrides, rest),
+            _ => (Section::Dependencies, arg),
+        };
+        let (name, descriptor) = match rest.split_once(':') {
+            Some((name, descriptor)) => (name, Some(descriptor.trim())),
+            None => (rest, None),
+        };
+
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            return Err(invalid(format!("`{}` is not a package name", name)));
+        }
+
+        let spec = match descriptor {
+            None => None,
+            Some(descriptor) if descriptor.starts_with('{') => {
+                let value: Value =
+                    serde_yaml::from_str(descriptor).map_err(|e| invalid(e.to_string()))?;
+                Some(DependencySpec::from_value(&value).map_err(invalid)?)
+            }
+            Some(constraint) => {
+                VersionConstraint::parse(constraint).map_err(|e| invalid(e.to_string()))?;
+                Some(DependencySpec::Simple(constraint.to_string()))
+            }
+        };
+
+        Ok(DependencyRequest {
+            section,
+            name: name.to_string(),
+            spec,
+        })
+    }
+}
+
+/// Edits the dependencies in the text of a pubspec.yaml, touching only the lines of the
+/// entries that change, so comments, blank lines, quoting and key order all survive
+#[derive(Debug, Clone)]
+pub struct PubspecEditor {
+    lines: Vec<String>,
+    newline: &'static str,
+}
+
+/// The lines of one dependency, `end` being exclusive
+struct Entry {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+/// A dependency section, found by the line of its key
+struct Block {
+    header: usize,
+    /// Written as `section: {}`, which has to become a block before adding to it
+    empty_flow: bool,
+    indent: Option<usize>,
+    entries: Vec<Entry>,
+}
+
+impl PubspecEditor {
+    pub fn new(contents: &str) -> Self {
+        let newline = match contents.contains("\r\n") {
+            true => "\r\n",
+            false => "\n",
+        };
+        PubspecEditor {
+            lines: contents.lines().map(String::from).collect(),
+            newline,
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EditError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| EditError::IoError {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        Ok(Self::new(&contents))
+    }
+
+    pub fn contents(&self) -> String {
+        let mut contents = self.lines.join(self.newline);
+        contents.push_str(self.newline);
+        contents
+    }
+
+    /// The pubspec as currently edited
+    pub fn pubspec(&self) -> Result<Pubspec, EditError> {
+        Pubspec::from_yaml(&self.contents()).map_err(EditError::InvalidResult)
+    }
+
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), EditError> {
+        let path = path.as_ref();
+        fs::write(path, self.contents()).map_err(|e| EditError::IoError {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    }
+
+    /// Whether `section` lists `name`
+    pub fn contains(&self, section: Section, name: &str) -> Result<bool, EditError> {
+        Ok(self
+            .block(section)?
+            .is_some_and(|block| block.entries.iter().any(|e| e.name == name)))
+    }
+
+    /// Adds `name` to `section`, or replaces what was there. New entries go in alphabetical
+    /// order when the section already is, and at its end otherwise.
+    pub fn set_dependency(
+        &mut self,
+        section: Section,
+        name: &str,
+        spec: &DependencySpec,
+    ) -> Result<(), EditError> {
+        let unit = self.indent_unit();
+        let Some(block) = self.block(section)? else {
+            if self
+                .lines
+                .last()
+                .is_some_and(|line| !line.trim().is_empty())
+            {
+                self.lines.push(String::new());
+            }
+            self.lines.push(format!("{}:", section.key()));
+            self.lines.extend(render(name, spec, unit, unit));
+            return Ok(());
+        };
+
+        if let Some(entry) = block.entries.iter().find(|e| e.name == name) {
+            let indent = indent_of(&self.lines[entry.start]);
+            let mut lines = render(name, spec, indent, unit);
+            // Keep a comment on a one line entry, e.g. `foo: ^1.0.0 # pinned for reasons`
+            if entry.end - entry.start == 1
+                && lines.len() == 1
+                && let Some(comment) = comment_of(&self.lines[entry.start])
+            {
+                lines[0] = format!("{} {}", lines[0], comment);
+            }
+            self.lines.splice(entry.start..entry.end, lines);
+            return Ok(());
+        }
+
+        let sorted = block.entries.windows(2).all(|w| w[0].name <= w[1].name);
+        let at = match sorted {
+            true => block
+                .entries
+                .iter()
+                .take_while(|e| e.name.as_str() < name)
+                .last(),
+            false => block.entries.last(),
+        }
+        .map(|e| e.end)
+        .unwrap_or(block.header + 1);
+        let indent = block.indent.unwrap_or(unit);
+        self.lines.splice(at..at, render(name, spec, indent, unit));
+        if block.empty_flow {
+            self.lines[block.header] = format!("{}:", section.key());
+        }
+        Ok(())
+    }
+
+    /// Sets the version constraint of `name` in every section that lists it as a hosted
+    /// dependency, returning the sections that changed
+    pub fn align(&mut self, name: &str, constraint: &str) -> Result<Vec<Section>, EditError> {
+        let pubspec = self.pubspec()?;
+        let mut changed = Vec::new();
+        for section in Section::ALL {
+            let Some(spec) = section.of(&pubspec).get(name) else {
+                continue;
+            };
+            if spec.hosted_constraint().is_some_and(|c| c != constraint) {
+                if !self.set_version_line(section, name, constraint)? {
+                    self.set_dependency(section, name, &spec.with_constraint(constraint))?;
+                }
+                changed.push(section);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Replaces the value of the `version:` line of a dependency written as a map, keeping the
+    /// rest of it as it is. False when it has no such line.
+    fn set_version_line(
+        &mut self,
+        section: Section,
+        name: &str,
+        constraint: &str,
+    ) -> Result<bool, EditError> {
+        let Some(block) = self.block(section)? else {
+            return Ok(false);
+        };
+        let Some(entry) = block.entries.iter().find(|e| e.name == name) else {
+            return Ok(false);
+        };
+        let Some(at) = (entry.start + 1..entry.end)
+            .find(|&i| is_content(&self.lines[i]) && key_of(&self.lines[i]) == Some("version"))
+        else {
+            return Ok(false);
+        };
+        let value = serde_yaml::to_string(constraint).expect("a string is always valid YAML");
+        let line = &self.lines[at];
+        let mut replaced = format!(
+            "{}version: {}",
+            " ".repeat(indent_of(line)),
+            value.trim_end()
+        );
+        if let Some(comment) = comment_of(line) {
+            replaced = format!("{} {}", replaced, comment);
+        }
+        self.lines[at] = replaced;
+        Ok(true)
+    }
+
+    /// Removes `name` from `section`, and the section too if nothing is left in it
+    pub fn remove_dependency(&mut self, section: Section, name: &str) -> Result<bool, EditError> {
+        let Some(block) = self.block(section)? else {
+            return Ok(false);
+        };
+        let Some(entry) = block.entries.iter().find(|e| e.name == name) else {
+            return Ok(false);
+        };
+        let start = match block.entries.len() {
+            1 => block.header,
+            _ => entry.start,
+        };
+        self.lines.drain(start..entry.end);
+
+        // Don't leave a double blank line, or blank lines at the end, where it was
+        let mut at = start;
+        while at > 0
+            && self.lines[at - 1].trim().is_empty()
+            && self.lines.get(at).is_none_or(|line| line.trim().is_empty())
+        {
+            self.lines.remove(at - 1);
+            at -= 1;
+        }
+        Ok(true)
+    }
+
+    fn block(&self, section: Section) -> Result<Option<Block>, EditError> {
+        let Some(header) = self
+            .lines
+            .iter()
+            .position(|line| indent_of(line) == 0 && key_of(line) == Some(section.key()))
+        else {
+            return Ok(None);
+        };
+        let value = value_of(&self.lines[header]);
+        let empty_flow = match value {
+            "" => false,
+            "{}" => true,
+            _ => return Err(EditError::UnsupportedLayout(section.key().to_string())),
+        };
+
+        let mut block = Block {
+            header,
+            empty_flow,
+            indent: None,
+            entries: Vec::new(),
+        };
+        for (i, line) in self.lines.iter().enumerate().skip(header + 1) {
+            if !is_content(line) {
+                continue;
+            }
+            let indent = indent_of(line);
+            if indent == 0 {
+                break;
+            }
+            let child = *block.indent.get_or_insert(indent);
+            if indent == child {
+                let name = key_of(line).unwrap_or_default().to_string();
+                block.entries.push(Entry {
+                    name,
+                    start: i,
+                    end: i + 1,
+                });
+            } else if let Some(entry) = block.entries.last_mut() {
+                entry.end = i + 1;
+            }
+        }
+        Ok(Some(block))
+    }
+
+    /// How far the file indents nested maps, 2 spaces unless it says otherwise
+    fn indent_unit(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|line| is_content(line))
+            .map(|line| indent_of(line))
+            .find(|indent| *indent > 0)
+            .unwrap_or(2)
+    }
+}
+
+/// The lines of a dependency entry, indented by `indent`, nesting by `unit`
+fn render(name: &str, spec: &DependencySpec, indent: usize, unit: usize) -> Vec<String> {
+    let pad = " ".repeat(indent);
+    let value = serde_yaml::to_value(spec).expect("a dependency is always valid YAML");
+    let yaml = serde_yaml::to_string(&value).expect("a dependency can always be written");
+    match value {
+        Value::Mapping(_) => std::iter::once(format!("{}{}:", pad, name))
+            .chain(yaml.lines().map(|line| {
+                // serde_yaml nests by 2 spaces
+                let nested = indent_of(line) / 2 * unit;
+                format!("{}{}{}", pad, " ".repeat(unit + nested), line.trim_start())
+            }))
+            .collect(),
+        _ => vec![format!("{}{}: {}", pad, name, yaml.trim_end())],
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Whether a line holds anything other than whitespace or a comment
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// The key of a `key: value` line, without any quotes
+fn key_of(line: &str) -> Option<&str> {
+    let (key, _) = line.trim().split_once(':')?;
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// What follows the key of a line, without any comment
+fn value_of(line: &str) -> &str {
+    let value = line.split_once(':').map(|(_, v)| v).unwrap_or_default();
+    match value.find('#') {
+        Some(comment) => value[..comment].trim(),
+        None => value.trim(),
+    }
+}
+
+fn comment_of(line: &str) -> Option<&str> {
+    line.find(" #").map(|i| line[i + 1..].trim_end())
+}
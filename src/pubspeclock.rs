@@ -5,10 +5,9 @@ use std::fs;
 use std::path::Path;
 use url::Url;
 
+use std::io;
 use std::path::PathBuf;
 use thiserror::Error;
-use std::io;
-
 
 use crate::stringy;
 
@@ -37,23 +36,35 @@ pub struct PackageSpec {
 }
 
 #[derive(Debug, Display, Clone, Serialize, Deserialize)]
-#[display("{} {} {}", name, url, sha256)]
+#[display("{} {}", name, url)]
 pub struct HostedPackage {
     pub name: PackageName,
     #[serde(with = "url_serde")]
     pub url: Url,
-    pub sha256: Sha256,
+    /// The hash of the archive, which locks from repositories that don't publish one lack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<Sha256>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GitPackage {
     #[serde(with = "url_serde")]
     pub url: Url,
     #[serde(rename = "ref")]
     pub ref_: Option<String>,
+    /// The commit `ref` pointed at when the lock was written
+    #[serde(rename = "resolved-ref")]
+    pub resolved_ref: Option<String>,
     pub path: Option<String>,
 }
 
+impl GitPackage {
+    pub fn with_resolved_ref(mut self, commit: impl Into<String>) -> Self {
+        self.resolved_ref = Some(commit.into());
+        self
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PathPackage {
     pub path: String,
@@ -78,7 +89,11 @@ mod url_serde {
     where
         S: Serializer,
     {
-        serializer.serialize_str(url.as_str())
+        // Dart writes hosts without the trailing slash, e.g. "https://pub.dev"
+        match url.path() {
+            "/" => serializer.serialize_str(url.as_str().trim_end_matches('/')),
+            _ => serializer.serialize_str(url.as_str()),
+        }
     }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Url, D::Error>
@@ -98,13 +113,66 @@ impl PubspecLock {
             source: e,
         })?;
 
-        serde_yaml::from_str(&contents).map_err(|e| PubspecLockError::YamlError {
-            path,
-            source: e,
-        })
+        serde_yaml::from_str(&contents).map_err(|e| PubspecLockError::YamlError { path, source: e })
+    }
+
+    /// The lock as dart writes it, with sorted keys and strings quoted unless they're plain words
+    pub fn to_yaml(&self) -> String {
+        let value = serde_json::to_value(self).expect("lockfiles can always be serialized");
+        let mut out = String::from(
+            "# Generated by pub\n# See https://dart.dev/tools/pub/glossary#lockfile\n",
+        );
+        write_yaml(&value, 0, &mut out);
+        out
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), PubspecLockError> {
+        let path = path.as_ref().to_owned();
+        fs::write(&path, self.to_yaml()).map_err(|e| PubspecLockError::IoError { path, source: e })
     }
 }
 
+fn write_yaml(value: &serde_json::Value, indent: usize, out: &mut String) {
+    let serde_json::Value::Object(map) = value else {
+        return;
+    };
+    let mut entries: Vec<_> = map.iter().filter(|(_, v)| !v.is_null()).collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (key, value) in entries {
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&yaml_scalar(key));
+        out.push(':');
+        match value {
+            serde_json::Value::Object(map) if map.is_empty() => out.push_str(" {}\n"),
+            serde_json::Value::Object(_) => {
+                out.push('\n');
+                write_yaml(value, indent + 2, out);
+            }
+            serde_json::Value::String(s) => {
+                out.push(' ');
+                out.push_str(&yaml_scalar(s));
+                out.push('\n');
+            }
+            other => {
+                out.push(' ');
+                out.push_str(&other.to_string());
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Quotes a string the way dart does, which is unless it looks like an identifier
+fn yaml_scalar(s: &str) -> String {
+    let plain = s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '-')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    match plain {
+        true => s.to_string(),
+        false => serde_json::to_string(s).expect("strings can always be serialized"),
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum PubspecLockError {
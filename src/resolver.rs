@@ -0,0 +1,1334 @@
+use crate::git::{GitCache, GitError};
+use crate::http::HttpClient;
+use crate::pubclient::{PubClient, PubClientError};
+use crate::pubpackage::Advisory;
+use crate::pubspec::{DependencySpec, Pubspec, PubspecError};
+use crate::pubspeclock::{GitPackage, PackageDescription, PackageName, PubspecLock, Sha256};
+use crate::sdk::{SdkConstraints, SdkError, SdkIncompatibility, SdkPaths, SdkVersions};
+use crate::version::{VersionConstraint, VersionError, parse_version};
+use indexmap::IndexMap;
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum ResolveError {
+    #[error(transparent)]
+    VersionError(#[from] VersionError),
+    #[error("Failed to get the versions of {name} from {url}: {source}")]
+    ListingError {
+        name: PackageName,
+        url: Url,
+        #[source]
+        source: Box<PubClientError>,
+    },
+    #[error(transparent)]
+    GitError(Box<GitError>),
+    #[error(transparent)]
+    PubspecError(#[from] PubspecError),
+    #[error(transparent)]
+    SdkError(#[from] SdkError),
+    #[error("Path dependency {name} points at {path}, which doesn't exist")]
+    MissingPath { name: PackageName, path: PathBuf },
+    #[error("Invalid dependency {name}: {reason}")]
+    InvalidDependency { name: PackageName, reason: String },
+    #[error("Expected the package at {path} to be named {expected}, but it is named {found}")]
+    NameMismatch {
+        expected: PackageName,
+        found: String,
+        path: PathBuf,
+    },
+    #[error("{name} is depended on from different sources: {first} and {second}")]
+    SourceConflict {
+        name: PackageName,
+        first: String,
+        second: String,
+    },
+    #[error("Could not find a version of {name} that satisfies {}", requirements.join(", "))]
+    NoVersion {
+        name: PackageName,
+        requirements: Vec<String>,
+    },
+    #[error("{package} {version} {reason}")]
+    IncompatibleSdk {
+        package: PackageName,
+        version: Version,
+        reason: Box<SdkIncompatibility>,
+    },
+    #[error("{name} is the name of both {} and {}", first.display(), second.display())]
+    DuplicateRoot {
+        name: PackageName,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("{name} is overridden differently: {first} and {second}")]
+    ConflictingOverrides {
+        name: PackageName,
+        first: String,
+        second: String,
+    },
+    #[error("Gave up resolving after trying {0} combinations of versions")]
+    TooComplex(usize),
+}
+
+impl From<GitError> for ResolveError {
+    fn from(e: GitError) -> Self {
+        ResolveError::GitError(Box::new(e))
+    }
+}
+
+/// Where a dependency comes from, as declared in a pubspec
+#[derive(Debug, Clone)]
+pub enum PackageSource {
+    Hosted(Url),
+    /// A local package, with its canonical path and whether it was declared as a relative path
+    Path {
+        path: PathBuf,
+        relative: bool,
+    },
+    Git(GitPackage),
+    Sdk(String),
+}
+
+impl PackageSource {
+    /// Whether two declarations refer to the same package source
+    pub fn same_as(&self, other: &PackageSource) -> bool {
+        match (self, other) {
+            (PackageSource::Hosted(a), PackageSource::Hosted(b)) => same_url(a, b),
+            (PackageSource::Path { path: a, .. }, PackageSource::Path { path: b, .. }) => a == b,
+            (PackageSource::Git(a), PackageSource::Git(b)) => {
+                same_url(&a.url, &b.url) && git_ref(a) == git_ref(b) && git_path(a) == git_path(b)
+            }
+            (PackageSource::Sdk(a), PackageSource::Sdk(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PackageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageSource::Hosted(url) => write!(f, "hosted {}", url),
+            PackageSource::Path { path, .. } => write!(f, "path {}", path.display()),
+            PackageSource::Git(git) => write!(f, "git {}", git.url),
+            PackageSource::Sdk(sdk) => write!(f, "sdk {}", sdk),
+        }
+    }
+}
+
+fn same_url(a: &Url, b: &Url) -> bool {
+    a.as_str().trim_end_matches('/') == b.as_str().trim_end_matches('/')
+}
+
+pub(crate) fn git_ref(git: &GitPackage) -> &str {
+    git.ref_.as_deref().unwrap_or("HEAD")
+}
+
+pub(crate) fn git_path(git: &GitPackage) -> &str {
+    git.path.as_deref().unwrap_or(".")
+}
+
+/// A dependency of one package on another
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub name: PackageName,
+    pub source: PackageSource,
+    pub constraint: VersionConstraint,
+}
+
+impl Dependency {
+    /// Interprets a dependency declared in the pubspec in `dir`, with hosted packages coming
+    /// from `default_url` unless they say otherwise
+    pub fn from_spec(
+        name: &str,
+        spec: &DependencySpec,
+        dir: &Path,
+        default_url: &Url,
+    ) -> Result<Self, ResolveError> {
+        let name = PackageName::new(name);
+        let invalid = |reason: String| ResolveError::InvalidDependency {
+            name: name.clone(),
+            reason,
+        };
+
+        let (source, constraint) = match spec {
+            DependencySpec::Simple(constraint) => (
+                PackageSource::Hosted(default_url.clone()),
+                VersionConstraint::parse(constraint)?,
+            ),
+            DependencySpec::Detailed(detailed) => {
+                let url = match &detailed.hosted {
+                    Some(hosted) => Url::parse(&hosted.url)
+                        .map_err(|e| invalid(format!("{}: {}", hosted.url, e)))?,
+                    None => default_url.clone(),
+                };
+                (
+                    PackageSource::Hosted(url),
+                    optional_constraint(&detailed.version)?,
+                )
+            }
+            DependencySpec::Path(spec_path) => {
+                let declared = Path::new(&spec_path.path);
+                let path =
+                    dir.join(declared)
+                        .canonicalize()
+                        .map_err(|_| ResolveError::MissingPath {
+                            name: name.clone(),
+                            path: dir.join(declared),
+                        })?;
+                let relative = declared.is_relative();
+                (
+                    PackageSource::Path { path, relative },
+                    optional_constraint(&spec_path.version)?,
+                )
+            }
+            DependencySpec::Git(git) => {
+                let url = Url::parse(&git.git.url)
+                    .or_else(|_| {
+                        dir.join(&git.git.url)
+                            .canonicalize()
+                            .ok()
+                            .and_then(|path| Url::from_directory_path(path).ok())
+                            .ok_or(())
+                    })
+                    .map_err(|_| invalid(format!("invalid git URL {}", git.git.url)))?;
+                let package = GitPackage {
+                    url,
+                    ref_: git.git.ref_.clone(),
+                    resolved_ref: None,
+                    path: git.git.path.clone(),
+                };
+                (
+                    PackageSource::Git(package),
+                    optional_constraint(&git.version)?,
+                )
+            }
+            DependencySpec::Sdk(sdk) => (
+                PackageSource::Sdk(sdk.sdk.clone()),
+                optional_constraint(&sdk.version)?,
+            ),
+        };
+
+        Ok(Dependency {
+            name,
+            source,
+            constraint,
+        })
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            PackageSource::Hosted(_) => write!(f, "{} {}", self.name, self.constraint),
+            source => write!(f, "{} from {}", self.name, source),
+        }
+    }
+}
+
+fn optional_constraint(constraint: &Option<String>) -> Result<VersionConstraint, ResolveError> {
+    match constraint {
+        Some(constraint) => Ok(VersionConstraint::parse(constraint)?),
+        None => Ok(VersionConstraint::any()),
+    }
+}
+
+/// The dependencies in one section of a pubspec, sorted by name
+pub fn dependencies_in(
+    section: &IndexMap<String, DependencySpec>,
+    dir: &Path,
+    default_url: &Url,
+) -> Result<Vec<Dependency>, ResolveError> {
+    let mut names: Vec<_> = section.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| Dependency::from_spec(name, &section[name], dir, default_url))
+        .collect()
+}
+
+/// The language version implied by an `environment.sdk` constraint, e.g. `3.2` for `^3.2.0`
+pub fn language_version(sdk: Option<&str>) -> Option<String> {
+    let constraint = VersionConstraint::parse(sdk?).ok()?;
+    let min = constraint.min?.version;
+    Some(format!("{}.{}", min.major, min.minor))
+}
+
+/// The package being resolved, i.e. the project whose pubspec.yaml we're reading
+#[derive(Debug, Clone)]
+pub struct RootPackage {
+    pub name: PackageName,
+    pub dir: PathBuf,
+    pub version: Version,
+    pub dependencies: Vec<Dependency>,
+    pub dev_dependencies: Vec<Dependency>,
+    pub overrides: Vec<Dependency>,
+    pub language_version: Option<String>,
+    pub sdk_constraints: SdkConstraints,
+}
+
+impl RootPackage {
+    pub fn from_pubspec(
+        pubspec: &Pubspec,
+        dir: &Path,
+        default_url: &Url,
+    ) -> Result<Self, ResolveError> {
+        let dir = dir.canonicalize().map_err(|_| ResolveError::MissingPath {
+            name: PackageName::new(&pubspec.name),
+            path: dir.to_path_buf(),
+        })?;
+
+        Ok(RootPackage {
+            name: PackageName::new(&pubspec.name),
+            version: package_version(pubspec)?,
+            dependencies: dependencies_in(&pubspec.dependencies, &dir, default_url)?,
+            dev_dependencies: dependencies_in(&pubspec.dev_dependencies, &dir, default_url)?,
+            overrides: dependencies_in(&pubspec.dependency_overrides, &dir, default_url)?,
+            language_version: language_version(
+                pubspec.environment.as_ref().map(|e| e.sdk.as_str()),
+            ),
+            sdk_constraints: SdkConstraints::from_environment(pubspec.environment.as_ref())?,
+            dir,
+        })
+    }
+}
+
+fn package_version(pubspec: &Pubspec) -> Result<Version, ResolveError> {
+    match &pubspec.version {
+        Some(version) => Ok(parse_version(version)?),
+        None => Ok(Version::new(0, 0, 0)),
+    }
+}
+
+/// A version of a hosted package, as listed by its repository
+#[derive(Debug, Clone)]
+pub struct HostedVersion {
+    pub version: Version,
+    pub sha256: Option<Sha256>,
+    pub retracted: bool,
+    pub dependencies: Vec<Dependency>,
+    pub language_version: Option<String>,
+    pub sdk_constraints: SdkConstraints,
+}
+
+/// A package in a local directory, either a path dependency or a git checkout
+#[derive(Debug, Clone)]
+pub struct LocalPackage {
+    pub name: String,
+    pub dir: PathBuf,
+    pub version: Version,
+    pub dependencies: Vec<Dependency>,
+    pub language_version: Option<String>,
+    pub sdk_constraints: SdkConstraints,
+}
+
+type Listing = Arc<Vec<HostedVersion>>;
+
+/// What a repository says about a hosted package as a whole
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostedStatus {
+    pub is_discontinued: bool,
+    pub replaced_by: Option<PackageName>,
+    /// Whether the repository knows of any security advisories for the package
+    pub has_advisories: bool,
+}
+
+/// Looks up the packages the resolver considers, remembering what it has already fetched
+pub struct PackageSources<'a> {
+    http: HttpClient,
+    default_url: Url,
+    /// Where listings for `default_url` are really fetched from, e.g. `PUB_HOSTED_URL`
+    default_source: Option<Url>,
+    git: Option<GitCache<'a>>,
+    sdks: SdkPaths,
+    sdk_versions: SdkVersions,
+    listings: Mutex<HashMap<(String, PackageName), Listing>>,
+    statuses: Mutex<HashMap<(String, PackageName), HostedStatus>>,
+    local: Mutex<HashMap<PathBuf, Arc<LocalPackage>>>,
+    /// Git checkouts by URL, ref, path and locked commit, so each is fetched only once
+    git_checkouts: Mutex<HashMap<GitKey, (GitPackage, Arc<LocalPackage>)>>,
+}
+
+type GitKey = (String, String, String, Option<String>);
+
+impl<'a> PackageSources<'a> {
+    pub fn new(http: impl Into<HttpClient>, default_url: Url) -> Self {
+        PackageSources {
+            http: http.into(),
+            default_url,
+            default_source: None,
+            git: None,
+            sdks: SdkPaths::default(),
+            sdk_versions: SdkVersions::default(),
+            listings: Mutex::default(),
+            statuses: Mutex::default(),
+            local: Mutex::default(),
+            git_checkouts: Mutex::default(),
+        }
+    }
+
+    /// Fetches listings for the default host from another repository, such as a mirror
+    pub fn with_default_source(mut self, url: Url) -> Self {
+        self.default_source = Some(url);
+        self
+    }
+
+    pub fn with_git(mut self, git: GitCache<'a>) -> Self {
+        self.git = Some(git);
+        self
+    }
+
+    /// Locates SDK packages in `sdks`, and only picks versions that support the SDKs there
+    pub fn with_sdks(mut self, sdks: SdkPaths) -> Self {
+        self.sdk_versions = sdks.versions();
+        self.sdks = sdks;
+        self
+    }
+
+    pub fn sdk_versions(&self) -> &SdkVersions {
+        &self.sdk_versions
+    }
+
+    pub fn default_url(&self) -> &Url {
+        &self.default_url
+    }
+
+    /// The versions of a hosted package, oldest first, or none if it doesn't exist
+    pub fn hosted_versions(
+        &self,
+        name: &PackageName,
+        url: &Url,
+    ) -> Result<Arc<Vec<HostedVersion>>, ResolveError> {
+        let key = (url.as_str().trim_end_matches('/').to_string(), name.clone());
+        if let Some(versions) = self.listings.lock().unwrap().get(&key) {
+            return Ok(versions.clone());
+        }
+
+        let listing = match self.client(url).listing(name) {
+            Ok(listing) => Some(listing),
+            Err(PubClientError::PackageNotFound { .. }) => None,
+            Err(e) => {
+                return Err(ResolveError::ListingError {
+                    name: name.clone(),
+                    url: url.clone(),
+                    source: Box::new(e),
+                });
+            }
+        };
+
+        let mut versions: Vec<_> = listing
+            .iter()
+            .flat_map(|listing| &listing.versions)
+            .filter_map(|v| {
+                // Versions we can't make sense of are as good as unavailable
+                let version = parse_version(v.version.as_ref()).ok()?;
+                let pubspec = v.parse_pubspec().transpose().ok()?;
+                let pubspec = pubspec.as_ref();
+                let dependencies = match pubspec {
+                    Some(p) => {
+                        dependencies_in(&p.dependencies, Path::new("."), &self.default_url).ok()?
+                    }
+                    None => Vec::new(),
+                };
+                Some(HostedVersion {
+                    version,
+                    sha256: v.archive_sha256.clone(),
+                    retracted: v.retracted,
+                    dependencies,
+                    language_version: language_version(
+                        pubspec
+                            .and_then(|p| p.environment.as_ref())
+                            .map(|e| e.sdk.as_str()),
+                    ),
+                    sdk_constraints: SdkConstraints::from_environment(
+                        pubspec.and_then(|p| p.environment.as_ref()),
+                    )
+                    .ok()?,
+                })
+            })
+            .collect();
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+        let status = listing
+            .map(|listing| HostedStatus {
+                is_discontinued: listing.is_discontinued,
+                replaced_by: listing.replaced_by,
+                has_advisories: listing.advisories_updated.is_some(),
+            })
+            .unwrap_or_default();
+        self.statuses.lock().unwrap().insert(key.clone(), status);
+
+        let versions = Arc::new(versions);
+        self.listings.lock().unwrap().insert(key, versions.clone());
+        Ok(versions)
+    }
+
+    /// Whether a hosted package is discontinued or has advisories
+    pub fn hosted_status(
+        &self,
+        name: &PackageName,
+        url: &Url,
+    ) -> Result<HostedStatus, ResolveError> {
+        self.hosted_versions(name, url)?;
+        let key = (url.as_str().trim_end_matches('/').to_string(), name.clone());
+        Ok(self.statuses.lock().unwrap()[&key].clone())
+    }
+
+    /// The security advisories for a hosted package, fetched only when it has any
+    pub fn advisories(&self, name: &PackageName, url: &Url) -> Result<Vec<Advisory>, ResolveError> {
+        if !self.hosted_status(name, url)?.has_advisories {
+            return Ok(Vec::new());
+        }
+        match self.client(url).advisories(name) {
+            Ok(advisories) => Ok(advisories.advisories),
+            Err(e) => Err(ResolveError::ListingError {
+                name: name.clone(),
+                url: url.clone(),
+                source: Box::new(e),
+            }),
+        }
+    }
+
+    /// A client for the repository at `url`, or the one it is really fetched from
+    fn client(&self, url: &Url) -> PubClient {
+        let fetch_url = match &self.default_source {
+            Some(source) if same_url(url, &self.default_url) => source.clone(),
+            _ => url.clone(),
+        };
+        PubClient::with_agent(fetch_url, self.http.clone())
+    }
+
+    /// The package in `dir`, with dependencies relative to it
+    pub fn local_package(&self, dir: &Path) -> Result<Arc<LocalPackage>, ResolveError> {
+        if let Some(package) = self.local.lock().unwrap().get(dir) {
+            return Ok(package.clone());
+        }
+
+        let pubspec = Pubspec::from_file(dir.join("pubspec.yaml"))?;
+        let package = Arc::new(LocalPackage {
+            name: pubspec.name.clone(),
+            dir: dir.to_path_buf(),
+            version: package_version(&pubspec)?,
+            dependencies: dependencies_in(&pubspec.dependencies, dir, &self.default_url)?,
+            language_version: language_version(
+                pubspec.environment.as_ref().map(|e| e.sdk.as_str()),
+            ),
+            sdk_constraints: SdkConstraints::from_environment(pubspec.environment.as_ref())?,
+        });
+
+        self.local
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), package.clone());
+        Ok(package)
+    }
+
+    /// Checks out a git package, at its `resolved_ref` if it has one
+    pub fn git_package(
+        &self,
+        name: &PackageName,
+        package: &GitPackage,
+    ) -> Result<(GitPackage, Arc<LocalPackage>), ResolveError> {
+        let key = (
+            package.url.as_str().trim_end_matches('/').to_string(),
+            git_ref(package).to_string(),
+            git_path(package).to_string(),
+            package.resolved_ref.clone(),
+        );
+        if let Some(checkout) = self.git_checkouts.lock().unwrap().get(&key) {
+            return Ok(checkout.clone());
+        }
+
+        let git = self
+            .git
+            .as_ref()
+            .ok_or_else(|| ResolveError::InvalidDependency {
+                name: name.clone(),
+                reason: "git dependencies need a pub cache".to_string(),
+            })?;
+        let checkout = git.install(name, package)?;
+        let local = self.local_package(&checkout.path)?;
+        let checkout = (package.clone().with_resolved_ref(checkout.commit), local);
+        self.git_checkouts
+            .lock()
+            .unwrap()
+            .insert(key, checkout.clone());
+        Ok(checkout)
+    }
+}
+
+/// Where a resolved package comes from
+#[derive(Debug, Clone)]
+pub enum ResolvedSource {
+    Hosted {
+        url: Url,
+        sha256: Option<Sha256>,
+    },
+    Path {
+        path: PathBuf,
+        relative: bool,
+    },
+    /// A git package, locked to a commit, and the directory it is checked out in
+    Git {
+        package: GitPackage,
+        dir: PathBuf,
+    },
+    /// A package shipped with an SDK, and where it is in that SDK
+    Sdk {
+        sdk: String,
+        dir: PathBuf,
+    },
+}
+
+impl ResolvedSource {
+    fn satisfies(&self, source: &PackageSource) -> bool {
+        match (self, source) {
+            (ResolvedSource::Hosted { url, .. }, PackageSource::Hosted(other)) => {
+                same_url(url, other)
+            }
+            (ResolvedSource::Path { path, .. }, PackageSource::Path { path: other, .. }) => {
+                path == other
+            }
+            (ResolvedSource::Git { package, .. }, other @ PackageSource::Git(_)) => {
+                PackageSource::Git(package.clone()).same_as(other)
+            }
+            (ResolvedSource::Sdk { sdk, .. }, PackageSource::Sdk(other)) => sdk == other,
+            _ => false,
+        }
+    }
+}
+
+/// How the root package depends on a resolved package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    DirectMain,
+    DirectDev,
+    DirectOverridden,
+    Transitive,
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DependencyKind::DirectMain => "direct main",
+            DependencyKind::DirectDev => "direct dev",
+            DependencyKind::DirectOverridden => "direct overridden",
+            DependencyKind::Transitive => "transitive",
+        })
+    }
+}
+
+/// A package picked by the resolver
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub name: PackageName,
+    pub version: Version,
+    pub source: ResolvedSource,
+    pub kind: DependencyKind,
+    pub dependencies: Vec<Dependency>,
+    pub language_version: Option<String>,
+    pub sdk_constraints: SdkConstraints,
+}
+
+/// The outcome of resolving a root package's dependencies
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub root: RootPackage,
+    pub packages: BTreeMap<PackageName, ResolvedPackage>,
+}
+
+impl Resolution {
+    /// `names` and every package they depend on, directly or not
+    pub fn with_dependencies<'n>(
+        &self,
+        names: impl IntoIterator<Item = &'n PackageName>,
+    ) -> BTreeSet<PackageName> {
+        let mut found = BTreeSet::new();
+        let mut pending: Vec<_> = names.into_iter().cloned().collect();
+        while let Some(name) = pending.pop() {
+            if let Some(package) = self.packages.get(&name)
+                && found.insert(name)
+            {
+                pending.extend(package.dependencies.iter().map(|d| d.name.clone()));
+            }
+        }
+        found
+    }
+
+    /// The SDK versions supported by the root and every package it uses
+    pub fn sdk_constraints(&self) -> SdkConstraints {
+        self.packages
+            .values()
+            .fold(self.root.sdk_constraints.clone(), |all, package| {
+                all.intersect(&package.sdk_constraints)
+            })
+    }
+}
+
+/// The resolution of `root` made of the `names` picked in `selected`
+fn resolution_of<'n>(
+    root: &RootPackage,
+    selected: &BTreeMap<PackageName, Candidate>,
+    names: impl IntoIterator<Item = &'n PackageName>,
+) -> Resolution {
+    let declared = |deps: &[Dependency], name: &PackageName| deps.iter().any(|d| &d.name == name);
+    let kind = |name: &PackageName| {
+        if declared(&root.overrides, name) {
+            DependencyKind::DirectOverridden
+        } else if declared(&root.dependencies, name) {
+            DependencyKind::DirectMain
+        } else if declared(&root.dev_dependencies, name) {
+            DependencyKind::DirectDev
+        } else {
+            DependencyKind::Transitive
+        }
+    };
+
+    let packages = names
+        .into_iter()
+        .map(|name| {
+            let candidate = selected[name].clone();
+            let package = ResolvedPackage {
+                kind: kind(name),
+                name: name.clone(),
+                version: candidate.version,
+                source: candidate.source,
+                dependencies: candidate.dependencies,
+                language_version: candidate.language_version,
+                sdk_constraints: candidate.sdk_constraints,
+            };
+            (name.clone(), package)
+        })
+        .collect();
+
+    Resolution {
+        root: root.clone(),
+        packages,
+    }
+}
+
+/// The outcome of resolving several roots together, with a resolution for each in the order
+/// they were given
+#[derive(Debug, Clone)]
+pub struct SharedResolution {
+    pub resolutions: Vec<Resolution>,
+}
+
+impl SharedResolution {
+    /// One resolution of everything the roots use, but not the roots themselves, for a
+    /// lockfile they all share. Each package has the most direct kind any root gives it.
+    pub fn combined(&self, root: RootPackage) -> Resolution {
+        let rank = |kind: DependencyKind| match kind {
+            DependencyKind::DirectOverridden => 3,
+            DependencyKind::DirectMain => 2,
+            DependencyKind::DirectDev => 1,
+            DependencyKind::Transitive => 0,
+        };
+        let roots: BTreeSet<_> = self.resolutions.iter().map(|r| &r.root.name).collect();
+
+        let mut packages: BTreeMap<PackageName, ResolvedPackage> = BTreeMap::new();
+        let all = self.resolutions.iter().flat_map(|r| r.packages.values());
+        for package in all.filter(|p| !roots.contains(&p.name)) {
+            match packages.get_mut(&package.name) {
+                Some(existing) if rank(package.kind) > rank(existing.kind) => {
+                    existing.kind = package.kind
+                }
+                Some(_) => {}
+                None => {
+                    packages.insert(package.name.clone(), package.clone());
+                }
+            }
+        }
+        Resolution { root, packages }
+    }
+
+    /// The resolution of a pub workspace, where the first root is the workspace's and the
+    /// others are its members, which become path packages
+    pub fn workspace(&self) -> Resolution {
+        let (first, members) = self
+            .resolutions
+            .split_first()
+            .expect("a workspace has a root");
+        let mut resolution = self.combined(first.root.clone());
+        for member in members {
+            let root = &member.root;
+            let package = ResolvedPackage {
+                name: root.name.clone(),
+                version: root.version.clone(),
+                source: ResolvedSource::Path {
+                    path: root.dir.clone(),
+                    relative: true,
+                },
+                kind: DependencyKind::DirectMain,
+                dependencies: root.dependencies.clone(),
+                language_version: root.language_version.clone(),
+                sdk_constraints: root.sdk_constraints.clone(),
+            };
+            resolution.packages.insert(root.name.clone(), package);
+        }
+        resolution
+    }
+}
+
+/// Which versions to try first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Preference {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    version: Version,
+    source: ResolvedSource,
+    dependencies: Vec<Dependency>,
+    language_version: Option<String>,
+    sdk_constraints: SdkConstraints,
+}
+
+#[derive(Debug, Clone)]
+struct Requirement {
+    dependency: Dependency,
+    from: PackageName,
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (from {})", self.dependency, self.from)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    selected: BTreeMap<PackageName, Candidate>,
+    requirements: BTreeMap<PackageName, Vec<Requirement>>,
+    /// The members of the workspace being resolved, which are used wherever they're depended on
+    members: BTreeSet<PackageName>,
+}
+
+impl State {
+    /// Whether the package selected for a dependency meets it. A workspace member meets hosted
+    /// dependencies on it that allow its version, as dart uses the member instead.
+    fn meets(
+        &self,
+        selected: &Candidate,
+        dependency: &Dependency,
+        overrides: &HashMap<PackageName, Dependency>,
+    ) -> bool {
+        let member = self.members.contains(&dependency.name)
+            && matches!(dependency.source, PackageSource::Hosted(_));
+        overrides.contains_key(&dependency.name)
+            || ((member || selected.source.satisfies(&dependency.source))
+                && dependency.constraint.allows(&selected.version))
+    }
+}
+
+const MAX_STEPS: usize = 100_000;
+
+/// A backtracking version solver, which picks one version of every package the root
+/// depends on, directly or not, such that every constraint is met
+pub struct Resolver<'a, 'b> {
+    sources: &'a PackageSources<'b>,
+    locked: HashMap<PackageName, Version>,
+    locked_git: HashMap<PackageName, GitPackage>,
+    preference: Preference,
+    steps: Mutex<usize>,
+}
+
+impl<'a, 'b> Resolver<'a, 'b> {
+    pub fn new(sources: &'a PackageSources<'b>) -> Self {
+        Resolver {
+            sources,
+            locked: HashMap::new(),
+            locked_git: HashMap::new(),
+            preference: Preference::default(),
+            steps: Mutex::new(0),
+        }
+    }
+
+    /// Prefers the versions in `lock`. Given several locks, prefers the newest version any of
+    /// them has.
+    pub fn with_lock(mut self, lock: &PubspecLock) -> Self {
+        for (name, spec) in &lock.packages {
+            if let Ok(version) = parse_version(spec.version.as_ref()) {
+                let locked = self.locked.entry(name.clone()).or_insert(version.clone());
+                *locked = version.max(locked.clone());
+            }
+            if let Some(PackageDescription::Git(git)) = &spec.description {
+                self.locked_git.insert(name.clone(), git.clone());
+            }
+        }
+        self
+    }
+
+    /// Forgets the locked versions of `names`, so that they can be upgraded
+    pub fn with_unlocked<'n>(mut self, names: impl IntoIterator<Item = &'n PackageName>) -> Self {
+        for name in names {
+            self.locked.remove(name);
+            self.locked_git.remove(name);
+        }
+        self
+    }
+
+    pub fn with_preference(mut self, preference: Preference) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    pub fn resolve(&self, root: &RootPackage) -> Result<Resolution, ResolveError> {
+        *self.steps.lock().unwrap() = 0;
+        self.check_sdk(&root.name, &root.version, &root.sdk_constraints)?;
+
+        let mut state = State::default();
+        state.selected.insert(
+            root.name.clone(),
+            Candidate {
+                version: root.version.clone(),
+                source: ResolvedSource::Path {
+                    path: root.dir.clone(),
+                    relative: true,
+                },
+                dependencies: Vec::new(),
+                language_version: root.language_version.clone(),
+                sdk_constraints: root.sdk_constraints.clone(),
+            },
+        );
+
+        let overrides: HashMap<_, _> = root
+            .overrides
+            .iter()
+            .map(|d| (d.name.clone(), d.clone()))
+            .collect();
+        let direct = root
+            .dependencies
+            .iter()
+            .chain(&root.dev_dependencies)
+            .chain(&root.overrides);
+        for dependency in direct {
+            state
+                .requirements
+                .entry(dependency.name.clone())
+                .or_default()
+                .push(Requirement {
+                    dependency: dependency.clone(),
+                    from: root.name.clone(),
+                });
+        }
+
+        let state = self.solve(state, &overrides)?;
+        let names = state.selected.keys().filter(|name| *name != &root.name);
+        Ok(resolution_of(root, &state.selected, names))
+    }
+
+    /// Resolves several roots at once, so that the packages they share get the same version.
+    /// The roots can depend on each other by path, and each gets a resolution of its own.
+    pub fn resolve_together(
+        &self,
+        roots: &[RootPackage],
+    ) -> Result<SharedResolution, ResolveError> {
+        self.resolve_roots(roots, State::default())
+    }
+
+    /// Resolves the packages of a pub workspace, the first being the workspace's own, like
+    /// `resolve_together`. The members can also depend on each other by version, and get each
+    /// other's local copies then too.
+    pub fn resolve_workspace(
+        &self,
+        roots: &[RootPackage],
+    ) -> Result<SharedResolution, ResolveError> {
+        let state = State {
+            members: roots.iter().map(|root| root.name.clone()).collect(),
+            ..State::default()
+        };
+        self.resolve_roots(roots, state)
+    }
+
+    fn resolve_roots(
+        &self,
+        roots: &[RootPackage],
+        mut state: State,
+    ) -> Result<SharedResolution, ResolveError> {
+        *self.steps.lock().unwrap() = 0;
+
+        let mut overrides: HashMap<PackageName, Requirement> = HashMap::new();
+        for root in roots {
+            self.check_sdk(&root.name, &root.version, &root.sdk_constraints)?;
+            if let Some(other) = roots
+                .iter()
+                .find(|r| r.name == root.name && r.dir != root.dir)
+            {
+                return Err(ResolveError::DuplicateRoot {
+                    name: root.name.clone(),
+                    first: other.dir.clone(),
+                    second: root.dir.clone(),
+                });
+            }
+            state.selected.insert(
+                root.name.clone(),
+                Candidate {
+                    version: root.version.clone(),
+                    source: ResolvedSource::Path {
+                        path: root.dir.clone(),
+                        relative: true,
+                    },
+                    // What the others get when they depend on it
+                    dependencies: root.dependencies.clone(),
+                    language_version: root.language_version.clone(),
+                    sdk_constraints: root.sdk_constraints.clone(),
+                },
+            );
+
+            for dependency in &root.overrides {
+                let requirement = Requirement {
+                    dependency: dependency.clone(),
+                    from: root.name.clone(),
+                };
+                match overrides.get(&dependency.name) {
+                    Some(other)
+                        if !other.dependency.source.same_as(&dependency.source)
+                            || other.dependency.constraint != dependency.constraint =>
+                    {
+                        return Err(ResolveError::ConflictingOverrides {
+                            name: dependency.name.clone(),
+                            first: other.to_string(),
+                            second: requirement.to_string(),
+                        });
+                    }
+                    _ => overrides.insert(dependency.name.clone(), requirement),
+                };
+            }
+        }
+        let overrides: HashMap<_, _> = overrides
+            .into_iter()
+            .map(|(name, requirement)| (name, requirement.dependency))
+            .collect();
+
+        for root in roots {
+            let direct = root
+                .dependencies
+                .iter()
+                .chain(&root.dev_dependencies)
+                .chain(&root.overrides);
+            for dependency in direct {
+                state
+                    .requirements
+                    .entry(dependency.name.clone())
+                    .or_default()
+                    .push(Requirement {
+                        dependency: dependency.clone(),
+                        from: root.name.clone(),
+                    });
+            }
+        }
+
+        // The roots are already chosen, so what the others ask of them has to be checked here
+        for root in roots {
+            let selected = &state.selected[&root.name];
+            let requirements = state.requirements.get(&root.name).into_iter().flatten();
+            for requirement in requirements {
+                if !state.meets(selected, &requirement.dependency, &overrides) {
+                    return Err(self.no_version(&root.name, &state));
+                }
+            }
+        }
+
+        let state = self.solve(state, &overrides)?;
+        let resolutions = roots
+            .iter()
+            .map(|root| {
+                let mut names = BTreeSet::new();
+                let mut pending: Vec<_> = root
+                    .dependencies
+                    .iter()
+                    .chain(&root.dev_dependencies)
+                    .chain(&root.overrides)
+                    .map(|d| d.name.clone())
+                    .collect();
+                while let Some(name) = pending.pop() {
+                    if name != root.name
+                        && let Some(candidate) = state.selected.get(&name)
+                        && names.insert(name)
+                    {
+                        pending.extend(candidate.dependencies.iter().map(|d| d.name.clone()));
+                    }
+                }
+                resolution_of(root, &state.selected, &names)
+            })
+            .collect();
+
+        Ok(SharedResolution { resolutions })
+    }
+
+    fn solve(
+        &self,
+        state: State,
+        overrides: &HashMap<PackageName, Dependency>,
+    ) -> Result<State, ResolveError> {
+        {
+            let mut steps = self.steps.lock().unwrap();
+            *steps += 1;
+            if *steps > MAX_STEPS {
+                return Err(ResolveError::TooComplex(MAX_STEPS));
+            }
+        }
+
+        // Decide the most constrained package first, so that conflicts show up early
+        let mut next: Option<(PackageName, Vec<Candidate>)> = None;
+        for name in state.requirements.keys() {
+            if state.selected.contains_key(name) {
+                continue;
+            }
+            let candidates = self.candidates(name, &state, overrides)?;
+            if next
+                .as_ref()
+                .is_none_or(|(_, best)| candidates.len() < best.len())
+            {
+                let exhausted = candidates.is_empty();
+                next = Some((name.clone(), candidates));
+                if exhausted {
+                    break;
+                }
+            }
+        }
+        let Some((name, candidates)) = next else {
+            return Ok(state);
+        };
+
+        let mut error = None;
+        let mut failed = HashSet::new();
+        for candidate in candidates {
+            // Versions with the same dependencies fail in the same way
+            let signature = candidate
+                .dependencies
+                .iter()
+                .map(|d| format!("{} {} {}", d.name, d.source, d.constraint))
+                .collect::<Vec<_>>();
+            if failed.contains(&signature) {
+                continue;
+            }
+
+            let result = self
+                .select(state.clone(), &name, candidate, overrides)
+                .and_then(|next| self.solve(next, overrides));
+            match result {
+                Ok(solution) => return Ok(solution),
+                Err(e @ ResolveError::TooComplex(_)) => return Err(e),
+                Err(e) => {
+                    failed.insert(signature);
+                    error = Some(e);
+                }
+            }
+        }
+
+        Err(error.unwrap_or_else(|| self.no_version(&name, &state)))
+    }
+
+    fn no_version(&self, name: &PackageName, state: &State) -> ResolveError {
+        ResolveError::NoVersion {
+            name: name.clone(),
+            requirements: state
+                .requirements
+                .get(name)
+                .into_iter()
+                .flatten()
+                .map(|r| r.to_string())
+                .collect(),
+        }
+    }
+
+    /// Adds a package to the solution, along with what it requires of others
+    fn select(
+        &self,
+        mut state: State,
+        name: &PackageName,
+        candidate: Candidate,
+        overrides: &HashMap<PackageName, Dependency>,
+    ) -> Result<State, ResolveError> {
+        for dependency in &candidate.dependencies {
+            state
+                .requirements
+                .entry(dependency.name.clone())
+                .or_default()
+                .push(Requirement {
+                    dependency: dependency.clone(),
+                    from: name.clone(),
+                });
+
+            let Some(selected) = state.selected.get(&dependency.name) else {
+                continue;
+            };
+            if !state.meets(selected, dependency, overrides) {
+                return Err(self.no_version(&dependency.name, &state));
+            }
+        }
+
+        state.selected.insert(name.clone(), candidate);
+        Ok(state)
+    }
+
+    /// The versions of `name` that meet every requirement on it, best first
+    fn candidates(
+        &self,
+        name: &PackageName,
+        state: &State,
+        overrides: &HashMap<PackageName, Dependency>,
+    ) -> Result<Vec<Candidate>, ResolveError> {
+        let requirements = &state.requirements[name];
+        let (source, constraint) = match overrides.get(name) {
+            Some(dependency) => (dependency.source.clone(), dependency.constraint.clone()),
+            None => {
+                let first = &requirements[0].dependency.source;
+                if let Some(other) = requirements
+                    .iter()
+                    .find(|r| !r.dependency.source.same_as(first))
+                {
+                    return Err(ResolveError::SourceConflict {
+                        name: name.clone(),
+                        first: requirements[0].to_string(),
+                        second: other.to_string(),
+                    });
+                }
+                let constraint = requirements.iter().fold(VersionConstraint::any(), |c, r| {
+                    c.intersect(&r.dependency.constraint)
+                });
+                (first.clone(), constraint)
+            }
+        };
+
+        let local = |package: &LocalPackage, source: ResolvedSource| {
+            if package.name != name.as_ref() {
+                return Err(ResolveError::NameMismatch {
+                    expected: name.clone(),
+                    found: package.name.clone(),
+                    path: package.dir.clone(),
+                });
+            }
+            self.check_sdk(name, &package.version, &package.sdk_constraints)?;
+            let candidate = Candidate {
+                version: package.version.clone(),
+                source,
+                dependencies: package.dependencies.clone(),
+                language_version: package.language_version.clone(),
+                sdk_constraints: package.sdk_constraints.clone(),
+            };
+            Ok(Some(candidate)
+                .filter(|c| constraint.allows(&c.version))
+                .into_iter()
+                .collect())
+        };
+
+        match &source {
+            PackageSource::Hosted(url) => Ok(self.hosted_candidates(name, url, &constraint)?),
+            PackageSource::Path { path, relative } => {
+                let package = self.sources.local_package(path)?;
+                local(
+                    &package,
+                    ResolvedSource::Path {
+                        path: path.clone(),
+                        relative: *relative,
+                    },
+                )
+            }
+            PackageSource::Git(git) => {
+                // Stay on the locked commit, as long as the dependency hasn't changed
+                let git = match self.locked_git.get(name) {
+                    Some(locked) if PackageSource::Git(locked.clone()).same_as(&source) => {
+                        locked.clone()
+                    }
+                    _ => git.clone(),
+                };
+                let (package, local_package) = self.sources.git_package(name, &git)?;
+                let dir = local_package.dir.clone();
+                local(&local_package, ResolvedSource::Git { package, dir })
+            }
+            PackageSource::Sdk(sdk) => {
+                let dir = self.sources.sdks.package_dir(sdk, name)?;
+                // The SDK's own pubspec pins the versions of what it depends on
+                let package = self.sources.local_package(&dir)?;
+                local(
+                    &package,
+                    ResolvedSource::Sdk {
+                        sdk: sdk.clone(),
+                        dir,
+                    },
+                )
+            }
+        }
+    }
+
+    fn hosted_candidates(
+        &self,
+        name: &PackageName,
+        url: &Url,
+        constraint: &VersionConstraint,
+    ) -> Result<Vec<Candidate>, ResolveError> {
+        let locked = self.locked.get(name);
+        let versions = self.sources.hosted_versions(name, url)?;
+
+        let matching: Vec<_> = versions
+            .iter()
+            .filter(|v| constraint.allows(&v.version))
+            // Retracted versions are only used when they are already locked
+            .filter(|v| !v.retracted || Some(&v.version) == locked)
+            .collect();
+
+        // A locked version that no longer works with the SDK needs upgrading, not replacing
+        if let Some(locked) = matching.iter().find(|v| Some(&v.version) == locked) {
+            self.check_sdk(name, &locked.version, &locked.sdk_constraints)?;
+        }
+        let (mut allowed, incompatible): (Vec<_>, Vec<_>) = matching.into_iter().partition(|v| {
+            v.sdk_constraints
+                .incompatibility(self.sources.sdk_versions())
+                .is_none()
+        });
+        if allowed.is_empty()
+            && let Some(newest) = incompatible.last()
+        {
+            self.check_sdk(name, &newest.version, &newest.sdk_constraints)?;
+        }
+
+        // The locked version, then stable releases, then prereleases
+        allowed.sort_by(|a, b| {
+            let key = |v: &HostedVersion| (Some(&v.version) != locked, !v.version.pre.is_empty());
+            key(a).cmp(&key(b)).then_with(|| match self.preference {
+                Preference::Newest => b.version.cmp(&a.version),
+                Preference::Oldest => a.version.cmp(&b.version),
+            })
+        });
+
+        Ok(allowed
+            .into_iter()
+            .map(|v| Candidate {
+                version: v.version.clone(),
+                source: ResolvedSource::Hosted {
+                    url: url.clone(),
+                    sha256: v.sha256.clone(),
+                },
+                dependencies: v.dependencies.clone(),
+                language_version: v.language_version.clone(),
+                sdk_constraints: v.sdk_constraints.clone(),
+            })
+            .collect())
+    }
+
+    fn check_sdk(
+        &self,
+        name: &PackageName,
+        version: &Version,
+        constraints: &SdkConstraints,
+    ) -> Result<(), ResolveError> {
+        match constraints.incompatibility(self.sources.sdk_versions()) {
+            Some(reason) => Err(ResolveError::IncompatibleSdk {
+                package: name.clone(),
+                version: version.clone(),
+                reason: Box::new(reason),
+            }),
+            None => Ok(()),
+        }
+    }
+}
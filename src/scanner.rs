@@ -1,4 +1,4 @@
-use crate::pubspec::{Pubspec, PubspecError};
+use crate::pubspec::{Pubspec, PubspecError, PubspecOverrides};
 use crate::pubspeclock::{PubspecLock, PubspecLockError};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -9,6 +9,10 @@ pub struct PubspecInfo {
     pub path: PathBuf,
     pub pubspec: Pubspec,
     pub lock_file: Option<PubspecLock>,
+    /// What pubspec_overrides.yaml says, if there is one
+    pub overrides: Option<PubspecOverrides>,
+    /// The packages of the workspace, when this is the root of one
+    pub members: Vec<PubspecInfo>,
 }
 
 pub struct Scanner {
@@ -21,6 +25,10 @@ pub enum ScannerError {
     PubspecError(#[from] PubspecError), // Assuming Pubspec has a similar error type
     #[error(transparent)]
     PubspecLockError(#[from] PubspecLockError),
+    #[error("{0} is a workspace member, but doesn't have `resolution: workspace`")]
+    NotAWorkspaceMember(PathBuf),
+    #[error("{0} has `resolution: workspace`, but no workspace above it lists it")]
+    NoWorkspaceRoot(PathBuf),
 }
 
 impl Scanner {
@@ -36,7 +44,9 @@ impl Scanner {
             .unwrap_or(false)
     }
 
-    fn load_pubspec_files(pubspec_path: &Path) -> Result<PubspecInfo, ScannerError> {
+    /// Loads a pubspec.yaml with the lock and overrides beside it, and the members of its
+    /// workspace if it is the root of one
+    pub fn load_pubspec_files(pubspec_path: &Path) -> Result<PubspecInfo, ScannerError> {
         let pubspec = Pubspec::from_file(pubspec_path)?;
 
         // Try to load the lock file if it exists
@@ -47,10 +57,43 @@ impl Scanner {
             None
         };
 
+        let overrides_path = pubspec_path.with_file_name("pubspec_overrides.yaml");
+        let overrides = match overrides_path.exists() {
+            true => Some(PubspecOverrides::from_file(&overrides_path)?),
+            false => None,
+        };
+
+        let dir = pubspec_path.parent().unwrap_or(Path::new("."));
+        let mut members = Vec::new();
+        for member in pubspec.workspace.iter().flatten() {
+            let info = Self::load_pubspec_files(&dir.join(member).join("pubspec.yaml"))?;
+            if !info.pubspec.is_workspace_member() {
+                return Err(ScannerError::NotAWorkspaceMember(info.path));
+            }
+            members.push(info);
+        }
+
         Ok(PubspecInfo {
             path: pubspec_path.to_path_buf(),
             pubspec,
             lock_file,
+            overrides,
+            members,
+        })
+    }
+
+    /// The pubspec.yaml of the workspace that lists the member at `pubspec_path`
+    fn workspace_root(pubspec_path: &Path) -> Option<PathBuf> {
+        let member = pubspec_path.parent()?.canonicalize().ok()?;
+        member.ancestors().skip(1).find_map(|dir| {
+            let path = dir.join("pubspec.yaml");
+            let pubspec = Pubspec::from_file(&path).ok()?;
+            pubspec
+                .workspace
+                .iter()
+                .flatten()
+                .any(|m| dir.join(m).canonicalize().ok().as_ref() == Some(&member))
+                .then_some(path)
         })
     }
 
@@ -74,7 +117,25 @@ impl Scanner {
                     Some(Ok(entry)) => {
                         if Self::is_pubspec_yaml(&entry) {
                             let result = Self::load_pubspec_files(entry.path());
-                            results.push(result);
+                            // A member is resolved with its workspace, so scan that instead
+                            let result = match result {
+                                Ok(info) if info.pubspec.is_workspace_member() => {
+                                    match Self::workspace_root(&info.path) {
+                                        Some(root) => Self::load_pubspec_files(&root),
+                                        None => Err(ScannerError::NoWorkspaceRoot(info.path)),
+                                    }
+                                }
+                                result => result,
+                            };
+                            let seen = result.as_ref().is_ok_and(|info| {
+                                results
+                                    .iter()
+                                    .flatten()
+                                    .any(|r: &PubspecInfo| same_file(&r.path, &info.path))
+                            });
+                            if !seen {
+                                results.push(result);
+                            }
                             walker.skip_current_dir();
                         }
                     }
@@ -88,3 +149,10 @@ impl Scanner {
         results
     }
 }
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -0,0 +1,264 @@
+use crate::pubspec::Environment;
+use crate::pubspeclock::PackageName;
+use crate::version::{Bound, VersionConstraint, VersionError, parse_version};
+use semver::Version;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SdkError {
+    #[error("Could not find the Flutter SDK, set FLUTTER_ROOT or flutter_root in the config")]
+    FlutterNotFound,
+    #[error("Could not find the Dart SDK, set DART_SDK or FLUTTER_ROOT")]
+    DartNotFound,
+    #[error("Unknown SDK {0}")]
+    UnknownSdk(String),
+    #[error("The {sdk} SDK at {root} has no package {name}")]
+    UnknownPackage {
+        sdk: String,
+        name: PackageName,
+        root: PathBuf,
+    },
+}
+
+/// Where the Flutter and Dart SDKs are, for packages that depend on them with `sdk:`
+#[derive(Debug, Clone, Default)]
+pub struct SdkPaths {
+    flutter_root: Option<PathBuf>,
+    dart_sdk: Option<PathBuf>,
+}
+
+impl SdkPaths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_flutter_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.flutter_root = Some(path.into());
+        self
+    }
+
+    pub fn with_dart_sdk(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dart_sdk = Some(path.into());
+        self
+    }
+
+    /// Finds the SDKs from `FLUTTER_ROOT`, `DART_SDK`, the config and the binaries on `PATH`
+    pub fn from_env(config_flutter_root: Option<PathBuf>) -> Self {
+        Self::locate(
+            env::var_os("FLUTTER_ROOT"),
+            env::var_os("DART_SDK"),
+            config_flutter_root,
+            env::var_os("PATH"),
+        )
+    }
+
+    /// Flutter is `flutter_root`, then `config_flutter_root`, then wherever `flutter` on `path`
+    /// lives. Dart is `dart_sdk`, then the one bundled with Flutter, then `dart` on `path`.
+    pub fn locate(
+        flutter_root: Option<OsString>,
+        dart_sdk: Option<OsString>,
+        config_flutter_root: Option<PathBuf>,
+        path: Option<OsString>,
+    ) -> Self {
+        let flutter_root = flutter_root
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or(config_flutter_root)
+            .or_else(|| sdk_root_on_path("flutter", path.as_ref()?));
+        let dart_sdk = dart_sdk
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                let bundled = flutter_root.as_ref()?.join("bin/cache/dart-sdk");
+                bundled.is_dir().then_some(bundled)
+            })
+            .or_else(|| sdk_root_on_path("dart", path.as_ref()?));
+
+        SdkPaths {
+            flutter_root,
+            dart_sdk,
+        }
+    }
+
+    pub fn flutter_root(&self) -> Option<&Path> {
+        self.flutter_root.as_deref()
+    }
+
+    pub fn dart_sdk(&self) -> Option<&Path> {
+        self.dart_sdk.as_deref()
+    }
+
+    /// The directory of package `name` in `sdk`, e.g. `$FLUTTER_ROOT/packages/flutter_test`
+    pub fn package_dir(&self, sdk: &str, name: &PackageName) -> Result<PathBuf, SdkError> {
+        let (root, dir) = match sdk {
+            "flutter" => {
+                let root = self.flutter_root().ok_or(SdkError::FlutterNotFound)?;
+                let dir = match name.as_ref() {
+                    "sky_engine" => root.join("bin/cache/pkg/sky_engine"),
+                    name => root.join("packages").join(name),
+                };
+                (root, dir)
+            }
+            "dart" => {
+                let root = self.dart_sdk().ok_or(SdkError::DartNotFound)?;
+                (root, root.join("pkg").join(name.as_ref()))
+            }
+            other => return Err(SdkError::UnknownSdk(other.to_string())),
+        };
+
+        match dir.join("pubspec.yaml").is_file() {
+            true => Ok(dir),
+            false => Err(SdkError::UnknownPackage {
+                sdk: sdk.to_string(),
+                name: name.clone(),
+                root: root.to_path_buf(),
+            }),
+        }
+    }
+
+    /// The versions of the SDKs that could be found, from `$DART_SDK/version`, and
+    /// `$FLUTTER_ROOT/version` or `bin/cache/flutter.version.json`
+    pub fn versions(&self) -> SdkVersions {
+        let dart = self
+            .dart_sdk()
+            .and_then(|root| fs::read_to_string(root.join("version")).ok())
+            .and_then(|version| parse_version(&version).ok());
+        let flutter = self.flutter_root().and_then(|root| {
+            let version = fs::read_to_string(root.join("version"))
+                .ok()
+                .or_else(|| flutter_version_json(root, "frameworkVersion"))?;
+            parse_version(&version).ok()
+        });
+        // Flutter records the version of the Dart SDK it bundles too
+        let dart = dart.or_else(|| {
+            let version = flutter_version_json(self.flutter_root()?, "dartSdkVersion")?;
+            parse_version(&version).ok()
+        });
+
+        SdkVersions { dart, flutter }
+    }
+}
+
+fn flutter_version_json(root: &Path, field: &str) -> Option<String> {
+    let json = fs::read_to_string(root.join("bin/cache/flutter.version.json")).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&json).ok()?;
+    value[field].as_str().map(String::from)
+}
+
+/// The installed SDK versions, where known
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SdkVersions {
+    pub dart: Option<Version>,
+    pub flutter: Option<Version>,
+}
+
+/// The SDK versions a package supports, from the `environment` in its pubspec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SdkConstraints {
+    pub dart: Option<VersionConstraint>,
+    pub flutter: Option<VersionConstraint>,
+}
+
+impl SdkConstraints {
+    pub fn from_environment(environment: Option<&Environment>) -> Result<Self, VersionError> {
+        let Some(environment) = environment else {
+            return Ok(Self::default());
+        };
+        Ok(SdkConstraints {
+            dart: Some(dart3_compatible(VersionConstraint::parse(
+                &environment.sdk,
+            )?)),
+            flutter: environment
+                .flutter
+                .as_deref()
+                .map(VersionConstraint::parse)
+                .transpose()?,
+        })
+    }
+
+    /// The SDK versions supported by both
+    pub fn intersect(&self, other: &SdkConstraints) -> SdkConstraints {
+        let both = |a: &Option<VersionConstraint>, b: &Option<VersionConstraint>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.intersect(b)),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        SdkConstraints {
+            dart: both(&self.dart, &other.dart),
+            flutter: both(&self.flutter, &other.flutter),
+        }
+    }
+
+    /// The first SDK that is installed at a version these constraints don't allow
+    pub fn incompatibility(&self, installed: &SdkVersions) -> Option<SdkIncompatibility> {
+        let check = |sdk, constraint: &Option<VersionConstraint>, version: &Option<Version>| {
+            let (constraint, version) = (constraint.as_ref()?, version.as_ref()?);
+            (!constraint.allows(version)).then(|| SdkIncompatibility {
+                sdk,
+                constraint: constraint.clone(),
+                installed: version.clone(),
+            })
+        };
+        check("Dart", &self.dart, &installed.dart)
+            .or_else(|| check("Flutter", &self.flutter, &installed.flutter))
+    }
+}
+
+/// Dart 3 accepts packages that only claim to support Dart 2, as long as they are null safe,
+/// by treating `>=2.12.0 <3.0.0` as `>=2.12.0 <4.0.0`
+fn dart3_compatible(mut constraint: VersionConstraint) -> VersionConstraint {
+    let dart3 = Version::new(3, 0, 0);
+    let null_safe = constraint
+        .min
+        .as_ref()
+        .is_some_and(|min| min.version >= Version::new(2, 12, 0));
+    let below_dart3 = constraint
+        .max
+        .as_ref()
+        .is_some_and(|max| max.version == dart3 && !max.inclusive);
+    if null_safe && below_dart3 {
+        constraint.max = Some(Bound {
+            version: Version::new(4, 0, 0),
+            inclusive: false,
+        });
+    }
+    constraint
+}
+
+/// An SDK that is installed at a version a package doesn't support
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdkIncompatibility {
+    pub sdk: &'static str,
+    pub constraint: VersionConstraint,
+    pub installed: Version,
+}
+
+impl fmt::Display for SdkIncompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "requires {} SDK version {}, but the current {} SDK version is {}",
+            self.sdk,
+            self.constraint.to_range_string(),
+            self.sdk,
+            self.installed
+        )
+    }
+}
+
+/// The SDK containing `binary` on `path`, following symlinks to the real `bin` directory
+fn sdk_root_on_path(binary: &str, path: &OsString) -> Option<PathBuf> {
+    let found = env::split_paths(path)
+        .flat_map(|dir| [dir.join(binary), dir.join(format!("{}.bat", binary))])
+        .find(|candidate| candidate.is_file())?;
+    let root = found.canonicalize().ok()?.parent()?.parent()?.to_path_buf();
+
+    // Flutter's bin/dart is a wrapper around the Dart SDK it bundles
+    let bundled = root.join("bin/cache/dart-sdk");
+    match binary == "dart" && bundled.is_dir() {
+        true => Some(bundled),
+        false => Some(root),
+    }
+}
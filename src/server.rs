@@ -0,0 +1,461 @@
+use crate::downloader::{DownloadError, PackageDownloader};
+use crate::pubcache::{PubCache, PubCacheError};
+use crate::pubclient::{PubClient, PubClientError};
+use crate::pubspeclock::{PackageName, PackageVersion, Sha256};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use serde_json::json;
+use sha2::Digest;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::{fs, io};
+use thiserror::Error;
+use url::Url;
+
+const PUB_V2_JSON: &str = "application/vnd.pub.v2+json";
+
+#[derive(Error, Debug)]
+pub enum ServeError {
+    #[error("Failed to listen for connections: {0}")]
+    BindError(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to read {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    PubCacheError(#[from] PubCacheError),
+    #[error("Invalid pubspec in {path}: {source}")]
+    InvalidPubspec {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[error("No pubspec.yaml in {0}")]
+    MissingPubspec(PathBuf),
+    #[error("Upstream listing failed: {0}")]
+    UpstreamError(#[from] PubClientError),
+    #[error("Upstream download failed: {0}")]
+    DownloadError(#[from] DownloadError),
+}
+
+/// A package version that has an archive in the cache
+#[derive(Debug)]
+pub struct CachedVersion {
+    pub version: PackageVersion,
+    pub archive_path: PathBuf,
+}
+
+/// Where a proxying repository fetches what isn't cached yet
+struct Upstream {
+    client: PubClient,
+    downloader: PackageDownloader,
+}
+
+/// The packages from one host in a `PubCache`, seen as a hosted repository
+pub struct CacheRepository {
+    cache: PubCache,
+    host: String,
+    upstream: Option<Upstream>,
+}
+
+impl CacheRepository {
+    pub fn new(cache: PubCache, host: impl Into<String>) -> Self {
+        CacheRepository {
+            cache,
+            host: host.into(),
+            upstream: None,
+        }
+    }
+
+    /// Makes this a pull-through proxy: listings come from `client`'s repository, and archives
+    /// missing from the cache are downloaded from it and cached, all checked against its listing
+    /// before being served
+    pub fn with_upstream(mut self, client: PubClient) -> Result<Self, ServeError> {
+        // Archives are fetched from upstream, but cached as the host's, like those of a mirror
+        let hosted_url =
+            Url::parse(&format!("https://{}", self.host)).map_err(PubCacheError::from)?;
+        let download_path = self.cache.download_path().as_ref().to_path_buf();
+        let downloader = PackageDownloader::new(&download_path)
+            .map_err(|e| ServeError::IoError {
+                path: download_path,
+                source: e,
+            })?
+            .with_client(PubClient::with_agent(hosted_url, client.http().clone()))
+            .with_mirrors(vec![client.hosted_url().clone()]);
+
+        self.upstream = Some(Upstream { client, downloader });
+        Ok(self)
+    }
+
+    /// The versions of `name` with a cached archive from the host being served, oldest first
+    pub fn versions(&self, name: &PackageName) -> Result<Vec<CachedVersion>, ServeError> {
+        let dir = self.cache.host_download_path(&self.host);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        // Package names can't contain '-', so the prefix can't match another package
+        let prefix = format!("{}-", name);
+        let entries = fs::read_dir(&dir).map_err(|e| ServeError::IoError {
+            path: dir.clone(),
+            source: e,
+        })?;
+
+        let mut versions: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let version = file_name
+                    .strip_prefix(&prefix)?
+                    .strip_suffix(".tar.gz")?
+                    .to_string();
+                Some(CachedVersion {
+                    version: PackageVersion::new(version),
+                    archive_path: entry.path(),
+                })
+            })
+            .collect();
+
+        versions.sort_by_cached_key(|v| semver::Version::parse(v.version.as_ref()).ok());
+        Ok(versions)
+    }
+
+    /// The cached archive of a package version. A proxy fetches it from upstream, or checks the
+    /// cached one against the upstream listing, falling back to the cache if upstream fails.
+    pub fn archive_path(
+        &self,
+        name: &PackageName,
+        version: &PackageVersion,
+    ) -> Result<Option<PathBuf>, ServeError> {
+        let path = self.cache.archive_path(&self.host, name, version);
+        let Some(upstream) = &self.upstream else {
+            return Ok(path.is_file().then_some(path));
+        };
+
+        // The downloader insists on someone listening for its progress
+        let (tx, _rx) = mpsc::channel();
+        match upstream.downloader.download_package(name, version, &tx) {
+            Ok(path) => {
+                self.record_sha256(name, version, &path)?;
+                Ok(Some(path))
+            }
+            Err(
+                DownloadError::PackageNotFound { .. }
+                | DownloadError::ListingError(PubClientError::PackageNotFound { .. }),
+            ) => Ok(None),
+            // A mismatched archive is removed, so one still there matched before upstream failed
+            Err(e) if path.is_file() && !matches!(e, DownloadError::HashMismatch { .. }) => {
+                eprintln!("Serving cached {} {}: {}", name, version, e);
+                Ok(Some(path))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The v2 listing of `name`, with archive URLs below `base_url`, or None if there's no such
+    /// package. A proxy passes on the upstream listing, or the cached versions if that fails.
+    pub fn listing(
+        &self,
+        name: &PackageName,
+        base_url: &Url,
+    ) -> Result<Option<serde_json::Value>, ServeError> {
+        if let Some(upstream) = &self.upstream {
+            match upstream.client.raw_listing(name) {
+                Ok(listing) => return Ok(Some(rewrite_archive_urls(listing, name, base_url))),
+                Err(PubClientError::PackageNotFound { .. }) => return Ok(None),
+                Err(e) => eprintln!("Serving cached versions of {}: {}", name, e),
+            }
+        }
+
+        let versions = self
+            .versions(name)?
+            .iter()
+            .map(|cached| self.version_json(name, cached, base_url))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The latest is the newest stable version, unless there are only prereleases
+        let latest = versions
+            .iter()
+            .rev()
+            .find(|v| {
+                v["version"]
+                    .as_str()
+                    .and_then(|v| semver::Version::parse(v).ok())
+                    .is_some_and(|v| v.pre.is_empty())
+            })
+            .or(versions.last())
+            .cloned();
+
+        Ok(latest.map(|latest| {
+            json!({
+                "name": name,
+                "latest": latest,
+                "versions": versions,
+            })
+        }))
+    }
+
+    fn version_json(
+        &self,
+        name: &PackageName,
+        cached: &CachedVersion,
+        base_url: &Url,
+    ) -> Result<serde_json::Value, ServeError> {
+        let io_error = |e| ServeError::IoError {
+            path: cached.archive_path.clone(),
+            source: e,
+        };
+        let archive_url = archive_url(base_url, name, &cached.version);
+        let published: DateTime<Utc> = fs::metadata(&cached.archive_path)
+            .and_then(|m| m.modified())
+            .map_err(io_error)?
+            .into();
+
+        Ok(json!({
+            "version": cached.version,
+            "pubspec": self.pubspec(name, cached)?,
+            "archive_url": archive_url.as_str(),
+            "archive_sha256": self.archive_sha256(name, cached)?,
+            "published": published,
+        }))
+    }
+
+    /// The pubspec as published, from the extracted package if there is one, or else the archive
+    fn pubspec(
+        &self,
+        name: &PackageName,
+        cached: &CachedVersion,
+    ) -> Result<serde_json::Value, ServeError> {
+        let extracted = self
+            .cache
+            .hosted_package_path(&self.host, name, &cached.version)
+            .join("pubspec.yaml");
+
+        let (path, contents) = if extracted.is_file() {
+            let contents = fs::read_to_string(&extracted).map_err(|e| ServeError::IoError {
+                path: extracted.clone(),
+                source: e,
+            })?;
+            (extracted, contents)
+        } else {
+            let contents = read_archived_pubspec(&cached.archive_path)?;
+            (cached.archive_path.clone(), contents)
+        };
+
+        serde_yaml::from_str(&contents).map_err(|e| ServeError::InvalidPubspec { path, source: e })
+    }
+
+    /// The hash recorded in `hosted-hashes`, recording it first if the archive has none yet
+    fn archive_sha256(
+        &self,
+        name: &PackageName,
+        cached: &CachedVersion,
+    ) -> Result<Sha256, ServeError> {
+        if let Some(hash) = self
+            .cache
+            .read_package_hash(&self.host, name, &cached.version)?
+        {
+            return Ok(hash);
+        }
+        self.record_sha256(name, &cached.version, &cached.archive_path)
+    }
+
+    /// Hashes an archive and records it in `hosted-hashes` for the host being served
+    fn record_sha256(
+        &self,
+        name: &PackageName,
+        version: &PackageVersion,
+        archive_path: &Path,
+    ) -> Result<Sha256, ServeError> {
+        let bytes = fs::read(archive_path).map_err(|e| ServeError::IoError {
+            path: archive_path.to_path_buf(),
+            source: e,
+        })?;
+        let hash = Sha256::new(hex::encode(sha2::Sha256::digest(&bytes)));
+        self.cache
+            .write_package_hash(&self.host, name, version, &hash)?;
+        Ok(hash)
+    }
+}
+
+fn archive_url(base_url: &Url, name: &PackageName, version: &PackageVersion) -> Url {
+    base_url
+        .join(&format!("/packages/{}/versions/{}.tar.gz", name, version))
+        .expect("package names and versions are valid in a URL path")
+}
+
+/// Points the archive URLs of an upstream listing at us, leaving everything else as it was
+fn rewrite_archive_urls(
+    mut listing: serde_json::Value,
+    name: &PackageName,
+    base_url: &Url,
+) -> serde_json::Value {
+    let rewrite = |version: &mut serde_json::Value| {
+        if let Some(number) = version["version"].as_str() {
+            let url = archive_url(base_url, name, &PackageVersion::new(number));
+            version["archive_url"] = json!(url.as_str());
+        }
+    };
+
+    if let Some(latest) = listing.get_mut("latest") {
+        rewrite(latest);
+    }
+    if let Some(versions) = listing.get_mut("versions").and_then(|v| v.as_array_mut()) {
+        versions.iter_mut().for_each(rewrite);
+    }
+    listing
+}
+
+fn read_archived_pubspec(archive_path: &Path) -> Result<String, ServeError> {
+    let io_error = |e| ServeError::IoError {
+        path: archive_path.to_path_buf(),
+        source: e,
+    };
+    let file = fs::File::open(archive_path).map_err(io_error)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let is_pubspec = entry
+            .path()
+            .map(|p| p.strip_prefix(".").unwrap_or(&p) == Path::new("pubspec.yaml"))
+            .unwrap_or(false);
+        if is_pubspec {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).map_err(io_error)?;
+            return Ok(contents);
+        }
+    }
+
+    Err(ServeError::MissingPubspec(archive_path.to_path_buf()))
+}
+
+/// Serves a `CacheRepository` over HTTP, as a Hosted Pub Repository (v2)
+pub struct Server {
+    http: tiny_http::Server,
+    repository: CacheRepository,
+}
+
+impl Server {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        repository: CacheRepository,
+    ) -> Result<Self, ServeError> {
+        let http = tiny_http::Server::http(address).map_err(ServeError::BindError)?;
+        Ok(Server { http, repository })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Handles requests until `unblock` is called. Can be called from several threads at once.
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            self.handle(request);
+        }
+    }
+
+    pub fn unblock(&self) {
+        self.http.unblock();
+    }
+
+    fn handle(&self, request: tiny_http::Request) {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let segments: Vec<_> = path.trim_start_matches('/').split('/').collect();
+
+        let response = match (request.method(), segments.as_slice()) {
+            (tiny_http::Method::Get, ["api", "packages", name]) => {
+                let base_url = self.base_url(&request);
+                match self.repository.listing(&PackageName::new(*name), &base_url) {
+                    Ok(Some(listing)) => json_response(200, &listing),
+                    Ok(None) => error_response(404, "NotFound", &format!("{} not found", name)),
+                    Err(e) => server_error(&path, e),
+                }
+            }
+            (tiny_http::Method::Get, ["packages", name, "versions", archive]) => {
+                let archive = match archive.strip_suffix(".tar.gz") {
+                    Some(version) => self
+                        .repository
+                        .archive_path(&PackageName::new(*name), &PackageVersion::new(version)),
+                    None => Ok(None),
+                };
+                let file = archive.and_then(|archive| {
+                    archive
+                        .map(|archive| {
+                            fs::File::open(&archive).map_err(|e| ServeError::IoError {
+                                path: archive,
+                                source: e,
+                            })
+                        })
+                        .transpose()
+                });
+                match file {
+                    Ok(Some(file)) => tiny_http::Response::from_file(file)
+                        .with_header(header("Content-Type", "application/octet-stream"))
+                        .boxed(),
+                    Ok(None) => error_response(404, "NotFound", &format!("{} not found", path)),
+                    Err(e) => server_error(&path, e),
+                }
+            }
+            (tiny_http::Method::Get, _) => {
+                error_response(404, "NotFound", &format!("{} not found", path))
+            }
+            _ => error_response(405, "MethodNotAllowed", "Only GET is supported"),
+        };
+
+        let _ = request.respond(response);
+    }
+
+    /// The URL clients reached us on, so archive URLs work from other machines on the network
+    fn base_url(&self, request: &tiny_http::Request) -> Url {
+        let host = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Host"))
+            .map(|h| h.value.to_string())
+            .or_else(|| self.local_addr().map(|a| a.to_string()))
+            .unwrap_or_default();
+
+        Url::parse(&format!("http://{}", host))
+            .unwrap_or_else(|_| Url::parse("http://localhost").expect("valid URL"))
+    }
+}
+
+fn header(name: &str, value: &str) -> tiny_http::Header {
+    tiny_http::Header::from_bytes(name, value).expect("valid header")
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> tiny_http::ResponseBox {
+    tiny_http::Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", PUB_V2_JSON))
+        .boxed()
+}
+
+/// An error in the format the repository spec asks for
+fn error_response(status: u16, code: &str, message: &str) -> tiny_http::ResponseBox {
+    json_response(
+        status,
+        &json!({"error": {"code": code, "message": message}}),
+    )
+}
+
+fn server_error(path: &str, error: ServeError) -> tiny_http::ResponseBox {
+    eprintln!("Error serving {}: {}", path, error);
+    match error {
+        ServeError::UpstreamError(_) | ServeError::DownloadError(_) => {
+            error_response(502, "BadGateway", &error.to_string())
+        }
+        _ => error_response(500, "InternalError", &error.to_string()),
+    }
+}
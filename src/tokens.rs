@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("Failed to read tokens from {path}: {source}")]
+    IoError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Failed to parse tokens from {path}: {source}")]
+    JsonError {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Invalid hosted URL: {0}")]
+    UrlParseError(#[from] url::ParseError),
+    #[error("Tokens can only be sent over https, or http to localhost: {0}")]
+    InsecureUrl(Url),
+    #[error("Could not find a configuration directory for pub-tokens.json")]
+    NoConfigDir,
+}
+
+/// A token for one hosted repository, as stored by `dart pub token add`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credential {
+    #[serde(with = "url_serde")]
+    pub url: Url,
+    /// The token itself, when it is stored in the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// The environment variable holding the token, when it isn't stored in the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl Credential {
+    pub fn with_token(url: Url, token: impl Into<String>) -> Self {
+        Credential {
+            url,
+            token: Some(token.into()),
+            env: None,
+        }
+    }
+
+    pub fn with_env_var(url: Url, env: impl Into<String>) -> Self {
+        Credential {
+            url,
+            token: None,
+            env: Some(env.into()),
+        }
+    }
+
+    /// The token to send, reading it from the environment if required
+    pub fn token(&self) -> Option<String> {
+        match (&self.token, &self.env) {
+            (Some(token), _) => Some(token.clone()),
+            (None, Some(var)) => env::var(var).ok(),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether requests to `url` belong to this credential's hosted repository
+    pub fn matches(&self, url: &Url) -> bool {
+        let base = url_key(&self.url);
+        let url = url.as_str();
+        url == base || url.starts_with(&format!("{}/", base))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TokensFile {
+    version: u32,
+    #[serde(default)]
+    hosted: Vec<Credential>,
+}
+
+/// The `pub-tokens.json` file shared with `dart pub token`
+#[derive(Debug, Clone)]
+pub struct TokenStore {
+    path: PathBuf,
+    credentials: Vec<Credential>,
+}
+
+impl TokenStore {
+    /// The location dart uses, e.g. `~/.config/dart/pub-tokens.json` on Linux
+    pub fn default_path() -> Result<PathBuf, TokenError> {
+        dirs::config_dir()
+            .map(|dir| dir.join("dart").join("pub-tokens.json"))
+            .ok_or(TokenError::NoConfigDir)
+    }
+
+    pub fn load_default() -> Result<Self, TokenError> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// Loads the tokens at `path`, which need not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TokenError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(TokenStore {
+                path,
+                credentials: Vec::new(),
+            });
+        }
+
+        let contents = fs::read_to_string(&path).map_err(|e| TokenError::IoError {
+            path: path.clone(),
+            source: e,
+        })?;
+        let file: TokensFile =
+            serde_json::from_str(&contents).map_err(|e| TokenError::JsonError {
+                path: path.clone(),
+                source: e,
+            })?;
+
+        Ok(TokenStore {
+            path,
+            credentials: file.hosted,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), TokenError> {
+        let file = TokensFile {
+            version: 1,
+            hosted: self.credentials.clone(),
+        };
+        let contents = serde_json::to_string_pretty(&file).map_err(|e| TokenError::JsonError {
+            path: self.path.clone(),
+            source: e,
+        })?;
+
+        self.path
+            .parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| fs::write(&self.path, contents))
+            .map_err(|e| TokenError::IoError {
+                path: self.path.clone(),
+                source: e,
+            })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn credentials(&self) -> &[Credential] {
+        &self.credentials
+    }
+
+    /// Adds a credential, replacing any existing one for the same hosted URL
+    pub fn add(&mut self, credential: Credential) -> Result<(), TokenError> {
+        let credential = Credential {
+            url: normalize_hosted_url(&credential.url)?,
+            ..credential
+        };
+        self.credentials
+            .retain(|c| url_key(&c.url) != url_key(&credential.url));
+        self.credentials.push(credential);
+        Ok(())
+    }
+
+    /// Removes the credential for a hosted URL, returning whether there was one
+    pub fn remove(&mut self, url: &Url) -> Result<bool, TokenError> {
+        let url = normalize_hosted_url(url)?;
+        let before = self.credentials.len();
+        self.credentials
+            .retain(|c| url_key(&c.url) != url_key(&url));
+        Ok(self.credentials.len() != before)
+    }
+
+    pub fn remove_all(&mut self) {
+        self.credentials.clear();
+    }
+
+    /// The credential to use for a request, preferring the most specific hosted URL
+    pub fn find(&self, url: &Url) -> Option<&Credential> {
+        self.credentials
+            .iter()
+            .filter(|c| c.matches(url))
+            .max_by_key(|c| c.url.as_str().len())
+    }
+}
+
+impl ureq::Middleware for TokenStore {
+    fn handle(
+        &self,
+        request: ureq::Request,
+        next: ureq::MiddlewareNext,
+    ) -> Result<ureq::Response, ureq::Error> {
+        let token = Url::parse(request.url())
+            .ok()
+            .and_then(|url| self.find(&url).and_then(Credential::token));
+
+        match token {
+            Some(token) if request.header("Authorization").is_none() => {
+                next.handle(request.set("Authorization", &format!("Bearer {}", token)))
+            }
+            _ => next.handle(request),
+        }
+    }
+}
+
+/// Checks a hosted URL is safe to send tokens to, and normalizes any trailing slash
+pub fn normalize_hosted_url(url: &Url) -> Result<Url, TokenError> {
+    let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    if url.scheme() != "https" && !(url.scheme() == "http" && local) {
+        return Err(TokenError::InsecureUrl(url.clone()));
+    }
+
+    Ok(Url::parse(url_key(url))?)
+}
+
+fn url_key(url: &Url) -> &str {
+    url.as_str().trim_end_matches('/')
+}
+
+mod url_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use url::Url;
+
+    pub fn serialize<S>(url: &Url, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(super::url_key(url))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Url, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let url_str = String::deserialize(deserializer)?;
+        Url::parse(&url_str).map_err(serde::de::Error::custom)
+    }
+}
@@ -1,4 +1,3 @@
-
 #[macro_export]
 macro_rules! stringy {
     ($name:ident) => {
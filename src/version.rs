@@ -0,0 +1,298 @@
+use semver::Version;
+use std::cmp::Ordering;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VersionError {
+    #[error("Invalid version {version}: {reason}")]
+    InvalidVersion { version: String, reason: String },
+    #[error("Invalid version constraint {0:?}")]
+    InvalidConstraint(String),
+}
+
+/// Parses a dart package version, which is a semantic version such as `1.2.3-dev.1+4`
+pub fn parse_version(version: &str) -> Result<Version, VersionError> {
+    Version::parse(version.trim()).map_err(|e| VersionError::InvalidVersion {
+        version: version.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// One end of a `VersionConstraint`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bound {
+    pub version: Version,
+    pub inclusive: bool,
+}
+
+/// A range of versions, written as in pubspecs: `any`, `1.2.3`, `^1.2.3` or `>=1.2.3 <2.0.0`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VersionConstraint {
+    pub min: Option<Bound>,
+    pub max: Option<Bound>,
+    /// Set when intersecting ranges left nothing
+    empty: bool,
+}
+
+impl VersionConstraint {
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn empty() -> Self {
+        VersionConstraint {
+            empty: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn exactly(version: Version) -> Self {
+        VersionConstraint {
+            min: Some(Bound {
+                version: version.clone(),
+                inclusive: true,
+            }),
+            max: Some(Bound {
+                version,
+                inclusive: true,
+            }),
+            empty: false,
+        }
+    }
+
+    /// `^version`: compatible versions, i.e. below the next major, or next minor before 1.0.0
+    pub fn compatible_with(version: Version) -> Self {
+        let max = caret_max(&version);
+        VersionConstraint {
+            min: Some(Bound {
+                version,
+                inclusive: true,
+            }),
+            max: Some(Bound {
+                version: max,
+                inclusive: false,
+            }),
+            empty: false,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, VersionError> {
+        let invalid = || VersionError::InvalidConstraint(text.to_string());
+        let trimmed = text.trim();
+        if trimmed == "any" {
+            return Ok(Self::any());
+        }
+
+        let mut constraint = Self::any();
+        let mut rest = trimmed;
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        while !rest.is_empty() {
+            let op = ["^", ">=", "<=", ">", "<"]
+                .into_iter()
+                .find(|op| rest.starts_with(op))
+                .unwrap_or("");
+            let after_op = rest[op.len()..].trim_start();
+            let end = after_op
+                .find(|c: char| c.is_whitespace() || c == '<' || c == '>' || c == '^')
+                .unwrap_or(after_op.len());
+            let version = parse_version(&after_op[..end]).map_err(|_| invalid())?;
+            rest = after_op[end..].trim_start();
+
+            let part = match op {
+                "^" => Self::compatible_with(version),
+                ">=" => Self::at_least(version, true),
+                ">" => Self::at_least(version, false),
+                "<=" => Self::below(version, true),
+                "<" => Self::below(version, false),
+                // A bare version is only valid on its own
+                _ if constraint == Self::any() && rest.is_empty() => Self::exactly(version),
+                _ => return Err(invalid()),
+            };
+            constraint = constraint.intersect(&part);
+        }
+
+        Ok(constraint)
+    }
+
+    fn at_least(version: Version, inclusive: bool) -> Self {
+        VersionConstraint {
+            min: Some(Bound { version, inclusive }),
+            ..Self::default()
+        }
+    }
+
+    fn below(version: Version, inclusive: bool) -> Self {
+        VersionConstraint {
+            max: Some(Bound { version, inclusive }),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_any(&self) -> bool {
+        !self.empty && self.min.is_none() && self.max.is_none()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    pub fn allows(&self, version: &Version) -> bool {
+        if self.empty {
+            return false;
+        }
+        if let Some(min) = &self.min {
+            match version.cmp(&min.version) {
+                Ordering::Less => return false,
+                Ordering::Equal if !min.inclusive => return false,
+                _ => {}
+            }
+        }
+        if let Some(max) = &self.max {
+            match version.cmp(&max.version) {
+                Ordering::Greater => return false,
+                Ordering::Equal if !max.inclusive => return false,
+                _ => {}
+            }
+            // Like dart, `<2.0.0` doesn't let in 2.0.0's prereleases, unless the minimum does
+            if !max.inclusive
+                && !version.pre.is_empty()
+                && max.version.pre.is_empty()
+                && same_release(version, &max.version)
+                && !self.min.as_ref().is_some_and(|min| {
+                    !min.version.pre.is_empty() && same_release(version, &min.version)
+                })
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The versions allowed by both constraints
+    pub fn intersect(&self, other: &VersionConstraint) -> VersionConstraint {
+        if self.empty || other.empty {
+            return Self::empty();
+        }
+
+        let min = match (&self.min, &other.min) {
+            (Some(a), Some(b)) => Some(tighter_min(a, b).clone()),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        let max = match (&self.max, &other.max) {
+            (Some(a), Some(b)) => Some(tighter_max(a, b).clone()),
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+
+        let empty = match (&min, &max) {
+            (Some(min), Some(max)) => match min.version.cmp(&max.version) {
+                Ordering::Greater => true,
+                Ordering::Equal => !(min.inclusive && max.inclusive),
+                Ordering::Less => false,
+            },
+            _ => false,
+        };
+
+        match empty {
+            true => Self::empty(),
+            false => VersionConstraint { min, max, empty },
+        }
+    }
+
+    /// The constraint spelled out with comparison operators, as in the `sdks` of a lockfile
+    pub fn to_range_string(&self) -> String {
+        if self.empty {
+            return "<empty>".to_string();
+        }
+        let min = self
+            .min
+            .as_ref()
+            .map(|b| format!("{}{}", if b.inclusive { ">=" } else { ">" }, b.version));
+        let max = self
+            .max
+            .as_ref()
+            .map(|b| format!("{}{}", if b.inclusive { "<=" } else { "<" }, b.version));
+        let parts: Vec<_> = min.into_iter().chain(max).collect();
+        match parts.is_empty() {
+            true => "any".to_string(),
+            false => parts.join(" "),
+        }
+    }
+
+    /// The constraint with its minimum raised to `version`, as `upgrade --tighten` does, or
+    /// `None` when it already starts there or doesn't allow `version` at all
+    pub fn tightened_to(&self, version: &Version) -> Option<VersionConstraint> {
+        if !self.allows(version) || self.min.as_ref().is_some_and(|min| &min.version == version) {
+            return None;
+        }
+        if self.is_any() {
+            return Some(Self::compatible_with(version.clone()));
+        }
+        Some(VersionConstraint {
+            min: Some(Bound {
+                version: version.clone(),
+                inclusive: true,
+            }),
+            ..self.clone()
+        })
+    }
+
+    /// Whether every version allowed by `other` is allowed by this constraint
+    pub fn allows_all(&self, other: &VersionConstraint) -> bool {
+        &self.intersect(other) == other
+    }
+}
+
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.empty {
+            return write!(f, "<empty>");
+        }
+        match (&self.min, &self.max) {
+            (None, None) => write!(f, "any"),
+            (Some(min), Some(max))
+                if min.inclusive && max.inclusive && min.version == max.version =>
+            {
+                write!(f, "{}", min.version)
+            }
+            (Some(min), Some(max))
+                if min.inclusive && !max.inclusive && max.version == caret_max(&min.version) =>
+            {
+                write!(f, "^{}", min.version)
+            }
+            _ => write!(f, "{}", self.to_range_string()),
+        }
+    }
+}
+
+fn caret_max(version: &Version) -> Version {
+    match version.major {
+        0 => Version::new(0, version.minor + 1, 0),
+        major => Version::new(major + 1, 0, 0),
+    }
+}
+
+fn same_release(a: &Version, b: &Version) -> bool {
+    (a.major, a.minor, a.patch) == (b.major, b.minor, b.patch)
+}
+
+fn tighter_min<'a>(a: &'a Bound, b: &'a Bound) -> &'a Bound {
+    match a.version.cmp(&b.version) {
+        Ordering::Greater => a,
+        Ordering::Less => b,
+        Ordering::Equal if !a.inclusive => a,
+        Ordering::Equal => b,
+    }
+}
+
+fn tighter_max<'a>(a: &'a Bound, b: &'a Bound) -> &'a Bound {
+    match a.version.cmp(&b.version) {
+        Ordering::Less => a,
+        Ordering::Greater => b,
+        Ordering::Equal if !a.inclusive => a,
+        Ordering::Equal => b,
+    }
+}
@@ -0,0 +1,238 @@
+#![allow(dead_code)]
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::Digest;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub enum MockResponse {
+    Ok {
+        content_type: &'static str,
+        body: Vec<u8>,
+    },
+    Status(u16),
+    Redirect(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub path: String,
+    pub authorization: Option<String>,
+}
+
+/// A minimal HTTP server standing in for a hosted pub repository
+pub struct MockServer {
+    pub url: String,
+    server: Arc<tiny_http::Server>,
+    routes: Arc<Mutex<HashMap<String, MockResponse>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let routes: Arc<Mutex<HashMap<String, MockResponse>>> = Default::default();
+        let requests: Arc<Mutex<Vec<RecordedRequest>>> = Default::default();
+
+        let (s, r, q) = (server.clone(), routes.clone(), requests.clone());
+        thread::spawn(move || {
+            for request in s.incoming_requests() {
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("Authorization"))
+                    .map(|h| h.value.to_string());
+                q.lock().unwrap().push(RecordedRequest {
+                    path: request.url().to_string(),
+                    authorization,
+                });
+
+                let routes = r.lock().unwrap();
+                let _ = match routes.get(request.url()) {
+                    Some(MockResponse::Ok { content_type, body }) => {
+                        request.respond(tiny_http::Response::from_data(body.clone()).with_header(
+                            tiny_http::Header::from_bytes("Content-Type", *content_type).unwrap(),
+                        ))
+                    }
+                    Some(MockResponse::Status(code)) => {
+                        request.respond(tiny_http::Response::empty(*code))
+                    }
+                    Some(MockResponse::Redirect(location)) => {
+                        request.respond(tiny_http::Response::empty(302).with_header(
+                            tiny_http::Header::from_bytes("Location", location.as_str()).unwrap(),
+                        ))
+                    }
+                    None => request.respond(tiny_http::Response::empty(404)),
+                };
+            }
+        });
+
+        Self {
+            url,
+            server,
+            routes,
+            requests,
+        }
+    }
+
+    pub fn route(&self, path: &str, response: MockResponse) {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), response);
+    }
+
+    pub fn json(&self, path: &str, body: String) {
+        self.route(
+            path,
+            MockResponse::Ok {
+                content_type: "application/vnd.pub.v2+json",
+                body: body.into_bytes(),
+            },
+        );
+    }
+
+    pub fn bytes(&self, path: &str, body: Vec<u8>) {
+        self.route(
+            path,
+            MockResponse::Ok {
+                content_type: "application/octet-stream",
+                body,
+            },
+        );
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+/// A gzipped tarball containing the given files
+pub fn tar_gz(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, contents.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(sha2::Sha256::digest(bytes))
+}
+
+/// A version in a listing made by `listing_json`, which by default has no dependencies or SDK
+/// constraint, and an archive at example.com with a made-up hash
+#[derive(Clone)]
+pub struct ListedVersion {
+    version: String,
+    archive_url: Option<String>,
+    archive_sha256: Option<String>,
+    hashed: bool,
+    dependencies: serde_json::Value,
+    sdk: Option<String>,
+    retracted: bool,
+}
+
+impl ListedVersion {
+    pub fn new(version: &str) -> Self {
+        ListedVersion {
+            version: version.to_string(),
+            archive_url: None,
+            archive_sha256: None,
+            hashed: true,
+            dependencies: serde_json::json!({}),
+            sdk: None,
+            retracted: false,
+        }
+    }
+
+    pub fn with_archive(mut self, url: &str, sha256: &str) -> Self {
+        self.archive_url = Some(url.to_string());
+        self.archive_sha256 = Some(sha256.to_string());
+        self
+    }
+
+    /// An archive at `url` that the listing gives no hash for
+    pub fn with_unhashed_archive(mut self, url: &str) -> Self {
+        self.archive_url = Some(url.to_string());
+        self.hashed = false;
+        self
+    }
+
+    /// Dependencies as they are in a pubspec
+    pub fn with_dependencies(mut self, dependencies: serde_json::Value) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    /// The Dart SDK constraint in the pubspec's environment
+    pub fn with_sdk(mut self, sdk: &str) -> Self {
+        self.sdk = Some(sdk.to_string());
+        self
+    }
+
+    pub fn with_retracted(mut self, retracted: bool) -> Self {
+        self.retracted = retracted;
+        self
+    }
+
+    fn to_json(&self, name: &str) -> serde_json::Value {
+        let version = &self.version;
+        let mut pubspec = serde_json::json!({
+            "name": name,
+            "version": version,
+            "dependencies": self.dependencies,
+        });
+        if let Some(sdk) = &self.sdk {
+            pubspec["environment"] = serde_json::json!({"sdk": sdk});
+        }
+        let archive_url = self
+            .archive_url
+            .clone()
+            .unwrap_or_else(|| format!("https://example.com/{}-{}.tar.gz", name, version));
+        let mut json = serde_json::json!({
+            "version": version,
+            "retracted": self.retracted,
+            "pubspec": pubspec,
+            "archive_url": archive_url,
+            "published": "2024-01-01T00:00:00Z",
+        });
+        if self.hashed {
+            json["archive_sha256"] = serde_json::json!(
+                self.archive_sha256
+                    .clone()
+                    .unwrap_or_else(|| format!("{}-{}", name, version))
+            );
+        }
+        json
+    }
+}
+
+/// A v2 listing for `name` with the given versions, the last being the latest
+pub fn listing_value(name: &str, versions: &[ListedVersion]) -> serde_json::Value {
+    let versions: Vec<_> = versions.iter().map(|v| v.to_json(name)).collect();
+    serde_json::json!({
+        "name": name,
+        "latest": versions.last().unwrap(),
+        "versions": versions,
+    })
+}
+
+pub fn listing_json(name: &str, versions: &[ListedVersion]) -> String {
+    listing_value(name, versions).to_string()
+}
@@ -0,0 +1,52 @@
+use flutter_pub::config::Config;
+use std::io::Write;
+use tempfile::NamedTempFile;
+use url::Url;
+
+#[test]
+fn test_load_config() {
+    let mut temp_file = NamedTempFile::new().expect("Failed to create temporary file");
+    temp_file
+        .write_all(
+            b"mirrors:\n  - https://mirror-one.example.com\n  - https://mirror-two.example.com/pub\n",
+        )
+        .expect("Failed to write to temporary file");
+
+    let config = Config::from_file(temp_file.path()).expect("Failed to load config");
+    assert_eq!(config.mirrors.len(), 2);
+    assert_eq!(
+        config.mirrors[1],
+        Url::parse("https://mirror-two.example.com/pub").unwrap()
+    );
+}
+
+#[test]
+fn test_empty_config() {
+    let mut temp_file = NamedTempFile::new().expect("Failed to create temporary file");
+    temp_file.write_all(b"{}").unwrap();
+
+    let config = Config::from_file(temp_file.path()).expect("Failed to load config");
+    assert!(config.mirrors.is_empty());
+}
+
+#[test]
+fn test_pub_dev_sources() {
+    let config = Config {
+        mirrors: vec![Url::parse("https://mirror.example.com").unwrap()],
+        ..Config::default()
+    };
+
+    let sources = config.pub_dev_sources(None);
+    assert_eq!(
+        sources,
+        vec![
+            Url::parse("https://pub.dev").unwrap(),
+            Url::parse("https://mirror.example.com").unwrap()
+        ]
+    );
+
+    let internal = Url::parse("https://pub.internal.example.com").unwrap();
+    let sources = config.pub_dev_sources(Some(internal.clone()));
+    assert_eq!(sources[0], internal);
+    assert_eq!(sources.len(), 2);
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::deps::{DependencyGraph, DepsError, DepsKind};
+    use flutter_pub::project::Project;
+    use flutter_pub::pubcache::PubCache;
+    use flutter_pub::pubspecedit::Section;
+    use flutter_pub::pubspeclock::PackageName;
+    use flutter_pub::sdk::SdkPaths;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    const LOCK: &str = r#"packages:
+  foo:
+    dependency: "direct main"
+    description:
+      name: foo
+      sha256: "aa"
+      url: "https://pub.dev"
+    source: hosted
+    version: "1.2.0"
+  bar:
+    dependency: "direct dev"
+    description:
+      name: bar
+      sha256: "bb"
+      url: "https://pub.dev"
+    source: hosted
+    version: "2.0.0"
+  baz:
+    dependency: transitive
+    description:
+      name: baz
+      sha256: "cc"
+      url: "https://pub.dev"
+    source: hosted
+    version: "0.3.1"
+sdks:
+  dart: ">=3.4.0 <4.0.0"
+"#;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// A project that depends on foo and, for development, bar, which both depend on baz
+    fn graph(temp_dir: &TempDir) -> Result<DependencyGraph, DepsError> {
+        let app = temp_dir.path().join("app");
+        write(
+            &app.join("pubspec.yaml"),
+            "name: app\nversion: 1.0.0\ndependencies:\n  foo: ^1.0.0\ndev_dependencies:\n  bar: ^2.0.0\n",
+        );
+        write(&app.join("pubspec.lock"), LOCK);
+        write(&app.join("bin/app.dart"), "void main() {}\n");
+
+        let cache = PubCache::new(temp_dir.path().join("cache")).unwrap();
+        let hosted = cache.root_path().join("hosted/pub.dev");
+        write(
+            &hosted.join("foo-1.2.0/pubspec.yaml"),
+            "name: foo\ndependencies:\n  baz: ^0.3.0\ndev_dependencies:\n  test: any\n",
+        );
+        write(
+            &hosted.join("foo-1.2.0/bin/generate.dart"),
+            "void main() {}\n",
+        );
+        write(
+            &hosted.join("bar-2.0.0/pubspec.yaml"),
+            "name: bar\ndependencies:\n  baz: any\n",
+        );
+        write(&hosted.join("baz-0.3.1/pubspec.yaml"), "name: baz\n");
+
+        let project = Project::load(&app).unwrap();
+        DependencyGraph::load(&project, &cache, &SdkPaths::new())
+    }
+
+    #[test]
+    fn test_styles() {
+        let temp_dir = TempDir::new().unwrap();
+        let graph = graph(&temp_dir).unwrap();
+
+        assert_eq!(
+            graph.tree(),
+            "app 1.0.0\n├── bar 2.0.0\n│   └── baz 0.3.1\n└── foo 1.2.0\n    └── baz...\n"
+        );
+        assert_eq!(
+            graph.list(),
+            "app 1.0.0\n\ndependencies:\n- foo 1.2.0\n  - baz ^0.3.0\n\ndev dependencies:\n- bar 2.0.0\n  - baz any\n\ntransitive dependencies:\n- baz 0.3.1\n"
+        );
+        assert_eq!(
+            graph.compact(),
+            "app 1.0.0\n\ndependencies:\n- foo 1.2.0 [baz]\n\ndev dependencies:\n- bar 2.0.0 [baz]\n\ntransitive dependencies:\n- baz 0.3.1\n"
+        );
+        assert_eq!(graph.executables(), vec!["app", "foo:generate"]);
+    }
+
+    #[test]
+    fn test_json_and_no_dev() {
+        let temp_dir = TempDir::new().unwrap();
+        let graph = graph(&temp_dir).unwrap();
+
+        let json = graph.to_json();
+        assert_eq!(json["root"], "app");
+        assert_eq!(
+            json["packages"][0],
+            serde_json::json!({
+                "name": "app",
+                "version": "1.0.0",
+                "kind": "root",
+                "source": "root",
+                "dependencies": ["bar", "foo"],
+                "directDependencies": ["foo"],
+                "devDependencies": ["bar"],
+            })
+        );
+        assert_eq!(json["packages"].as_array().unwrap().len(), 4);
+
+        let graph = graph.without_dev();
+        assert_eq!(graph.root.dependencies, vec![PackageName::new("foo")]);
+        let kinds: Vec<_> = graph.packages.values().map(|p| p.kind).collect();
+        assert_eq!(kinds, vec![DepsKind::Transitive, DepsKind::Direct]);
+    }
+
+    #[test]
+    fn test_paths_to() {
+        let temp_dir = TempDir::new().unwrap();
+        let graph = graph(&temp_dir).unwrap();
+
+        let paths = graph.paths_to(&PackageName::new("baz"));
+        let described: Vec<Vec<_>> = paths
+            .iter()
+            .map(|path| {
+                path.iter()
+                    .map(|edge| format!("{} {} {}", edge.to, edge.constraint, edge.version))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                vec!["bar ^2.0.0 2.0.0", "baz any 0.3.1"],
+                vec!["foo ^1.0.0 1.2.0", "baz ^0.3.0 0.3.1"],
+            ]
+        );
+        assert_eq!(paths[0][0].section, Section::DevDependencies);
+        assert_eq!(
+            paths[0][0].pubspec,
+            temp_dir.path().join("app/pubspec.yaml")
+        );
+        assert_eq!(paths[1][0].section, Section::Dependencies);
+        assert_eq!(
+            paths[1][1].pubspec,
+            temp_dir
+                .path()
+                .join("cache/hosted/pub.dev/foo-1.2.0/pubspec.yaml")
+        );
+
+        assert!(graph.paths_to(&PackageName::new("test")).is_empty());
+    }
+
+    #[test]
+    fn test_uncached_package() {
+        let temp_dir = TempDir::new().unwrap();
+        graph(&temp_dir).unwrap();
+        fs::remove_dir_all(temp_dir.path().join("cache/hosted/pub.dev/baz-0.3.1")).unwrap();
+
+        let project = Project::load(temp_dir.path().join("app")).unwrap();
+        let cache = PubCache::new(temp_dir.path().join("cache")).unwrap();
+        let result = DependencyGraph::load(&project, &cache, &SdkPaths::new());
+        assert!(matches!(result, Err(DepsError::NotCached { .. })));
+    }
+}
@@ -1,10 +1,19 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use flutter_pub::downloader::{DownloadEvent, PackageDownloader};
-    use flutter_pub::pubspeclock::{PackageName, PackageVersion};
+    use crate::common::{
+        ListedVersion, MockResponse, MockServer, listing_json, sha256_hex, tar_gz,
+    };
+    use flutter_pub::downloader::{
+        DownloadError, DownloadEvent, PackageDownload, PackageDownloader,
+    };
+    use flutter_pub::pubclient::PubClient;
+    use flutter_pub::pubspeclock::{PackageName, PackageVersion, Sha256};
     use std::sync::mpsc;
     use tempfile::TempDir;
     use threadpool::ThreadPool;
+    use url::Url;
 
     #[test]
     fn test_download_single_package() {
@@ -30,9 +39,18 @@ mod tests {
         let pool = ThreadPool::new(4);
         let downloader = PackageDownloader::new(temp_dir.path()).unwrap();
 
+        let pub_dev = Url::parse("https://pub.dev").unwrap();
         let packages = vec![
-            (PackageName::new("path"), PackageVersion::new("1.8.3")),
-            (PackageName::new("http"), PackageVersion::new("0.13.6")),
+            PackageDownload::new(
+                PackageName::new("path"),
+                PackageVersion::new("1.8.3"),
+                pub_dev.clone(),
+            ),
+            PackageDownload::new(
+                PackageName::new("http"),
+                PackageVersion::new("0.13.6"),
+                pub_dev,
+            ),
         ];
         let (tx, _rx) = mpsc::channel();
 
@@ -41,7 +59,7 @@ mod tests {
 
         let expected_files: std::collections::HashSet<String> = packages
             .iter()
-            .map(|(name, version)| format!("{}-{}.tar.gz", name, version))
+            .map(|p| format!("{}-{}.tar.gz", p.name, p.version))
             .collect();
 
         for result in results {
@@ -168,4 +186,352 @@ mod tests {
             DownloadEvent::Failed { package, error: _ } if package == "nonexistent-package-0.0.1"
         ));
     }
+
+    fn mock_downloader(server: &MockServer, cache: &TempDir) -> PackageDownloader {
+        PackageDownloader::new(cache.path())
+            .unwrap()
+            .with_client(PubClient::new(Url::parse(&server.url).unwrap()))
+    }
+
+    #[test]
+    fn test_download_follows_archive_url_redirects() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\nversion: 1.0.0\n")]);
+        let archive_url = format!("{}/storage/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, &sha256_hex(&archive))],
+            ),
+        );
+        server.route(
+            "/storage/foo-1.0.0.tar.gz",
+            MockResponse::Redirect(format!("{}/blobs/abc", server.url)),
+        );
+        server.bytes("/blobs/abc", archive.clone());
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, _rx) = mpsc::channel();
+
+        let path = downloader
+            .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+            .unwrap();
+
+        let port = Url::parse(&server.url).unwrap().port().unwrap();
+        let dir = format!("127.0.0.1%58{}", port);
+        assert_eq!(path, temp_dir.path().join(dir).join("foo-1.0.0.tar.gz"));
+        assert_eq!(std::fs::read(&path).unwrap(), archive);
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/api/packages/foo",
+                "/storage/foo-1.0.0.tar.gz",
+                "/blobs/abc"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_download_rejects_hash_mismatch() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let archive_url = format!("{}/archives/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, &"0".repeat(64))],
+            ),
+        );
+        server.bytes("/archives/foo-1.0.0.tar.gz", archive);
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, rx) = mpsc::channel();
+
+        let result = downloader.download_package(
+            &PackageName::new("foo"),
+            &PackageVersion::new("1.0.0"),
+            &tx,
+        );
+        drop(tx);
+
+        assert!(matches!(result, Err(DownloadError::HashMismatch { .. })));
+        assert!(matches!(
+            rx.iter().last(),
+            Some(DownloadEvent::Failed { .. })
+        ));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_download_version_missing_from_listing() {
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive("/archives/foo-1.0.0.tar.gz", "aa")],
+            ),
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, _rx) = mpsc::channel();
+
+        let result = downloader.download_package(
+            &PackageName::new("foo"),
+            &PackageVersion::new("2.0.0"),
+            &tx,
+        );
+
+        assert!(matches!(result, Err(DownloadError::PackageNotFound { .. })));
+    }
+
+    #[test]
+    fn test_download_relative_archive_url() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0")
+                    .with_archive("/archives/foo-1.0.0.tar.gz", &sha256_hex(&archive))],
+            ),
+        );
+        server.bytes("/archives/foo-1.0.0.tar.gz", archive);
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, _rx) = mpsc::channel();
+
+        let result = downloader.download_package(
+            &PackageName::new("foo"),
+            &PackageVersion::new("1.0.0"),
+            &tx,
+        );
+
+        assert!(result.unwrap().exists());
+    }
+
+    #[test]
+    fn test_download_without_listed_hash() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let archive_url = format!("{}/archives/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_unhashed_archive(&archive_url)],
+            ),
+        );
+        server.bytes("/archives/foo-1.0.0.tar.gz", archive);
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, _rx) = mpsc::channel();
+
+        let result = downloader.download_package(
+            &PackageName::new("foo"),
+            &PackageVersion::new("1.0.0"),
+            &tx,
+        );
+
+        assert!(result.unwrap().exists());
+    }
+
+    fn serve_foo(server: &MockServer, archive: &[u8], listed_sha256: &str) {
+        let archive_url = format!("{}/archives/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, listed_sha256)],
+            ),
+        );
+        server.bytes("/archives/foo-1.0.0.tar.gz", archive.to_vec());
+    }
+
+    #[test]
+    fn test_download_falls_back_to_mirror() {
+        let primary = MockServer::start();
+        primary.route("/api/packages/foo", MockResponse::Status(500));
+        let mirror = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        serve_foo(&mirror, &archive, &sha256_hex(&archive));
+
+        let temp_dir = TempDir::new().unwrap();
+        let pub_dev = Url::parse("https://pub.dev").unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path())
+            .unwrap()
+            .with_mirrors(vec![
+                Url::parse(&primary.url).unwrap(),
+                Url::parse(&mirror.url).unwrap(),
+            ]);
+        let (tx, _rx) = mpsc::channel();
+
+        let download = PackageDownload::new(
+            PackageName::new("foo"),
+            PackageVersion::new("1.0.0"),
+            pub_dev,
+        )
+        .with_sha256(Sha256::new(sha256_hex(&archive)));
+        let path = downloader.download_package_from(&download, &tx).unwrap();
+
+        assert_eq!(std::fs::read(path).unwrap(), archive);
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(mirror.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_download_skips_mirror_not_matching_locked_hash() {
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let tampered = tar_gz(&[("pubspec.yaml", "name: foo\nevil: true\n")]);
+
+        // The first mirror is self-consistent, but doesn't match the lockfile
+        let bad = MockServer::start();
+        serve_foo(&bad, &tampered, &sha256_hex(&tampered));
+        let good = MockServer::start();
+        serve_foo(&good, &archive, &sha256_hex(&archive));
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path())
+            .unwrap()
+            .with_mirrors(vec![
+                Url::parse(&bad.url).unwrap(),
+                Url::parse(&good.url).unwrap(),
+            ]);
+        let (tx, _rx) = mpsc::channel();
+
+        let download = PackageDownload::new(
+            PackageName::new("foo"),
+            PackageVersion::new("1.0.0"),
+            Url::parse("https://pub.dev").unwrap(),
+        )
+        .with_sha256(Sha256::new(sha256_hex(&archive)));
+        let path = downloader.download_package_from(&download, &tx).unwrap();
+
+        assert_eq!(std::fs::read(path).unwrap(), archive);
+    }
+
+    #[test]
+    fn test_download_reports_last_mirror_error() {
+        let primary = MockServer::start();
+        let mirror = MockServer::start();
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path())
+            .unwrap()
+            .with_mirrors(vec![
+                Url::parse(&primary.url).unwrap(),
+                Url::parse(&mirror.url).unwrap(),
+            ]);
+        let (tx, rx) = mpsc::channel();
+
+        let result = downloader.download_package(
+            &PackageName::new("foo"),
+            &PackageVersion::new("1.0.0"),
+            &tx,
+        );
+        drop(tx);
+
+        assert!(matches!(result, Err(DownloadError::PackageNotFound { .. })));
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(mirror.requests().len(), 1);
+
+        let failures = rx
+            .iter()
+            .filter(|e| matches!(e, DownloadEvent::Failed { .. }))
+            .count();
+        assert_eq!(failures, 1);
+    }
+
+    #[test]
+    fn test_mirrors_only_apply_to_default_host() {
+        let mirror = MockServer::start();
+        let other = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        serve_foo(&other, &archive, &sha256_hex(&archive));
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path())
+            .unwrap()
+            .with_mirrors(vec![Url::parse(&mirror.url).unwrap()]);
+        let (tx, _rx) = mpsc::channel();
+
+        let download = PackageDownload::new(
+            PackageName::new("foo"),
+            PackageVersion::new("1.0.0"),
+            Url::parse(&other.url).unwrap(),
+        );
+        downloader.download_package_from(&download, &tx).unwrap();
+
+        assert!(mirror.requests().is_empty());
+    }
+
+    #[test]
+    fn test_cached_archives_are_kept_per_repository() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let other = tar_gz(&[("pubspec.yaml", "name: foo\ndescription: other\n")]);
+        serve_foo(&first, &archive, &sha256_hex(&archive));
+        serve_foo(&second, &other, &sha256_hex(&other));
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path()).unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let download = |server: &MockServer| {
+            let download = PackageDownload::new(
+                PackageName::new("foo"),
+                PackageVersion::new("1.0.0"),
+                Url::parse(&server.url).unwrap(),
+            );
+            downloader.download_package_from(&download, &tx).unwrap()
+        };
+
+        assert_eq!(std::fs::read(download(&first)).unwrap(), archive);
+        assert_eq!(std::fs::read(download(&second)).unwrap(), other);
+    }
+
+    #[test]
+    fn test_cached_archive_checked_against_listing() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        serve_foo(&server, &archive, &sha256_hex(&archive));
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = mock_downloader(&server, &temp_dir);
+        let (tx, _rx) = mpsc::channel();
+        let download = PackageDownload::new(
+            PackageName::new("foo"),
+            PackageVersion::new("1.0.0"),
+            Url::parse(&server.url).unwrap(),
+        );
+
+        // A cached archive that matches is used as it is
+        let path = downloader.download_package_from(&download, &tx).unwrap();
+        downloader.download_package_from(&download, &tx).unwrap();
+        let archive_fetches = || {
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.path.starts_with("/archives/"))
+                .count()
+        };
+        assert_eq!(archive_fetches(), 1);
+
+        // One that doesn't is fetched again
+        let tampered = tar_gz(&[("pubspec.yaml", "name: foo\nevil: true\n")]);
+        std::fs::write(&path, tampered).unwrap();
+        downloader.download_package_from(&download, &tx).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), archive);
+        assert_eq!(archive_fetches(), 2);
+    }
 }
@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::drift::{DriftPolicy, DriftReport};
+    use flutter_pub::project::Project;
+    use flutter_pub::pubspeclock::PackageName;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// A project that depends on foo, locked at `foo` along with a transitive bar at `bar`
+    fn project(dir: &Path, constraint: &str, foo: &str, bar: &str) -> Project {
+        fs::create_dir_all(dir).unwrap();
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        fs::write(
+            dir.join("pubspec.yaml"),
+            format!("name: {}\ndependencies:\n  foo: {}\n", name, constraint),
+        )
+        .unwrap();
+        let package = |name: &str, dependency: &str, version: &str| {
+            format!(
+                "  {name}:\n    dependency: {dependency}\n    description:\n      name: {name}\n      sha256: \"00\"\n      url: \"https://pub.dev\"\n    source: hosted\n    version: \"{version}\"\n"
+            )
+        };
+        fs::write(
+            dir.join("pubspec.lock"),
+            format!(
+                "packages:\n{}{}sdks:\n  dart: \">=3.4.0 <4.0.0\"\n",
+                package("foo", "\"direct main\"", foo),
+                package("bar", "transitive", bar)
+            ),
+        )
+        .unwrap();
+        Project::load(dir).unwrap()
+    }
+
+    #[test]
+    fn test_report_and_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        let one = temp_dir.path().join("one");
+        let two = temp_dir.path().join("two");
+        let three = temp_dir.path().join("three");
+        let projects = vec![
+            project(&one, "^1.10.0", "1.10.0", "2.0.0"),
+            project(&two, "^1.9.0", "1.9.2", "2.0.0"),
+            project(&three, "^1.9.0", "1.10.0", "2.0.1"),
+        ];
+
+        let report = DriftReport::for_projects(&projects);
+        let foo = &report.packages[1];
+        assert_eq!(foo.package, PackageName::new("foo"));
+        let versions: Vec<_> = foo.versions.iter().map(|v| v.version.as_ref()).collect();
+        assert_eq!(versions, vec!["1.9.2", "1.10.0"]);
+        assert_eq!(foo.versions[1].projects[0].directory, one);
+        assert_eq!(foo.versions[1].projects[1].directory, three);
+        assert_eq!(
+            foo.versions[1].projects[1].constraint.as_deref(),
+            Some("^1.9.0")
+        );
+        assert_eq!(report.packages[0].versions[0].projects[0].section, None);
+
+        let table = report.to_table();
+        assert!(
+            table.contains(&format!("foo\n  1.9.2   {} (^1.9.0)\n", two.display())),
+            "{}",
+            table
+        );
+        assert!(table.contains(&format!("{} (transitive)", three.display())));
+
+        assert!(DriftPolicy::new().violations(&report).is_empty());
+        assert!(
+            DriftPolicy::new()
+                .with_max_versions(2)
+                .violations(&report)
+                .is_empty()
+        );
+        assert_eq!(
+            DriftPolicy::new()
+                .with_max_versions(1)
+                .violations(&report)
+                .len(),
+            2
+        );
+        assert_eq!(
+            DriftPolicy::new().with_max_drifted(1).violations(&report),
+            vec!["2 packages are locked at more than one version, more than 1"]
+        );
+        assert!(
+            DriftPolicy::new()
+                .with_max_drifted(1)
+                .with_ignored([PackageName::new("bar")])
+                .violations(&report)
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::git::{GitCache, GitError};
+    use flutter_pub::project::{Project, lock_file_of};
+    use flutter_pub::pubcache::PubCache;
+    use flutter_pub::pubspeclock::{GitPackage, PackageDescription, PackageName};
+    use flutter_pub::resolver::{PackageSources, ResolvedSource, Resolver};
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use tempfile::TempDir;
+    use url::Url;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn commit_file(repo: &Path, path: &str, contents: &str) -> String {
+        let file = repo.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, contents).unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "--quiet", "-m", path]);
+        git(repo, &["rev-parse", "HEAD"])
+    }
+
+    /// A repository with `foo` at its root and `bar` in `packages/bar`
+    fn repository(dir: &Path) -> (Url, String) {
+        git(dir, &["init", "--quiet", "-b", "main"]);
+        commit_file(dir, "pubspec.yaml", "name: foo\n");
+        let commit = commit_file(dir, "packages/bar/pubspec.yaml", "name: bar\n");
+        git(dir, &["tag", "v1.0.0"]);
+        (Url::from_directory_path(dir).unwrap(), commit)
+    }
+
+    fn package(url: &Url, reference: Option<&str>, path: Option<&str>) -> GitPackage {
+        GitPackage {
+            url: url.clone(),
+            ref_: reference.map(String::from),
+            resolved_ref: None,
+            path: path.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_install_default_ref() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, commit) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+
+        let checkout = GitCache::new(&cache)
+            .install(&PackageName::new("foo"), &package(&url, None, None))
+            .unwrap();
+
+        assert_eq!(checkout.commit, commit);
+        assert_eq!(
+            checkout.path,
+            cache_dir.path().join("git").join(format!("foo-{}", commit))
+        );
+        assert_eq!(
+            fs::read_to_string(checkout.path.join("pubspec.yaml")).unwrap(),
+            "name: foo\n"
+        );
+
+        let mirror = cache.git_mirror_path(&url);
+        assert!(mirror.starts_with(cache_dir.path().join("git").join("cache")));
+        assert_eq!(git(&mirror, &["rev-parse", "--is-bare-repository"]), "true");
+    }
+
+    #[test]
+    fn test_install_with_path() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, commit) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+
+        let checkout = GitCache::new(&cache)
+            .install(
+                &PackageName::new("bar"),
+                &package(&url, Some("v1.0.0"), Some("packages/bar")),
+            )
+            .unwrap();
+
+        assert_eq!(checkout.commit, commit);
+        assert_eq!(
+            checkout.path,
+            cache
+                .git_checkout_path(&PackageName::new("bar"), &commit)
+                .join("packages/bar")
+        );
+
+        let result = GitCache::new(&cache).install(
+            &PackageName::new("baz"),
+            &package(&url, None, Some("packages/baz")),
+        );
+        assert!(matches!(result, Err(GitError::NoPubspec { .. })));
+
+        let result = GitCache::new(&cache)
+            .install(&PackageName::new("baz"), &package(&url, None, Some("../x")));
+        assert!(matches!(result, Err(GitError::InvalidPath { .. })));
+    }
+
+    #[test]
+    fn test_branch_moves_after_fetch() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, first) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+        let git_cache = GitCache::new(&cache);
+        let foo = package(&url, Some("main"), None);
+
+        let checkout = git_cache.install(&PackageName::new("foo"), &foo).unwrap();
+        assert_eq!(checkout.commit, first);
+
+        let second = commit_file(
+            repo_dir.path(),
+            "pubspec.yaml",
+            "name: foo\nversion: 2.0.0\n",
+        );
+        let checkout = git_cache.install(&PackageName::new("foo"), &foo).unwrap();
+        assert_eq!(checkout.commit, second);
+        assert!(
+            cache
+                .git_checkout_path(&PackageName::new("foo"), &first)
+                .exists()
+        );
+
+        // The tag still names the first commit
+        assert_eq!(git_cache.resolve(&url, Some("v1.0.0")).unwrap(), first);
+        // A commit the mirror already has resolves without fetching
+        assert_eq!(git_cache.resolve(&url, Some(&first)).unwrap(), first);
+    }
+
+    #[test]
+    fn test_install_honors_resolved_ref() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, first) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+        let git_cache = GitCache::new(&cache);
+
+        commit_file(
+            repo_dir.path(),
+            "pubspec.yaml",
+            "name: foo\nversion: 2.0.0\n",
+        );
+        let locked = package(&url, Some("main"), None).with_resolved_ref(&first);
+
+        let checkout = git_cache
+            .install(&PackageName::new("foo"), &locked)
+            .unwrap();
+        assert_eq!(checkout.commit, first);
+        assert_eq!(
+            fs::read_to_string(checkout.path.join("pubspec.yaml")).unwrap(),
+            "name: foo\n"
+        );
+
+        let upgraded = git_cache
+            .upgrade(&PackageName::new("foo"), &locked)
+            .unwrap();
+        assert_ne!(upgraded.commit, first);
+    }
+
+    #[test]
+    fn test_resolve_keeps_commit_locked_by_dart() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, first) = repository(repo_dir.path());
+        commit_file(
+            repo_dir.path(),
+            "pubspec.yaml",
+            "name: foo\nversion: 2.0.0\n",
+        );
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+
+        let app_dir = TempDir::new().unwrap();
+        let app = app_dir.path();
+        fs::write(
+            app.join("pubspec.yaml"),
+            format!("name: app\ndependencies:\n  foo:\n    git: {}\n", url),
+        )
+        .unwrap();
+        // What dart writes for a git dependency without a ref or path
+        fs::write(
+            app.join("pubspec.lock"),
+            format!(
+                "packages:\n  foo:\n    dependency: \"direct main\"\n    description:\n      path: \".\"\n      ref: HEAD\n      resolved-ref: {}\n      url: \"{}\"\n    source: git\n    version: \"0.0.0\"\n",
+                first, url
+            ),
+        )
+        .unwrap();
+
+        let project = Project::load(app).unwrap();
+        let sources = PackageSources::new(ureq::agent(), Url::parse("https://pub.dev").unwrap())
+            .with_git(GitCache::new(&cache));
+        let root = project.root_package(sources.default_url()).unwrap();
+        let resolution = Resolver::new(&sources)
+            .with_lock(project.lock.as_ref().unwrap())
+            .resolve(&root)
+            .unwrap();
+
+        let foo = &resolution.packages[&PackageName::new("foo")];
+        assert!(matches!(
+            &foo.source,
+            ResolvedSource::Git { package, .. }
+                if package.resolved_ref.as_deref() == Some(first.as_str())
+        ));
+        let lock = lock_file_of(&resolution);
+        match &lock.packages[&PackageName::new("foo")].description {
+            Some(PackageDescription::Git(git)) => {
+                assert_eq!(git.ref_.as_deref(), Some("HEAD"));
+                assert_eq!(git.path.as_deref(), Some("."));
+            }
+            _ => panic!("Expected a git description"),
+        }
+    }
+
+    #[test]
+    fn test_sources_check_out_each_ref_once() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, first) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+        let sources = PackageSources::new(ureq::agent(), Url::parse("https://pub.dev").unwrap())
+            .with_git(GitCache::new(&cache));
+        let foo = PackageName::new("foo");
+
+        let (checkout, _) = sources
+            .git_package(&foo, &package(&url, None, None))
+            .unwrap();
+        assert_eq!(checkout.resolved_ref.as_deref(), Some(first.as_str()));
+
+        // The branch moving doesn't change what the same sources resolve it to
+        commit_file(
+            repo_dir.path(),
+            "pubspec.yaml",
+            "name: foo\nversion: 2.0.0\n",
+        );
+        let (checkout, local) = sources
+            .git_package(&foo, &package(&url, Some("HEAD"), Some(".")))
+            .unwrap();
+        assert_eq!(checkout.resolved_ref.as_deref(), Some(first.as_str()));
+        assert_eq!(local.version.to_string(), "0.0.0");
+    }
+
+    #[test]
+    fn test_unknown_ref() {
+        let repo_dir = TempDir::new().unwrap();
+        let (url, _) = repository(repo_dir.path());
+        let cache_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(cache_dir.path()).unwrap();
+
+        let result = GitCache::new(&cache)
+            .install(&PackageName::new("foo"), &package(&url, Some("nope"), None));
+        assert!(matches!(result, Err(GitError::UnknownRef { .. })));
+    }
+}
@@ -0,0 +1,216 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ListedVersion, listing_json};
+    use flutter_pub::http::{HttpClient, HttpError, HttpSettings, HttpSetupError};
+    use flutter_pub::pubclient::{PubClient, PubClientError};
+    use flutter_pub::pubspeclock::PackageName;
+    use rustls_pki_types::pem::PemObject;
+    use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::{fs, thread};
+    use tempfile::TempDir;
+    use url::Url;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    /// An https server for 127.0.0.1, with a certificate signed by the fixture CA,
+    /// answering every request with `body`
+    fn start_tls_server(body: String) -> String {
+        let certs = CertificateDer::pem_file_iter(fixture("server.pem"))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(fixture("server.key")).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(
+            rustls::crypto::ring::default_provider().into(),
+        )
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .unwrap();
+        let config = Arc::new(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = rustls::ServerConnection::new(config.clone()).unwrap();
+                let mut tls = rustls::StreamOwned::new(connection, stream);
+                let mut reader = BufReader::new(&mut tls);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                    line.clear();
+                }
+                let _ = write!(
+                    tls,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/vnd.pub.v2+json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            }
+        });
+
+        url
+    }
+
+    fn foo_listing() -> String {
+        listing_json(
+            "foo",
+            &[ListedVersion::new("1.0.0")
+                .with_archive("https://example.com/foo.tar.gz", &"0".repeat(64))],
+        )
+    }
+
+    #[test]
+    fn test_no_proxy_matching() {
+        let settings = HttpSettings {
+            no_proxy: vec![
+                "localhost".to_string(),
+                ".internal.example.com".to_string(),
+                "pub.example.org:8443".to_string(),
+            ],
+            ..HttpSettings::default()
+        };
+
+        assert!(settings.bypasses_proxy(&url("http://localhost:8080/x")));
+        assert!(settings.bypasses_proxy(&url("https://repo.internal.example.com")));
+        assert!(settings.bypasses_proxy(&url("https://internal.example.com")));
+        assert!(settings.bypasses_proxy(&url("https://pub.example.org:8443")));
+        assert!(!settings.bypasses_proxy(&url("https://pub.example.org")));
+        assert!(!settings.bypasses_proxy(&url("https://notinternal.example.com")));
+        assert!(!settings.bypasses_proxy(&url("https://pub.dev")));
+
+        let everything = HttpSettings {
+            no_proxy: vec!["*".to_string()],
+            ..HttpSettings::default()
+        };
+        assert!(everything.bypasses_proxy(&url("https://pub.dev")));
+    }
+
+    #[test]
+    fn test_proxy_chosen_by_scheme() {
+        let client = HttpClient::new(
+            HttpSettings {
+                http_proxy: Some("http://proxy.example.com:3128".to_string()),
+                https_proxy: Some("http://secure-proxy.example.com:3128".to_string()),
+                no_proxy: vec!["localhost".to_string()],
+                ca_bundle: None,
+            },
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            client.proxy_for(&url("http://pub.example.com")),
+            Some("http://proxy.example.com:3128")
+        );
+        assert_eq!(
+            client.proxy_for(&url("https://pub.dev")),
+            Some("http://secure-proxy.example.com:3128")
+        );
+        assert_eq!(client.proxy_for(&url("http://localhost:8080")), None);
+    }
+
+    #[test]
+    fn test_unreachable_proxy_is_a_proxy_error() {
+        // Bind then drop, so nothing is listening on the port
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let http = HttpClient::new(
+            HttpSettings {
+                http_proxy: Some(format!("http://127.0.0.1:{}", port)),
+                ..HttpSettings::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        let client = PubClient::with_agent(url("http://pub.example.com"), http);
+        let error = client.listing(&PackageName::new("foo")).unwrap_err();
+
+        assert!(
+            matches!(error, PubClientError::HttpError(HttpError::Proxy { .. })),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_untrusted_certificate() {
+        let server = start_tls_server(foo_listing());
+
+        let http = HttpClient::new(HttpSettings::default(), None).unwrap();
+        let client = PubClient::with_agent(url(&server), http);
+        let error = client.listing(&PackageName::new("foo")).unwrap_err();
+
+        assert!(
+            matches!(
+                error,
+                PubClientError::HttpError(HttpError::UntrustedCertificate { .. })
+            ),
+            "{:?}",
+            error
+        );
+        assert!(error.to_string().contains("SSL_CERT_FILE"));
+    }
+
+    #[test]
+    fn test_ca_bundle_is_trusted() {
+        let server = start_tls_server(foo_listing());
+
+        let http = HttpClient::new(
+            HttpSettings {
+                ca_bundle: Some(fixture("ca.pem")),
+                ..HttpSettings::default()
+            },
+            None,
+        )
+        .unwrap();
+        let client = PubClient::with_agent(url(&server), http);
+        let listing = client.listing(&PackageName::new("foo")).unwrap();
+
+        assert_eq!(listing.name, PackageName::new("foo"));
+    }
+
+    #[test]
+    fn test_bad_ca_bundle() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty = temp_dir.path().join("empty.pem");
+        fs::write(&empty, "not a certificate\n").unwrap();
+
+        let settings = |path: PathBuf| HttpSettings {
+            ca_bundle: Some(path),
+            ..HttpSettings::default()
+        };
+
+        assert!(matches!(
+            HttpClient::new(settings(empty), None),
+            Err(HttpSetupError::EmptyCaBundle { .. })
+        ));
+        assert!(matches!(
+            HttpClient::new(settings(temp_dir.path().join("missing.pem")), None),
+            Err(HttpSetupError::CaBundle { .. })
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::link::{self, Link, LinkError};
+    use flutter_pub::project::Project;
+    use flutter_pub::pubspec::PubspecOverrides;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write_pubspec(dir: &Path, contents: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("pubspec.yaml"), contents).unwrap();
+    }
+
+    /// `app` depends on `core` by version and on `ui` by path, and `ui` depends on `model` by
+    /// version
+    fn monorepo(root: &Path) -> Vec<Project> {
+        write_pubspec(
+            &root.join("apps/app"),
+            "name: app\ndependencies:\n  core: ^1.0.0\n  ui:\n    path: ../../packages/ui\ndependency_overrides:\n  http: 1.0.0\n",
+        );
+        write_pubspec(
+            &root.join("packages/ui"),
+            "name: ui\ndependencies:\n  model: ^1.0.0\ndev_dependencies:\n  core: any\n",
+        );
+        write_pubspec(&root.join("packages/core"), "name: core\nversion: 1.2.0\n");
+        write_pubspec(
+            &root.join("packages/model"),
+            "name: model\nversion: 1.0.0\n",
+        );
+        ["apps/app", "packages/ui", "packages/core", "packages/model"]
+            .iter()
+            .map(|dir| Project::load(root.join(dir)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_plan() {
+        let temp_dir = TempDir::new().unwrap();
+        let projects = monorepo(temp_dir.path());
+
+        let links = Link::plan(&projects);
+
+        assert_eq!(links.len(), 2);
+        let app = &links[0];
+        assert_eq!(app.path, projects[0].overrides_path());
+        let linked: Vec<_> = app
+            .linked
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+            .collect();
+        // model comes through ui, and ui's dev dependency on core doesn't count
+        assert_eq!(
+            linked,
+            vec![
+                ("core", "../../packages/core"),
+                ("model", "../../packages/model")
+            ]
+        );
+        // Those from pubspec.yaml are kept, as the file replaces them
+        let names: Vec<_> = app.overrides.dependency_overrides.keys().collect();
+        assert_eq!(names, vec!["http", "core", "model"]);
+
+        let ui = &links[1];
+        let linked: Vec<_> = ui.linked.keys().collect();
+        assert_eq!(linked, vec!["core", "model"]);
+        assert_eq!(ui.linked["core"], "../core");
+    }
+
+    #[test]
+    fn test_write_and_unlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let projects = monorepo(temp_dir.path());
+        let links = Link::plan(&projects);
+
+        links[0].write().unwrap();
+        let written = fs::read_to_string(&links[0].path).unwrap();
+        assert!(written.starts_with("# Generated by flutter-pub link"));
+        assert_eq!(
+            PubspecOverrides::from_file(&links[0].path).unwrap(),
+            links[0].overrides
+        );
+        let reloaded = Project::load(&projects[0].dir).unwrap();
+        assert_eq!(reloaded.overrides.as_ref(), Some(&links[0].overrides));
+
+        // Linking again replaces what it wrote before
+        links[0].write().unwrap();
+
+        assert!(link::unlink(&projects[0]).unwrap());
+        assert!(!links[0].path.exists());
+        assert!(!link::unlink(&projects[0]).unwrap());
+    }
+
+    #[test]
+    fn test_leaves_hand_written_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let projects = monorepo(temp_dir.path());
+        let links = Link::plan(&projects);
+        let hand_written = "dependency_overrides:\n  core:\n    path: /elsewhere/core\n";
+        fs::write(&links[0].path, hand_written).unwrap();
+
+        assert!(matches!(links[0].write(), Err(LinkError::NotGenerated(_))));
+        assert!(!link::unlink(&projects[0]).unwrap());
+        assert_eq!(fs::read_to_string(&links[0].path).unwrap(), hand_written);
+    }
+}
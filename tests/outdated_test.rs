@@ -0,0 +1,161 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ListedVersion, MockServer, listing_value};
+    use flutter_pub::outdated::{MonorepoPackage, OutdatedKind, OutdatedReport};
+    use flutter_pub::project::Project;
+    use flutter_pub::resolver::{PackageSources, Resolver};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+    use url::Url;
+
+    /// A listing of versions that have no dependencies, `(version, retracted)`
+    fn listing(name: &str, versions: &[(&str, bool)], extra: serde_json::Value) -> String {
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|(version, retracted)| ListedVersion::new(version).with_retracted(*retracted))
+            .collect();
+        let mut listing = listing_value(name, &versions);
+        listing
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        listing.to_string()
+    }
+
+    fn server() -> MockServer {
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", false),
+                    ("1.1.0", false),
+                    ("2.0.0", false),
+                    ("3.0.0-dev.1", false),
+                ],
+                serde_json::json!({"advisoriesUpdated": "2024-02-01T00:00:00Z"}),
+            ),
+        );
+        server.json(
+            "/api/packages/foo/advisories",
+            serde_json::json!({
+                "advisories": [{
+                    "id": "GHSA-1234",
+                    "affected": [{"package": {"ecosystem": "Pub", "name": "foo"}, "versions": ["1.0.0"]}],
+                }],
+                "advisoriesUpdated": "2024-02-01T00:00:00Z",
+            })
+            .to_string(),
+        );
+        server.json(
+            "/api/packages/bar",
+            listing(
+                "bar",
+                &[("1.0.0", false), ("1.0.1", true)],
+                serde_json::json!({"isDiscontinued": true, "replacedBy": "baz"}),
+            ),
+        );
+        server
+    }
+
+    /// A project that depends on foo ^1.0.0 and bar, locked at foo 1.0.0 and bar 1.0.1
+    fn project(dir: &Path, sources: &PackageSources) -> Project {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("pubspec.yaml"),
+            "name: app\ndependencies:\n  foo: ^1.0.0\ndev_dependencies:\n  bar: any\n",
+        )
+        .unwrap();
+        let project = Project::load(dir).unwrap();
+        let root = project.root_package(sources.default_url()).unwrap();
+        let resolution = Resolver::new(sources).resolve(&root).unwrap();
+        let lock = project
+            .lock_file(&resolution)
+            .to_yaml()
+            .replace("1.1.0", "1.0.0")
+            .replace("bar-1.0.0", "bar-1.0.1")
+            .replace("version: \"1.0.0\"\n  foo", "version: \"1.0.1\"\n  foo");
+        fs::write(project.lock_path(), lock).unwrap();
+        Project::load(dir).unwrap()
+    }
+
+    #[test]
+    fn test_report() {
+        let server = server();
+        let sources = PackageSources::new(ureq::agent(), Url::parse(&server.url).unwrap());
+        let temp_dir = TempDir::new().unwrap();
+        let project = project(temp_dir.path(), &sources);
+
+        let report = OutdatedReport::for_project(&sources, &project).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"packages": [
+                {
+                    "package": "foo",
+                    "kind": "direct",
+                    "isDiscontinued": false,
+                    "isCurrentRetracted": false,
+                    "isCurrentAffectedByAdvisory": true,
+                    "current": {"version": "1.0.0"},
+                    "upgradable": {"version": "1.1.0"},
+                    "resolvable": {"version": "2.0.0"},
+                    "latest": {"version": "2.0.0"},
+                },
+                {
+                    "package": "bar",
+                    "kind": "dev",
+                    "isDiscontinued": true,
+                    "isCurrentRetracted": true,
+                    "isCurrentAffectedByAdvisory": false,
+                    "current": {"version": "1.0.1"},
+                    "upgradable": {"version": "1.0.0"},
+                    "resolvable": {"version": "1.0.0"},
+                    "latest": {"version": "1.0.0"},
+                },
+            ]})
+        );
+
+        let table = report.to_table();
+        assert!(table.contains("\ndirect dependencies:\nfoo"), "{}", table);
+        assert!(table.contains("*1.0.0 (advisory)"), "{}", table);
+        assert!(
+            table.contains("bar (discontinued, replaced by baz)"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn test_monorepo() {
+        let server = server();
+        let sources = PackageSources::new(ureq::agent(), Url::parse(&server.url).unwrap());
+        let temp_dir = TempDir::new().unwrap();
+        let one = temp_dir.path().join("one");
+        let two = temp_dir.path().join("two");
+        let reports = vec![
+            (
+                one.clone(),
+                OutdatedReport::for_project(&sources, &project(&one, &sources)).unwrap(),
+            ),
+            (
+                two.clone(),
+                OutdatedReport::for_project(&sources, &project(&two, &sources)).unwrap(),
+            ),
+        ];
+        assert_eq!(reports[0].1.packages[0].kind, OutdatedKind::Direct);
+
+        let packages = MonorepoPackage::aggregate(&reports);
+        let foo = packages
+            .iter()
+            .find(|p| p.package.as_ref() == "foo")
+            .unwrap();
+        assert_eq!(foo.latest.as_ref().unwrap().to_string(), "2.0.0");
+        assert_eq!(foo.current["1.0.0"], vec![one, two]);
+        assert!(foo.is_outdated());
+    }
+}
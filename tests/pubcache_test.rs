@@ -1,9 +1,7 @@
 #[cfg(test)]
 mod tests {
     use flutter_pub::pubcache::PubCache;
-    use flutter_pub::pubspeclock::{
-        HostedPackage, PackageName, PackageVersion, Sha256,
-    };
+    use flutter_pub::pubspeclock::{HostedPackage, PackageName, PackageVersion, Sha256};
     use flutter_pub::scopeyscope::Let;
     use std::fs;
     use tempfile::TempDir;
@@ -24,7 +22,7 @@ mod tests {
         let desc = HostedPackage {
             name: PackageName::new("test_package"),
             url: Url::parse("https://pub.dev").unwrap(),
-            sha256: Sha256::new("abc123"),
+            sha256: Some(Sha256::new("abc123")),
         };
 
         let path = cache
@@ -52,7 +50,7 @@ mod tests {
         let desc = HostedPackage {
             name: PackageName::new("test_package"),
             url: Url::parse("https://pub.dev").unwrap(),
-            sha256: Sha256::new("abc123"),
+            sha256: Some(Sha256::new("abc123")),
         };
 
         let path = cache
@@ -127,9 +125,7 @@ mod tests {
         assert!(hash_file.exists());
 
         // Verify file content
-        let content = fs::read_to_string(hash_file)
-            .unwrap()
-            .let_(|it| Sha256::new(it));
+        let content = fs::read_to_string(hash_file).unwrap().let_(Sha256::new);
         assert_eq!(content, hash);
     }
 }
@@ -1,8 +1,8 @@
 #[cfg(test)]
 mod tests {
     use chrono::DateTime;
-    use flutter_pub::pubpackage::PubPackageVersion;
-    use flutter_pub::pubspeclock::{PackageVersion, Sha256};
+    use flutter_pub::pubpackage::{PubPackageListing, PubPackageVersion};
+    use flutter_pub::pubspeclock::{PackageName, PackageVersion, Sha256};
 
     #[test]
     fn test_parse_package_version() {
@@ -31,11 +31,113 @@ mod tests {
         assert_eq!(package.version, PackageVersion::new("0.13.6"));
         assert_eq!(
             package.archive_sha256,
-            Sha256::new("5895291c13fa8a3bd82e76d5627f69e0d85ca6a30dcac95c4ea19a5d555879c2")
+            Some(Sha256::new(
+                "5895291c13fa8a3bd82e76d5627f69e0d85ca6a30dcac95c4ea19a5d555879c2"
+            ))
         );
         assert_eq!(
             package.published,
             DateTime::parse_from_rfc3339("2023-05-01T17:54:17.086948Z").unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_package_version_without_hash() {
+        let json = r#"{
+            "version": "1.0.0",
+            "archive_url": "https://example.com/packages/foo/versions/1.0.0.tar.gz",
+            "published": "2024-01-01T00:00:00Z"
+        }"#;
+
+        let package = PubPackageVersion::from_json(json).unwrap();
+        assert_eq!(package.archive_sha256, None);
+    }
+
+    #[test]
+    fn test_parse_package_listing() {
+        let json = r#"{
+            "name": "old_thing",
+            "isDiscontinued": true,
+            "replacedBy": "new_thing",
+            "advisoriesUpdated": "2024-02-01T10:00:00.000Z",
+            "latest": {
+                "version": "1.1.0",
+                "pubspec": {"name": "old_thing", "version": "1.1.0"},
+                "archive_url": "https://pub.dev/api/archives/old_thing-1.1.0.tar.gz",
+                "archive_sha256": "bbbb",
+                "published": "2023-06-01T00:00:00.000Z"
+            },
+            "versions": [
+                {
+                    "version": "1.0.0",
+                    "pubspec": {
+                        "name": "old_thing",
+                        "version": "1.0.0",
+                        "dependencies": {"path": "^1.8.0"}
+                    },
+                    "archive_url": "https://pub.dev/api/archives/old_thing-1.0.0.tar.gz",
+                    "archive_sha256": "aaaa",
+                    "published": "2023-01-01T00:00:00.000Z"
+                },
+                {
+                    "version": "1.0.1",
+                    "retracted": true,
+                    "pubspec": {"name": "old_thing", "version": "1.0.1"},
+                    "archive_url": "https://pub.dev/api/archives/old_thing-1.0.1.tar.gz",
+                    "archive_sha256": "cccc",
+                    "published": "2023-02-01T00:00:00.000Z"
+                },
+                {
+                    "version": "1.1.0",
+                    "pubspec": {"name": "old_thing", "version": "1.1.0"},
+                    "archive_url": "https://pub.dev/api/archives/old_thing-1.1.0.tar.gz",
+                    "archive_sha256": "bbbb",
+                    "published": "2023-06-01T00:00:00.000Z"
+                }
+            ]
+        }"#;
+
+        let listing = PubPackageListing::from_json(json).unwrap();
+        assert_eq!(listing.name, PackageName::new("old_thing"));
+        assert_eq!(listing.latest.version, PackageVersion::new("1.1.0"));
+        assert!(listing.is_discontinued);
+        assert_eq!(listing.replaced_by, Some(PackageName::new("new_thing")));
+        assert!(listing.advisories_updated.is_some());
+        assert_eq!(listing.versions.len(), 3);
+
+        let retracted = listing.version(&PackageVersion::new("1.0.1")).unwrap();
+        assert!(retracted.retracted);
+
+        let available: Vec<_> = listing
+            .available_versions()
+            .map(|v| v.version.clone())
+            .collect();
+        assert_eq!(
+            available,
+            vec![PackageVersion::new("1.0.0"), PackageVersion::new("1.1.0")]
+        );
+
+        let pubspec = listing.versions[0].parse_pubspec().unwrap().unwrap();
+        assert!(pubspec.dependencies.contains_key("path"));
+    }
+
+    #[test]
+    fn test_parse_package_listing_defaults() {
+        let json = r#"{
+            "name": "plain",
+            "latest": {
+                "version": "2.0.0",
+                "archive_url": "https://example.com/plain-2.0.0.tar.gz",
+                "archive_sha256": "dddd",
+                "published": "2023-06-01T00:00:00Z"
+            },
+            "versions": []
+        }"#;
+
+        let listing = PubPackageListing::from_json(json).unwrap();
+        assert!(!listing.is_discontinued);
+        assert!(!listing.latest.retracted);
+        assert_eq!(listing.replaced_by, None);
+        assert_eq!(listing.advisories_updated, None);
+    }
 }
@@ -1,4 +1,7 @@
-use flutter_pub::pubspec::Pubspec;
+use flutter_pub::pubspec::{
+    DependencySpec, DetailedDependency, GitDependency, GitRepo, HostedDependency, PathDependency,
+    Pubspec, SdkDependency,
+};
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -43,7 +46,9 @@ flutter:
 "#;
 
     let mut temp_file = NamedTempFile::new().expect("Failed to create temporary file");
-    temp_file.write_all(yaml_content.as_bytes()).expect("Failed to write to temporary file");
+    temp_file
+        .write_all(yaml_content.as_bytes())
+        .expect("Failed to write to temporary file");
     temp_file.flush().expect("Failed to flush temporary file");
     temp_file
 }
@@ -56,7 +61,7 @@ fn test_load_pubspec() {
     // Test basic fields
     assert_eq!(pubspec.name, "my_flutter_app");
     assert_eq!(pubspec.version.as_deref(), Some("1.0.0+1"));
-    
+
     // Test environment
     let env = pubspec.environment.unwrap();
     assert_eq!(env.sdk, ">=2.12.0 <3.0.0");
@@ -70,7 +75,7 @@ fn test_load_pubspec() {
     // Test Flutter config
     let flutter_config = pubspec.flutter.unwrap();
     assert_eq!(flutter_config.uses_material_design, Some(true));
-    
+
     // Test assets
     let assets = flutter_config.assets.unwrap();
     assert!(assets.contains(&"assets/images/".to_string()));
@@ -86,7 +91,9 @@ fn test_load_pubspec() {
 fn test_load_invalid_yaml() {
     let invalid_yaml = r#"invalid: - yaml: content"#;
     let mut temp_file = NamedTempFile::new().expect("Failed to create temporary file");
-    temp_file.write_all(invalid_yaml.as_bytes()).expect("Failed to write to temporary file");
+    temp_file
+        .write_all(invalid_yaml.as_bytes())
+        .expect("Failed to write to temporary file");
     temp_file.flush().expect("Failed to flush temporary file");
 
     let result = Pubspec::from_file(temp_file.path());
@@ -97,4 +104,224 @@ fn test_load_invalid_yaml() {
 fn test_load_nonexistent_file() {
     let result = Pubspec::from_file("nonexistent_pubspec.yaml");
     assert!(result.is_err());
-}
\ No newline at end of file
+}
+
+fn parse_dependencies(yaml: &str) -> Result<Pubspec, String> {
+    serde_yaml::from_str(&format!("name: app\ndependencies:\n{}", yaml)).map_err(|e| e.to_string())
+}
+
+fn dependency(yaml: &str) -> DependencySpec {
+    let pubspec = parse_dependencies(yaml).unwrap();
+    let (_, spec) = pubspec.dependencies.into_iter().next().unwrap();
+    spec
+}
+
+#[test]
+fn test_dependency_forms() {
+    assert_eq!(
+        dependency("  foo: ^1.0.0\n"),
+        DependencySpec::Simple("^1.0.0".to_string())
+    );
+    assert_eq!(
+        dependency("  foo:\n"),
+        DependencySpec::Simple("any".to_string())
+    );
+
+    assert_eq!(
+        dependency("  flutter:\n    sdk: flutter\n"),
+        DependencySpec::Sdk(SdkDependency {
+            sdk: "flutter".to_string(),
+            version: None
+        })
+    );
+
+    let hosted = DependencySpec::Detailed(DetailedDependency {
+        version: Some("^1.0.0".to_string()),
+        hosted: Some(HostedDependency {
+            name: None,
+            url: "https://pub.example.com".to_string(),
+        }),
+    });
+    assert_eq!(
+        dependency("  foo:\n    hosted: https://pub.example.com\n    version: ^1.0.0\n"),
+        hosted
+    );
+    assert_eq!(
+        dependency(
+            "  foo:\n    hosted:\n      name: foo\n      url: https://pub.example.com\n    version: ^1.0.0\n"
+        ),
+        DependencySpec::Detailed(DetailedDependency {
+            version: Some("^1.0.0".to_string()),
+            hosted: Some(HostedDependency {
+                name: Some("foo".to_string()),
+                url: "https://pub.example.com".to_string()
+            }),
+        })
+    );
+    assert_eq!(
+        dependency("  foo:\n    version: ^2.0.0\n"),
+        DependencySpec::Detailed(DetailedDependency {
+            version: Some("^2.0.0".to_string()),
+            hosted: None
+        })
+    );
+
+    let git = |ref_: Option<&str>, path: Option<&str>, version: Option<&str>| {
+        DependencySpec::Git(GitDependency {
+            git: GitRepo {
+                url: "https://github.com/x/y.git".to_string(),
+                ref_: ref_.map(String::from),
+                path: path.map(String::from),
+            },
+            version: version.map(String::from),
+        })
+    };
+    assert_eq!(
+        dependency("  foo:\n    git: https://github.com/x/y.git\n"),
+        git(None, None, None)
+    );
+    assert_eq!(
+        dependency(
+            "  foo:\n    git:\n      url: https://github.com/x/y.git\n      ref: main\n      path: packages/foo\n    version: ^1.0.0\n"
+        ),
+        git(Some("main"), Some("packages/foo"), Some("^1.0.0"))
+    );
+
+    assert_eq!(
+        dependency("  foo:\n    path: ../foo\n    version: 1.2.3\n"),
+        DependencySpec::Path(PathDependency {
+            path: "../foo".to_string(),
+            version: Some("1.2.3".to_string())
+        })
+    );
+}
+
+#[test]
+fn test_dependency_errors_name_the_key() {
+    let error = |yaml: &str| parse_dependencies(yaml).unwrap_err();
+
+    let message = error("  foo:\n    gti: https://github.com/x/y.git\n");
+    assert!(
+        message.contains("invalid dependency `foo`: unknown key `gti`"),
+        "{}",
+        message
+    );
+
+    let message = error("  foo:\n    git:\n      ref: main\n");
+    assert!(message.contains("`git.url` is missing"), "{}", message);
+
+    let message =
+        error("  foo:\n    git:\n      url: https://github.com/x/y.git\n      branch: main\n");
+    assert!(message.contains("unknown key `git.branch`"), "{}", message);
+
+    let message = error("  foo:\n    hosted:\n      name: foo\n");
+    assert!(message.contains("`hosted.url` is missing"), "{}", message);
+
+    let message = error("  foo:\n    path: ../foo\n    git: https://github.com/x/y.git\n");
+    assert!(
+        message.contains("can't have both `path` and `git`"),
+        "{}",
+        message
+    );
+
+    let message = error("  foo:\n    sdk:\n      - flutter\n");
+    assert!(message.contains("`sdk` must be a string"), "{}", message);
+
+    let message = error("  foo:\n    version: 1\n");
+    assert!(
+        message.contains("`version` must be a string"),
+        "{}",
+        message
+    );
+
+    let message = error("  foo: [1]\n");
+    assert!(message.contains("invalid dependency `foo`"), "{}", message);
+}
+
+#[test]
+fn test_round_trip_keeps_unknown_keys_and_order() {
+    let yaml = r#"name: app
+version: 1.2.0
+publish_to: none
+environment:
+  sdk: ^3.4.0
+  flutter: '>=3.22.0'
+dependencies:
+  zebra: ^1.0.0
+  apple:
+    hosted: https://pub.example.com
+    version: ^2.0.0
+  router:
+    git: https://github.com/x/router.git
+  flutter:
+    sdk: flutter
+flutter_launcher_icons:
+  android: true
+  image_path: assets/icon.png
+flutter:
+  uses-material-design: true
+  generate: true
+  assets:
+  - assets/
+topics:
+- widgets
+"#;
+    let pubspec = Pubspec::from_yaml(yaml).unwrap();
+    assert_eq!(pubspec.publish_to.as_deref(), Some("none"));
+    assert_eq!(pubspec.topics, Some(vec!["widgets".to_string()]));
+    assert!(pubspec.extra.contains_key("flutter_launcher_icons"));
+    assert!(
+        pubspec
+            .flutter
+            .as_ref()
+            .unwrap()
+            .extra
+            .contains_key("generate")
+    );
+    assert_eq!(
+        pubspec.dependencies.keys().collect::<Vec<_>>(),
+        ["zebra", "apple", "router", "flutter"]
+    );
+
+    assert_eq!(pubspec.to_yaml(), yaml);
+}
+
+#[test]
+fn test_new_keys_are_written_last() {
+    let mut pubspec = Pubspec::from_yaml(
+        "name: app
+dependencies:
+  foo: ^1.0.0
+",
+    )
+    .unwrap();
+    pubspec.description = Some("An app".to_string());
+    pubspec.dev_dependencies.insert(
+        "bar".to_string(),
+        DependencySpec::Simple("^2.0.0".to_string()),
+    );
+
+    assert_eq!(
+        pubspec.to_yaml(),
+        "name: app\ndependencies:\n  foo: ^1.0.0\ndescription: An app\ndev_dependencies:\n  bar: ^2.0.0\n"
+    );
+}
+
+#[test]
+fn test_with_constraint_keeps_the_source() {
+    let hosted = dependency("  foo:\n    hosted: https://pub.example.com\n    version: ^1.0.0\n");
+    assert_eq!(hosted.hosted_constraint(), Some("^1.0.0"));
+    assert_eq!(
+        hosted.with_constraint("^2.0.0"),
+        dependency("  foo:\n    hosted: https://pub.example.com\n    version: ^2.0.0\n")
+    );
+
+    assert_eq!(
+        dependency("  foo:\n").with_constraint("^1.2.0"),
+        DependencySpec::Simple("^1.2.0".to_string())
+    );
+    assert_eq!(
+        dependency("  foo:\n    path: ../foo\n").hosted_constraint(),
+        None
+    );
+}
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::pubspec::{DependencySpec, GitDependency, GitRepo};
+    use flutter_pub::pubspecedit::{DependencyRequest, EditError, PubspecEditor, Section};
+
+    const PUBSPEC: &str = r#"# The app
+name: app
+environment:
+  sdk: ^3.4.0
+
+dependencies:
+  # Keep this one pinned
+  apple: "1.0.0"
+  cherry: ^2.0.0 # the fork
+
+  zebra:
+    path: ../zebra
+
+dev_dependencies:
+  lints: ^4.0.0
+"#;
+
+    fn simple(constraint: &str) -> DependencySpec {
+        DependencySpec::Simple(constraint.to_string())
+    }
+
+    #[test]
+    fn test_add_keeps_everything_else() {
+        let mut editor = PubspecEditor::new(PUBSPEC);
+        editor
+            .set_dependency(Section::Dependencies, "banana", &simple("^1.2.0"))
+            .unwrap();
+
+        assert_eq!(
+            editor.contents(),
+            PUBSPEC.replace(
+                "  apple: \"1.0.0\"\n",
+                "  apple: \"1.0.0\"\n  banana: ^1.2.0\n"
+            )
+        );
+        assert!(
+            editor
+                .pubspec()
+                .unwrap()
+                .dependencies
+                .contains_key("banana")
+        );
+    }
+
+    #[test]
+    fn test_replace_keeps_the_comment() {
+        let mut editor = PubspecEditor::new(PUBSPEC);
+        editor
+            .set_dependency(Section::Dependencies, "cherry", &simple(">=2.1.0 <3.0.0"))
+            .unwrap();
+
+        assert!(
+            editor
+                .contents()
+                .contains("  cherry: '>=2.1.0 <3.0.0' # the fork\n\n  zebra:\n")
+        );
+    }
+
+    #[test]
+    fn test_add_map_dependencies() {
+        let mut editor = PubspecEditor::new(PUBSPEC);
+        let git = DependencySpec::Git(GitDependency {
+            git: GitRepo {
+                url: "https://github.com/x/router.git".to_string(),
+                ref_: Some("main".to_string()),
+                path: None,
+            },
+            version: None,
+        });
+        editor
+            .set_dependency(Section::DevDependencies, "router", &git)
+            .unwrap();
+        editor
+            .set_dependency(Section::DependencyOverrides, "apple", &simple("1.0.1"))
+            .unwrap();
+
+        assert!(editor.contents().ends_with(
+            "dev_dependencies:\n  lints: ^4.0.0\n  router:\n    git:\n      url: https://github.com/x/router.git\n      ref: main\n\ndependency_overrides:\n  apple: 1.0.1\n"
+        ));
+        let pubspec = editor.pubspec().unwrap();
+        assert_eq!(pubspec.dev_dependencies["router"], git);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut editor = PubspecEditor::new(PUBSPEC);
+        assert!(
+            editor
+                .remove_dependency(Section::Dependencies, "zebra")
+                .unwrap()
+        );
+        assert!(
+            !editor
+                .remove_dependency(Section::Dependencies, "zebra")
+                .unwrap()
+        );
+        assert!(
+            editor
+                .remove_dependency(Section::DevDependencies, "lints")
+                .unwrap()
+        );
+
+        assert!(editor.contents().ends_with("  cherry: ^2.0.0 # the fork\n"));
+        assert!(!editor.contains(Section::Dependencies, "zebra").unwrap());
+        assert!(editor.contains(Section::Dependencies, "apple").unwrap());
+    }
+
+    #[test]
+    fn test_flow_sections() {
+        let mut editor = PubspecEditor::new("name: app\ndependencies: {}\n");
+        editor
+            .set_dependency(Section::Dependencies, "apple", &simple("^1.0.0"))
+            .unwrap();
+        assert_eq!(
+            editor.contents(),
+            "name: app\ndependencies:\n  apple: ^1.0.0\n"
+        );
+
+        let mut editor = PubspecEditor::new("name: app\ndependencies: {apple: ^1.0.0}\n");
+        assert!(matches!(
+            editor.set_dependency(Section::Dependencies, "banana", &simple("^1.0.0")),
+            Err(EditError::UnsupportedLayout(_))
+        ));
+    }
+
+    #[test]
+    fn test_align() {
+        let pubspec = format!(
+            "{}  cherry: ^2.0.0\n\ndependency_overrides:\n  cherry:\n    version: 2.0.1 # pinned\n    hosted: https://example.com\n",
+            PUBSPEC
+        );
+        let mut editor = PubspecEditor::new(&pubspec);
+        assert_eq!(
+            editor.align("cherry", "^2.1.0").unwrap(),
+            Section::ALL.to_vec()
+        );
+        assert_eq!(
+            editor.contents(),
+            pubspec
+                .replace("cherry: ^2.0.0", "cherry: ^2.1.0")
+                .replace("version: 2.0.1", "version: ^2.1.0")
+        );
+
+        assert!(editor.align("cherry", "^2.1.0").unwrap().is_empty());
+        assert!(editor.align("zebra", "^1.0.0").unwrap().is_empty());
+
+        editor.align("cherry", ">=2.1.0 <3.0.0").unwrap();
+        assert!(editor.contents().ends_with(
+            "    version: '>=2.1.0 <3.0.0' # pinned\n    hosted: https://example.com\n"
+        ));
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let request = DependencyRequest::parse("foo").unwrap();
+        assert_eq!(request.section, Section::Dependencies);
+        assert_eq!(request.spec, None);
+
+        let request = DependencyRequest::parse("dev:foo:^1.2.0").unwrap();
+        assert_eq!(request.section, Section::DevDependencies);
+        assert_eq!(request.name, "foo");
+        assert_eq!(request.spec, Some(simple("^1.2.0")));
+
+        let request = DependencyRequest::parse("override:foo:{path: ../foo}").unwrap();
+        assert_eq!(request.section, Section::DependencyOverrides);
+        assert!(matches!(request.spec, Some(DependencySpec::Path(_))));
+
+        let request = DependencyRequest::parse("foo:{git: https://github.com/x/foo.git}").unwrap();
+        assert!(matches!(request.spec, Some(DependencySpec::Git(_))));
+
+        assert!(DependencyRequest::parse("Foo").is_err());
+        assert!(DependencyRequest::parse("foo:banana").is_err());
+        assert!(DependencyRequest::parse("foo:{gti: x}").is_err());
+    }
+}
@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use flutter_pub::pubspeclock::{HostedPackage, PackageDescription, PackageName, PackageVersion, PubspecLock, Sha256};
+    use flutter_pub::pubspeclock::{
+        GitPackage, HostedPackage, PackageDescription, PackageName, PackageVersion, PubspecLock,
+        Sha256,
+    };
     use std::fs::File;
     use std::io::Write;
     use tempfile::TempDir;
@@ -61,7 +64,10 @@ packages:
         assert!(lock_file.sdks.is_some());
         assert_eq!(lock_file.packages.len(), 4);
 
-        let adaptive_pkg = lock_file.packages.get(&PackageName::new("adaptive_number")).unwrap();
+        let adaptive_pkg = lock_file
+            .packages
+            .get(&PackageName::new("adaptive_number"))
+            .unwrap();
         assert_eq!(adaptive_pkg.version, PackageVersion::new("1.0.0"));
         match &adaptive_pkg.description.as_ref().unwrap() {
             PackageDescription::Hosted(HostedPackage { name, url, sha256 }) => {
@@ -69,8 +75,10 @@ packages:
                 let expected_url = Url::parse("https://pub.dev").unwrap();
                 assert!(url.eq(&expected_url));
                 assert_eq!(
-                    sha256,
-                    &Sha256::new("3a567544e9b5c9c803006f51140ad544aedc79604fd4f3f2c1380003f97c1d77")
+                    sha256.as_ref(),
+                    Some(&Sha256::new(
+                        "3a567544e9b5c9c803006f51140ad544aedc79604fd4f3f2c1380003f97c1d77"
+                    ))
                 );
             }
             _ => panic!("Expected Hosted variant"),
@@ -82,7 +90,10 @@ packages:
         let http_pkg = lock_file.packages.get(&PackageName::new("http")).unwrap();
         assert_eq!(http_pkg.version, PackageVersion::new("0.13.6"));
 
-        let flutter_pkg = lock_file.packages.get(&PackageName::new("flutter")).unwrap();
+        let flutter_pkg = lock_file
+            .packages
+            .get(&PackageName::new("flutter"))
+            .unwrap();
         assert_eq!(flutter_pkg.version, PackageVersion::new("0.0.0"));
         assert_eq!(flutter_pkg.source, "sdk");
         assert_eq!(flutter_pkg.dependency, "direct main");
@@ -93,4 +104,77 @@ packages:
             _ => panic!("Expected Sdk variant for Flutter package"),
         }
     }
+
+    const DART_LOCK: &str = r#"# Generated by pub
+# See https://dart.dev/tools/pub/glossary#lockfile
+packages:
+  flutter:
+    dependency: "direct main"
+    description: flutter
+    source: sdk
+    version: "0.0.0"
+  my_git:
+    dependency: "direct main"
+    description:
+      path: "packages/my_git"
+      ref: main
+      resolved-ref: "4c8a1e6b0f3e4ad2a6b1a9e2f0c9d8e7b6a5f4e3"
+      url: "https://github.com/example/monorepo.git"
+    source: git
+    version: "1.2.0"
+  path:
+    dependency: transitive
+    description:
+      name: path
+      sha256: "1234"
+      url: "https://pub.dev"
+    source: hosted
+    version: "1.8.3"
+  shared:
+    dependency: "direct main"
+    description:
+      path: "../shared"
+      relative: true
+    source: path
+    version: "0.1.0"
+sdks:
+  dart: ">=3.0.0 <4.0.0"
+  flutter: ">=3.10.0"
+"#;
+
+    #[test]
+    fn test_git_description() {
+        let lock: PubspecLock = serde_yaml::from_str(DART_LOCK).unwrap();
+        let spec = lock.packages.get(&PackageName::new("my_git")).unwrap();
+
+        match spec.description.as_ref().unwrap() {
+            PackageDescription::Git(GitPackage {
+                url,
+                ref_,
+                resolved_ref,
+                path,
+            }) => {
+                assert_eq!(url.as_str(), "https://github.com/example/monorepo.git");
+                assert_eq!(ref_.as_deref(), Some("main"));
+                assert_eq!(
+                    resolved_ref.as_deref(),
+                    Some("4c8a1e6b0f3e4ad2a6b1a9e2f0c9d8e7b6a5f4e3")
+                );
+                assert_eq!(path.as_deref(), Some("packages/my_git"));
+            }
+            other => panic!("Expected Git variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_writes_lock_like_dart() {
+        let lock: PubspecLock = serde_yaml::from_str(DART_LOCK).unwrap();
+        assert_eq!(lock.to_yaml(), DART_LOCK);
+
+        let temp_dir = TempDir::new().unwrap();
+        let lock_path = temp_dir.path().join("pubspec.lock");
+        lock.write_to_file(&lock_path).unwrap();
+        let reread = PubspecLock::from_file(&lock_path).unwrap();
+        assert_eq!(reread.to_yaml(), DART_LOCK);
+    }
 }
@@ -0,0 +1,969 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{ListedVersion, MockServer, listing_json, listing_value};
+    use flutter_pub::project::{Project, ProjectError};
+    use flutter_pub::pubcache::PubCache;
+    use flutter_pub::pubspeclock::{PackageDescription, PackageName, PubspecLock};
+    use flutter_pub::resolver::{
+        DependencyKind, PackageSources, Preference, Resolution, ResolveError, ResolvedSource,
+        Resolver,
+    };
+    use flutter_pub::scanner::ScannerError;
+    use flutter_pub::sdk::SdkPaths;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+    use url::Url;
+
+    fn write_pubspec(dir: &Path, contents: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("pubspec.yaml"), contents).unwrap();
+    }
+
+    /// A listing whose versions are `(version, dependencies)`, with dependencies as pubspec YAML values
+    fn listing(name: &str, versions: &[(&str, serde_json::Value)]) -> String {
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|(version, dependencies)| {
+                ListedVersion::new(version).with_dependencies(dependencies.clone())
+            })
+            .collect();
+        listing_json(name, &versions)
+    }
+
+    fn resolve(sources: &PackageSources, dir: &Path) -> Result<Resolution, ResolveError> {
+        let project = Project::load(dir).unwrap();
+        let root = project.root_package(sources.default_url())?;
+        let mut resolver = Resolver::new(sources);
+        if let Some(lock) = &project.lock {
+            resolver = resolver.with_lock(lock);
+        }
+        resolver.resolve(&root)
+    }
+
+    fn versions(resolution: &Resolution) -> Vec<(String, String)> {
+        resolution
+            .packages
+            .values()
+            .map(|p| (p.name.to_string(), p.version.to_string()))
+            .collect()
+    }
+
+    fn sources(server: &MockServer) -> PackageSources<'static> {
+        PackageSources::new(ureq::agent(), Url::parse(&server.url).unwrap())
+    }
+
+    /// A monorepo where `app` depends on `shared`, which depends on `util`, all by path
+    fn monorepo(root: &Path) {
+        write_pubspec(
+            &root.join("app"),
+            "name: app\nenvironment:\n  sdk: ^3.2.0\ndependencies:\n  shared:\n    path: ../shared\n",
+        );
+        write_pubspec(
+            &root.join("shared"),
+            "name: shared\nversion: 1.2.0\nenvironment:\n  sdk: '>=3.0.0 <4.0.0'\ndependencies:\n  util:\n    path: ../util\n",
+        );
+        write_pubspec(&root.join("util"), "name: util\nversion: 0.1.0\n");
+    }
+
+    #[test]
+    fn test_transitive_path_dependencies() {
+        let temp_dir = TempDir::new().unwrap();
+        monorepo(temp_dir.path());
+        let server = MockServer::start();
+
+        let resolution = resolve(&sources(&server), &temp_dir.path().join("app")).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("shared".to_string(), "1.2.0".to_string()),
+                ("util".to_string(), "0.1.0".to_string())
+            ]
+        );
+        let shared = &resolution.packages[&PackageName::new("shared")];
+        assert_eq!(shared.kind, DependencyKind::DirectMain);
+        assert_eq!(shared.language_version.as_deref(), Some("3.0"));
+        assert!(matches!(
+            &shared.source,
+            ResolvedSource::Path { relative: true, .. }
+        ));
+        assert_eq!(
+            resolution.packages[&PackageName::new("util")].kind,
+            DependencyKind::Transitive
+        );
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn test_writes_relative_lock_and_package_config() {
+        let temp_dir = TempDir::new().unwrap();
+        monorepo(temp_dir.path());
+        let server = MockServer::start();
+        let cache = PubCache::new(temp_dir.path().join("cache")).unwrap();
+        let app = temp_dir.path().join("app");
+
+        let resolution = resolve(&sources(&server), &app).unwrap();
+        let project = Project::load(&app).unwrap();
+        project.write(&resolution, &cache).unwrap();
+
+        let lock = PubspecLock::from_file(app.join("pubspec.lock")).unwrap();
+        let util = &lock.packages[&PackageName::new("util")];
+        assert_eq!(util.source, "path");
+        assert_eq!(util.dependency, "transitive");
+        match &util.description {
+            Some(PackageDescription::Path(path)) => {
+                assert_eq!(path.path, "../util");
+                assert!(path.relative);
+            }
+            other => panic!("Expected a path description, got {:?}", other),
+        }
+        // What both app and shared support
+        assert_eq!(lock.sdks.unwrap().dart.as_deref(), Some(">=3.2.0 <4.0.0"));
+
+        let config: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(app.join(".dart_tool/package_config.json")).unwrap(),
+        )
+        .unwrap();
+        let packages: Vec<_> = config["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| {
+                (
+                    p["name"].as_str().unwrap(),
+                    p["rootUri"].as_str().unwrap(),
+                    p["languageVersion"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            packages,
+            vec![
+                ("app", "../", Some("3.2")),
+                ("shared", "../../shared", Some("3.0")),
+                ("util", "../../util", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_path_dependency() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            temp_dir.path(),
+            "name: app\ndependencies:\n  nope:\n    path: ../nope\n",
+        );
+        let server = MockServer::start();
+
+        let result = resolve(&sources(&server), temp_dir.path());
+        assert!(matches!(result, Err(ResolveError::MissingPath { .. })));
+    }
+
+    #[test]
+    fn test_path_package_with_wrong_name() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            &temp_dir.path().join("app"),
+            "name: app\ndependencies:\n  shared:\n    path: ../other\n",
+        );
+        write_pubspec(&temp_dir.path().join("other"), "name: other\n");
+        let server = MockServer::start();
+
+        let result = resolve(&sources(&server), &temp_dir.path().join("app"));
+        assert!(matches!(result, Err(ResolveError::NameMismatch { .. })));
+    }
+
+    #[test]
+    fn test_hosted_dependencies_of_path_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            &temp_dir.path().join("app"),
+            "name: app\ndependencies:\n  shared:\n    path: ../shared\n  foo: '>=1.0.0 <1.2.0'\n",
+        );
+        write_pubspec(
+            &temp_dir.path().join("shared"),
+            "name: shared\ndependencies:\n  foo: ^1.1.0\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("1.1.0", serde_json::json!({})),
+                    ("1.2.0", serde_json::json!({})),
+                    ("1.3.0-dev.1", serde_json::json!({})),
+                ],
+            ),
+        );
+
+        let resolution = resolve(&sources(&server), &temp_dir.path().join("app")).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("foo".to_string(), "1.1.0".to_string()),
+                ("shared".to_string(), "0.0.0".to_string())
+            ]
+        );
+        assert!(matches!(
+            &resolution.packages[&PackageName::new("foo")].source,
+            ResolvedSource::Hosted { sha256, .. } if sha256.as_ref().map(|s| s.as_ref()) == Some("foo-1.1.0")
+        ));
+    }
+
+    #[test]
+    fn test_pubspec_overrides_replace_those_in_pubspec() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        write_pubspec(
+            &app,
+            "name: app\ndependencies:\n  shared: ^1.0.0\n  foo: ^1.0.0\ndependency_overrides:\n  foo: 1.0.0\n",
+        );
+        fs::write(
+            app.join("pubspec_overrides.yaml"),
+            "dependency_overrides:\n  shared:\n    path: ../shared\n",
+        )
+        .unwrap();
+        write_pubspec(
+            &temp_dir.path().join("shared"),
+            "name: shared\nversion: 1.1.0\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("1.1.0", serde_json::json!({})),
+                ],
+            ),
+        );
+
+        let resolution = resolve(&sources(&server), &app).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("foo".to_string(), "1.1.0".to_string()),
+                ("shared".to_string(), "1.1.0".to_string())
+            ]
+        );
+        let shared = &resolution.packages[&PackageName::new("shared")];
+        assert_eq!(shared.kind, DependencyKind::DirectOverridden);
+        assert!(matches!(shared.source, ResolvedSource::Path { .. }));
+    }
+
+    #[test]
+    fn test_backtracks_to_compatible_version() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            temp_dir.path(),
+            "name: app\ndependencies:\n  foo: any\n  bar: ^1.0.0\n",
+        );
+        let server = MockServer::start();
+        // foo 2.0.0 needs bar 2, so foo 1.0.0 has to be used instead
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({"bar": "^1.0.0"})),
+                    ("2.0.0", serde_json::json!({"bar": "^2.0.0"})),
+                ],
+            ),
+        );
+        server.json(
+            "/api/packages/bar",
+            listing(
+                "bar",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("2.0.0", serde_json::json!({})),
+                ],
+            ),
+        );
+
+        let resolution = resolve(&sources(&server), temp_dir.path()).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("bar".to_string(), "1.0.0".to_string()),
+                ("foo".to_string(), "1.0.0".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skips_versions_with_invalid_pubspecs() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(temp_dir.path(), "name: app\ndependencies:\n  foo: any\n");
+        let server = MockServer::start();
+        let mut foo = listing_value(
+            "foo",
+            &[ListedVersion::new("1.0.0"), ListedVersion::new("1.1.0")],
+        );
+        // Published long ago, when pubspecs were checked less carefully
+        foo["versions"][1]["pubspec"]["dependencies"] = serde_json::json!(["bar"]);
+        server.json("/api/packages/foo", foo.to_string());
+
+        let resolution = resolve(&sources(&server), temp_dir.path()).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![("foo".to_string(), "1.0.0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_conflict_names_requirements() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            &temp_dir.path().join("app"),
+            "name: app\ndependencies:\n  shared:\n    path: ../shared\n  foo: ^2.0.0\n",
+        );
+        write_pubspec(
+            &temp_dir.path().join("shared"),
+            "name: shared\ndependencies:\n  foo: ^1.0.0\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("2.0.0", serde_json::json!({})),
+                ],
+            ),
+        );
+
+        let error = resolve(&sources(&server), &temp_dir.path().join("app")).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("foo ^2.0.0 (from app)"), "{}", message);
+        assert!(message.contains("foo ^1.0.0 (from shared)"), "{}", message);
+    }
+
+    #[test]
+    fn test_resolve_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_pubspec(&root.join("one"), "name: one\ndependencies:\n  foo: any\n");
+        write_pubspec(
+            &root.join("two"),
+            "name: two\ndependencies:\n  one:\n    path: ../one\ndev_dependencies:\n  foo: ^1.0.0\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("2.0.0", serde_json::json!({})),
+                ],
+            ),
+        );
+        let sources = sources(&server);
+        let roots: Vec<_> = ["one", "two"]
+            .iter()
+            .map(|name| {
+                let project = Project::load(root.join(name)).unwrap();
+                project.root_package(sources.default_url()).unwrap()
+            })
+            .collect();
+
+        // Alone, one would get foo 2.0.0
+        let shared = Resolver::new(&sources).resolve_together(&roots).unwrap();
+        let [one, two] = shared.resolutions.as_slice() else {
+            panic!("expected a resolution for each root");
+        };
+        assert_eq!(
+            versions(one),
+            vec![("foo".to_string(), "1.0.0".to_string())]
+        );
+        assert_eq!(
+            versions(two),
+            vec![
+                ("foo".to_string(), "1.0.0".to_string()),
+                ("one".to_string(), "0.0.0".to_string())
+            ]
+        );
+        let foo = &PackageName::new("foo");
+        assert_eq!(one.packages[foo].kind, DependencyKind::DirectMain);
+        assert_eq!(two.packages[foo].kind, DependencyKind::DirectDev);
+        assert!(matches!(
+            two.packages[&PackageName::new("one")].source,
+            ResolvedSource::Path { .. }
+        ));
+
+        let combined = shared.combined(roots[0].clone());
+        assert_eq!(
+            versions(&combined),
+            vec![("foo".to_string(), "1.0.0".to_string())]
+        );
+        assert_eq!(combined.packages[foo].kind, DependencyKind::DirectMain);
+
+        write_pubspec(
+            &root.join("three"),
+            "name: three\ndependencies:\n  foo: ^2.0.0\n",
+        );
+        let three = Project::load(root.join("three"))
+            .unwrap()
+            .root_package(sources.default_url())
+            .unwrap();
+        let error = Resolver::new(&sources)
+            .resolve_together(&[roots[1].clone(), three.clone()])
+            .unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("foo ^1.0.0 (from two)"), "{}", message);
+        assert!(message.contains("foo ^2.0.0 (from three)"), "{}", message);
+
+        let mut duplicate = three.clone();
+        duplicate.dir = root.join("elsewhere");
+        assert!(matches!(
+            Resolver::new(&sources).resolve_together(&[three, duplicate]),
+            Err(ResolveError::DuplicateRoot { .. })
+        ));
+    }
+
+    #[test]
+    fn test_workspace() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_pubspec(
+            root,
+            "name: _\nenvironment:\n  sdk: ^3.6.0\nworkspace:\n  - pkgs/a\n  - pkgs/b\n",
+        );
+        write_pubspec(
+            &root.join("pkgs/a"),
+            "name: a\nversion: 1.0.0\nresolution: workspace\ndependencies:\n  foo: ^1.0.0\n",
+        );
+        write_pubspec(
+            &root.join("pkgs/b"),
+            "name: b\nresolution: workspace\ndependencies:\n  a:\n    path: ../a\ndev_dependencies:\n  foo: any\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("2.0.0", serde_json::json!({})),
+                ],
+            ),
+        );
+        let sources = sources(&server);
+        let cache = PubCache::new(root.join("cache")).unwrap();
+
+        let project = Project::load(root).unwrap();
+        let roots: Vec<_> = project
+            .workspace_packages()
+            .iter()
+            .map(|p| p.root_package(sources.default_url()).unwrap())
+            .collect();
+        assert_eq!(roots.len(), 3);
+        let resolution = Resolver::new(&sources)
+            .resolve_workspace(&roots)
+            .unwrap()
+            .workspace();
+        project.write(&resolution, &cache).unwrap();
+
+        let lock = PubspecLock::from_file(root.join("pubspec.lock")).unwrap();
+        let names: Vec<_> = lock.packages.keys().map(|n| n.to_string()).collect();
+        assert_eq!(names, vec!["foo"]);
+        assert_eq!(
+            lock.packages[&PackageName::new("foo")].version.as_ref(),
+            "1.0.0"
+        );
+        assert_eq!(
+            lock.packages[&PackageName::new("foo")].dependency,
+            "direct main"
+        );
+
+        let config: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(root.join(".dart_tool/package_config.json")).unwrap(),
+        )
+        .unwrap();
+        let packages: Vec<_> = config["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| (p["name"].as_str().unwrap(), p["rootUri"].as_str().unwrap()))
+            .collect();
+        assert_eq!(packages[0], ("_", "../"));
+        assert_eq!(packages[1], ("a", "../pkgs/a"));
+        assert_eq!(packages[2], ("b", "../pkgs/b"));
+
+        assert_eq!(
+            fs::read_to_string(root.join("pkgs/a/.dart_tool/pub/workspace_ref.json")).unwrap(),
+            "{\n  \"workspaceRoot\": \"../../../..\"\n}\n"
+        );
+        assert!(
+            root.join("pkgs/b/.dart_tool/pub/workspace_ref.json")
+                .exists()
+        );
+        assert!(!root.join(".dart_tool/pub/workspace_ref.json").exists());
+    }
+
+    #[test]
+    fn test_workspace_members_depend_on_each_other_by_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        write_pubspec(root, "name: _\nworkspace:\n  - pkgs/a\n  - pkgs/b\n");
+        write_pubspec(
+            &root.join("pkgs/a"),
+            "name: a\nversion: 1.2.0\nresolution: workspace\ndependencies:\n  b: ^2.0.0\n  foo: ^1.0.0\n",
+        );
+        write_pubspec(
+            &root.join("pkgs/b"),
+            "name: b\nversion: 2.0.0\nresolution: workspace\ndependencies:\n  a: ^1.0.0\n",
+        );
+        // Neither member is published, so they can only come from the workspace
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing("foo", &[("1.0.0", serde_json::json!({}))]),
+        );
+        let sources = sources(&server);
+
+        let project = Project::load(root).unwrap();
+        let roots: Vec<_> = project
+            .workspace_packages()
+            .iter()
+            .map(|p| p.root_package(sources.default_url()).unwrap())
+            .collect();
+        let shared = Resolver::new(&sources).resolve_workspace(&roots).unwrap();
+
+        let a = &shared.resolutions[1];
+        assert_eq!(
+            versions(a),
+            vec![
+                ("b".to_string(), "2.0.0".to_string()),
+                ("foo".to_string(), "1.0.0".to_string())
+            ]
+        );
+        assert!(matches!(
+            a.packages[&PackageName::new("b")].source,
+            ResolvedSource::Path { .. }
+        ));
+        let resolution = shared.workspace();
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("a".to_string(), "1.2.0".to_string()),
+                ("b".to_string(), "2.0.0".to_string()),
+                ("foo".to_string(), "1.0.0".to_string())
+            ]
+        );
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, vec!["/api/packages/foo"]);
+
+        // A member whose version doesn't fit is still a conflict
+        write_pubspec(
+            &root.join("pkgs/b"),
+            "name: b\nversion: 2.0.0\nresolution: workspace\ndependencies:\n  a: ^2.0.0\n",
+        );
+        let project = Project::load(root).unwrap();
+        let roots: Vec<_> = project
+            .workspace_packages()
+            .iter()
+            .map(|p| p.root_package(sources.default_url()).unwrap())
+            .collect();
+        let error = Resolver::new(&sources)
+            .resolve_workspace(&roots)
+            .unwrap_err();
+        assert!(
+            matches!(&error, ResolveError::NoVersion { name, .. } if name.as_ref() == "a"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_workspace_member_needs_resolution() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(temp_dir.path(), "name: _\nworkspace:\n  - a\n");
+        write_pubspec(&temp_dir.path().join("a"), "name: a\n");
+        assert!(matches!(
+            Project::load(temp_dir.path()),
+            Err(ProjectError::ScannerError(
+                ScannerError::NotAWorkspaceMember(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_prefers_locked_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(temp_dir.path(), "name: app\ndependencies:\n  foo: ^1.0.0\n");
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({})),
+                    ("1.1.0", serde_json::json!({})),
+                ],
+            ),
+        );
+        let sources = sources(&server);
+
+        let resolution = resolve(&sources, temp_dir.path()).unwrap();
+        assert_eq!(
+            resolution.packages[&PackageName::new("foo")]
+                .version
+                .to_string(),
+            "1.1.0"
+        );
+
+        // Pretend 1.0.0 was locked
+        let project = Project::load(temp_dir.path()).unwrap();
+        let lock = project
+            .lock_file(&resolution)
+            .to_yaml()
+            .replace("1.1.0", "1.0.0");
+        fs::write(temp_dir.path().join("pubspec.lock"), lock).unwrap();
+
+        let resolution = resolve(&sources, temp_dir.path()).unwrap();
+        assert_eq!(
+            resolution.packages[&PackageName::new("foo")]
+                .version
+                .to_string(),
+            "1.0.0"
+        );
+    }
+
+    #[test]
+    fn test_unlocking_named_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            temp_dir.path(),
+            "name: app\ndependencies:\n  foo: ^1.0.0\n  bar: ^1.0.0\n",
+        );
+        let server = MockServer::start();
+        for name in ["bar", "baz"] {
+            server.json(
+                &format!("/api/packages/{}", name),
+                listing(
+                    name,
+                    &[
+                        ("1.0.0", serde_json::json!({})),
+                        ("1.1.0", serde_json::json!({})),
+                    ],
+                ),
+            );
+        }
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("1.0.0", serde_json::json!({"baz": "^1.0.0"})),
+                    ("1.1.0", serde_json::json!({"baz": "^1.0.0"})),
+                ],
+            ),
+        );
+        let sources = sources(&server);
+
+        let project = Project::load(temp_dir.path()).unwrap();
+        let root = project.root_package(sources.default_url()).unwrap();
+        let newest = Resolver::new(&sources).resolve(&root).unwrap();
+        let lock = project
+            .lock_file(&newest)
+            .to_yaml()
+            .replace("1.1.0", "1.0.0");
+        fs::write(project.lock_path(), lock).unwrap();
+        let lock = PubspecLock::from_file(project.lock_path()).unwrap();
+
+        let foo = [PackageName::new("foo")];
+        let resolution = Resolver::new(&sources)
+            .with_lock(&lock)
+            .with_unlocked(&foo)
+            .resolve(&root)
+            .unwrap();
+        assert_eq!(
+            versions(&resolution),
+            [("bar", "1.0.0"), ("baz", "1.0.0"), ("foo", "1.1.0")]
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+
+        let transitive = resolution.with_dependencies(&foo);
+        assert_eq!(
+            transitive.iter().map(|n| n.as_ref()).collect::<Vec<_>>(),
+            ["baz", "foo"]
+        );
+        let resolution = Resolver::new(&sources)
+            .with_lock(&lock)
+            .with_unlocked(&transitive)
+            .resolve(&root)
+            .unwrap();
+        assert_eq!(
+            versions(&resolution),
+            [("bar", "1.0.0"), ("baz", "1.1.0"), ("foo", "1.1.0")]
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_oldest_versions_written_separately() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        write_pubspec(&app, "name: app\ndependencies:\n  foo: ^1.0.0\n");
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing(
+                "foo",
+                &[
+                    ("0.9.0", serde_json::json!({"bar": "^2.0.0"})),
+                    ("1.0.0", serde_json::json!({"bar": "^2.1.0"})),
+                    ("1.1.0", serde_json::json!({"bar": "^2.1.0"})),
+                ],
+            ),
+        );
+        server.json(
+            "/api/packages/bar",
+            listing(
+                "bar",
+                &[
+                    ("2.0.0", serde_json::json!({})),
+                    ("2.1.0", serde_json::json!({})),
+                    ("2.2.0", serde_json::json!({})),
+                ],
+            ),
+        );
+        let sources = sources(&server);
+        let cache = PubCache::new(temp_dir.path().join("cache")).unwrap();
+
+        let project = Project::load(&app).unwrap();
+        let root = project.root_package(sources.default_url()).unwrap();
+        let resolution = Resolver::new(&sources)
+            .with_preference(Preference::Oldest)
+            .resolve(&root)
+            .unwrap();
+        assert_eq!(
+            versions(&resolution),
+            [("bar", "2.1.0"), ("foo", "1.0.0")].map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+
+        project
+            .write_to(
+                &resolution,
+                &cache,
+                app.join("pubspec_downgrade.lock"),
+                app.join(".dart_tool/package_config_downgrade.json"),
+            )
+            .unwrap();
+        assert!(!project.lock_path().exists());
+        assert!(!project.package_config_path().exists());
+        let lock = PubspecLock::from_file(app.join("pubspec_downgrade.lock")).unwrap();
+        assert_eq!(
+            lock.packages[&PackageName::new("bar")].version.to_string(),
+            "2.1.0"
+        );
+        assert!(
+            app.join(".dart_tool/package_config_downgrade.json")
+                .is_file()
+        );
+    }
+
+    /// A Flutter SDK whose flutter package pins collection, like the real one does
+    fn fake_flutter(root: &Path) {
+        write_pubspec(
+            &root.join("packages/flutter"),
+            "name: flutter\nversion: 0.0.0\nenvironment:\n  sdk: ^3.4.0\ndependencies:\n  collection: 1.18.0\n  sky_engine:\n    sdk: flutter\n",
+        );
+        write_pubspec(
+            &root.join("packages/flutter_test"),
+            "name: flutter_test\ndependencies:\n  flutter:\n    sdk: flutter\n",
+        );
+        write_pubspec(&root.join("bin/cache/pkg/sky_engine"), "name: sky_engine\n");
+    }
+
+    #[test]
+    fn test_flutter_sdk_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        let flutter_root = temp_dir.path().join("flutter");
+        fake_flutter(&flutter_root);
+        let app = temp_dir.path().join("app");
+        write_pubspec(
+            &app,
+            "name: app\ndependencies:\n  flutter:\n    sdk: flutter\n  collection: ^1.17.0\ndev_dependencies:\n  flutter_test:\n    sdk: flutter\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/collection",
+            listing(
+                "collection",
+                &[
+                    ("1.17.0", serde_json::json!({})),
+                    ("1.18.0", serde_json::json!({})),
+                    ("1.19.0", serde_json::json!({})),
+                ],
+            ),
+        );
+        let sources = sources(&server).with_sdks(SdkPaths::new().with_flutter_root(&flutter_root));
+
+        let resolution = resolve(&sources, &app).unwrap();
+
+        assert_eq!(
+            versions(&resolution),
+            vec![
+                ("collection".to_string(), "1.18.0".to_string()),
+                ("flutter".to_string(), "0.0.0".to_string()),
+                ("flutter_test".to_string(), "0.0.0".to_string()),
+                ("sky_engine".to_string(), "0.0.0".to_string()),
+            ]
+        );
+        let flutter_test = &resolution.packages[&PackageName::new("flutter_test")];
+        assert_eq!(flutter_test.kind, DependencyKind::DirectDev);
+        assert!(matches!(
+            &flutter_test.source,
+            ResolvedSource::Sdk { sdk, dir } if sdk == "flutter" && dir.ends_with("packages/flutter_test")
+        ));
+
+        let cache = PubCache::new(temp_dir.path().join("cache")).unwrap();
+        let project = Project::load(&app).unwrap();
+        let lock = project.lock_file(&resolution);
+        assert!(matches!(
+            &lock.packages[&PackageName::new("sky_engine")].description,
+            Some(PackageDescription::Sdk(sdk)) if sdk == "flutter"
+        ));
+        let config = project.package_config(&resolution, &cache);
+        let sky_engine = config
+            .packages
+            .iter()
+            .find(|p| p.name == "sky_engine")
+            .unwrap();
+        assert!(
+            sky_engine
+                .root_uri
+                .as_ref()
+                .unwrap()
+                .ends_with("/bin/cache/pkg/sky_engine/")
+        );
+    }
+
+    #[test]
+    fn test_sdk_dependency_without_sdk() {
+        let temp_dir = TempDir::new().unwrap();
+        write_pubspec(
+            temp_dir.path(),
+            "name: app\ndependencies:\n  flutter:\n    sdk: flutter\n",
+        );
+        let server = MockServer::start();
+
+        let result = resolve(&sources(&server), temp_dir.path());
+        assert!(matches!(result, Err(ResolveError::SdkError(_))));
+    }
+
+    /// A Dart SDK claiming to be `version`
+    fn dart_sdk(dir: &Path, version: &str) -> SdkPaths {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("version"), format!("{}\n", version)).unwrap();
+        SdkPaths::new().with_dart_sdk(dir)
+    }
+
+    /// A listing whose versions are `(version, Dart SDK constraint)`
+    fn listing_with_sdks(name: &str, versions: &[(&str, &str)]) -> String {
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|(version, sdk)| ListedVersion::new(version).with_sdk(sdk))
+            .collect();
+        listing_json(name, &versions)
+    }
+
+    #[test]
+    fn test_project_requires_newer_sdk() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        write_pubspec(&app, "name: app\nenvironment:\n  sdk: ^3.5.0\n");
+        let server = MockServer::start();
+        let sources = sources(&server).with_sdks(dart_sdk(&temp_dir.path().join("dart"), "3.4.1"));
+
+        let error = resolve(&sources, &app).unwrap_err();
+
+        assert!(matches!(error, ResolveError::IncompatibleSdk { .. }));
+        assert_eq!(
+            error.to_string(),
+            "app 0.0.0 requires Dart SDK version >=3.5.0 <4.0.0, but the current Dart SDK version is 3.4.1"
+        );
+    }
+
+    #[test]
+    fn test_skips_versions_for_newer_sdks() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        write_pubspec(
+            &app,
+            "name: app\nenvironment:\n  sdk: ^3.0.0\ndependencies:\n  foo: ^1.0.0\n",
+        );
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing_with_sdks(
+                "foo",
+                &[
+                    ("1.0.0", ">=2.12.0 <3.0.0"),
+                    ("1.1.0", "^3.2.0"),
+                    ("1.2.0", "^3.5.0"),
+                ],
+            ),
+        );
+        let sources = sources(&server).with_sdks(dart_sdk(&temp_dir.path().join("dart"), "3.4.1"));
+
+        let resolution = resolve(&sources, &app).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("foo".to_string(), "1.1.0".to_string())]
+        );
+        let lock = Project::load(&app).unwrap().lock_file(&resolution);
+        assert_eq!(lock.sdks.unwrap().dart.as_deref(), Some(">=3.2.0 <4.0.0"));
+
+        // Packages for Dart 2 that are null safe still work with Dart 3
+        write_pubspec(
+            &app,
+            "name: app\nenvironment:\n  sdk: ^3.0.0\ndependencies:\n  foo: 1.0.0\n",
+        );
+        let resolution = resolve(&sources, &app).unwrap();
+        assert_eq!(
+            versions(&resolution),
+            vec![("foo".to_string(), "1.0.0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_locked_version_requires_newer_sdk() {
+        let temp_dir = TempDir::new().unwrap();
+        let app = temp_dir.path().join("app");
+        write_pubspec(&app, "name: app\ndependencies:\n  foo: ^1.0.0\n");
+        let server = MockServer::start();
+        server.json(
+            "/api/packages/foo",
+            listing_with_sdks("foo", &[("1.0.0", "^3.0.0"), ("1.1.0", "^3.5.0")]),
+        );
+
+        let newer = sources(&server).with_sdks(dart_sdk(&temp_dir.path().join("new"), "3.5.0"));
+        let resolution = resolve(&newer, &app).unwrap();
+        let lock = Project::load(&app).unwrap().lock_file(&resolution);
+        lock.write_to_file(app.join("pubspec.lock")).unwrap();
+
+        let older = sources(&server).with_sdks(dart_sdk(&temp_dir.path().join("old"), "3.4.0"));
+        let error = resolve(&older, &app).unwrap_err();
+        assert!(
+            matches!(&error, ResolveError::IncompatibleSdk { package, .. } if package.as_ref() == "foo"),
+            "{}",
+            error
+        );
+    }
+}
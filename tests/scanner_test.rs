@@ -54,4 +54,32 @@ version: 2.0.0
             }
         }
     }
+
+    #[test]
+    fn test_workspace_members_scan_as_their_workspace() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let member_dir = root.join("pkgs").join("a");
+        fs::create_dir_all(&member_dir).unwrap();
+        fs::write(
+            root.join("pubspec.yaml"),
+            "name: _\nworkspace:\n  - pkgs/a\n",
+        )
+        .unwrap();
+        fs::write(
+            member_dir.join("pubspec.yaml"),
+            "name: a\nresolution: workspace\n",
+        )
+        .unwrap();
+
+        let scanner = Scanner::new(vec![root.join("pkgs"), root.to_path_buf()]);
+        let results = scanner.scan();
+
+        assert_eq!(results.len(), 1);
+        let info = results.into_iter().next().unwrap().unwrap();
+        assert_eq!(info.pubspec.name, "_");
+        assert!(info.pubspec.is_workspace());
+        assert_eq!(info.members.len(), 1);
+        assert!(info.members[0].pubspec.is_workspace_member());
+    }
 }
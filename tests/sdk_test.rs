@@ -0,0 +1,204 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::pubspec::Environment;
+    use flutter_pub::pubspeclock::PackageName;
+    use flutter_pub::sdk::{SdkConstraints, SdkError, SdkPaths, SdkVersions};
+    use flutter_pub::version::parse_version;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    /// Just enough of a Flutter SDK to find its packages and bundled Dart SDK
+    fn fake_flutter(root: &Path) {
+        for package in [
+            "packages/flutter",
+            "packages/flutter_test",
+            "bin/cache/pkg/sky_engine",
+        ] {
+            let dir = root.join(package);
+            fs::create_dir_all(&dir).unwrap();
+            let name = dir.file_name().unwrap().to_str().unwrap().to_string();
+            fs::write(dir.join("pubspec.yaml"), format!("name: {}\n", name)).unwrap();
+        }
+        fs::create_dir_all(root.join("bin/cache/dart-sdk/bin")).unwrap();
+        fs::write(root.join("bin/flutter"), "#!/bin/sh\n").unwrap();
+        fs::write(root.join("bin/dart"), "#!/bin/sh\n").unwrap();
+    }
+
+    #[test]
+    fn test_flutter_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        fake_flutter(temp_dir.path());
+        let sdks = SdkPaths::new().with_flutter_root(temp_dir.path());
+
+        assert_eq!(
+            sdks.package_dir("flutter", &PackageName::new("flutter_test"))
+                .unwrap(),
+            temp_dir.path().join("packages/flutter_test")
+        );
+        assert_eq!(
+            sdks.package_dir("flutter", &PackageName::new("sky_engine"))
+                .unwrap(),
+            temp_dir.path().join("bin/cache/pkg/sky_engine")
+        );
+        assert!(matches!(
+            sdks.package_dir("flutter", &PackageName::new("nope")),
+            Err(SdkError::UnknownPackage { .. })
+        ));
+        assert!(matches!(
+            sdks.package_dir("fuchsia", &PackageName::new("flutter")),
+            Err(SdkError::UnknownSdk(_))
+        ));
+        assert!(matches!(
+            SdkPaths::new().package_dir("flutter", &PackageName::new("flutter")),
+            Err(SdkError::FlutterNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_locate_prefers_flutter_root() {
+        let env_root = TempDir::new().unwrap();
+        let config_root = TempDir::new().unwrap();
+        fake_flutter(env_root.path());
+
+        let sdks = SdkPaths::locate(
+            Some(env_root.path().into()),
+            None,
+            Some(config_root.path().to_path_buf()),
+            None,
+        );
+        assert_eq!(sdks.flutter_root(), Some(env_root.path()));
+        assert_eq!(
+            sdks.dart_sdk(),
+            Some(env_root.path().join("bin/cache/dart-sdk").as_path())
+        );
+
+        let sdks = SdkPaths::locate(None, None, Some(config_root.path().to_path_buf()), None);
+        assert_eq!(sdks.flutter_root(), Some(config_root.path()));
+        assert_eq!(sdks.dart_sdk(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_locate_on_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let flutter = temp_dir.path().join("flutter");
+        fake_flutter(&flutter);
+        let flutter = flutter.canonicalize().unwrap();
+
+        let path =
+            std::env::join_paths([temp_dir.path().join("elsewhere"), flutter.join("bin")]).unwrap();
+        let sdks = SdkPaths::locate(None, None, None, Some(path.clone()));
+        assert_eq!(sdks.flutter_root(), Some(flutter.as_path()));
+
+        // Flutter's dart wrapper leads to the bundled SDK, even without knowing about Flutter
+        let links = temp_dir.path().join("links");
+        fs::create_dir_all(&links).unwrap();
+        std::os::unix::fs::symlink(flutter.join("bin/dart"), links.join("dart")).unwrap();
+        let dart_only = std::env::join_paths([links]).unwrap();
+        let sdks = SdkPaths::locate(Some("".into()), None, None, Some(dart_only));
+        assert_eq!(sdks.flutter_root(), None);
+        assert_eq!(
+            sdks.dart_sdk(),
+            Some(flutter.join("bin/cache/dart-sdk").as_path())
+        );
+
+        let sdks = SdkPaths::locate(None, Some("/opt/dart".into()), None, Some(path));
+        assert_eq!(sdks.dart_sdk(), Some(Path::new("/opt/dart")));
+    }
+
+    #[test]
+    fn test_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let flutter = temp_dir.path().join("flutter");
+        fake_flutter(&flutter);
+        fs::write(flutter.join("version"), "3.22.1\n").unwrap();
+        fs::write(flutter.join("bin/cache/dart-sdk/version"), "3.4.1\n").unwrap();
+
+        let sdks = SdkPaths::locate(Some(flutter.clone().into()), None, None, None);
+        assert_eq!(
+            sdks.versions(),
+            SdkVersions {
+                dart: Some(parse_version("3.4.1").unwrap()),
+                flutter: Some(parse_version("3.22.1").unwrap()),
+            }
+        );
+
+        // Newer Flutter SDKs only have flutter.version.json
+        fs::remove_file(flutter.join("version")).unwrap();
+        fs::remove_file(flutter.join("bin/cache/dart-sdk/version")).unwrap();
+        fs::write(
+            flutter.join("bin/cache/flutter.version.json"),
+            r#"{"frameworkVersion": "3.24.0", "channel": "stable", "dartSdkVersion": "3.5.0"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sdks.versions(),
+            SdkVersions {
+                dart: Some(parse_version("3.5.0").unwrap()),
+                flutter: Some(parse_version("3.24.0").unwrap()),
+            }
+        );
+
+        assert_eq!(SdkPaths::new().versions(), SdkVersions::default());
+    }
+
+    fn constraints(sdk: &str, flutter: Option<&str>) -> SdkConstraints {
+        SdkConstraints::from_environment(Some(&Environment {
+            sdk: sdk.to_string(),
+            flutter: flutter.map(String::from),
+            extra: Default::default(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_incompatibility() {
+        let installed = SdkVersions {
+            dart: Some(parse_version("3.4.1").unwrap()),
+            flutter: Some(parse_version("3.22.1").unwrap()),
+        };
+
+        assert_eq!(
+            constraints("^3.0.0", Some(">=3.10.0")).incompatibility(&installed),
+            None
+        );
+        // Null safe Dart 2 packages are allowed on Dart 3
+        assert_eq!(
+            constraints(">=2.12.0 <3.0.0", None).incompatibility(&installed),
+            None
+        );
+
+        let reason = constraints("^3.5.0", None)
+            .incompatibility(&installed)
+            .unwrap();
+        assert_eq!(
+            reason.to_string(),
+            "requires Dart SDK version >=3.5.0 <4.0.0, but the current Dart SDK version is 3.4.1"
+        );
+        assert!(
+            constraints(">=2.7.0 <3.0.0", None)
+                .incompatibility(&installed)
+                .is_some()
+        );
+
+        let reason = constraints("^3.0.0", Some(">=3.24.0"))
+            .incompatibility(&installed)
+            .unwrap();
+        assert_eq!(reason.sdk, "Flutter");
+
+        // Unknown SDK versions can't be checked
+        assert_eq!(
+            constraints("^3.5.0", Some(">=3.24.0")).incompatibility(&SdkVersions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_intersect() {
+        let merged =
+            constraints("^3.0.0", None).intersect(&constraints(">=3.2.0 <4.0.0", Some(">=3.16.0")));
+        assert_eq!(merged.dart.unwrap().to_range_string(), ">=3.2.0 <4.0.0");
+        assert_eq!(merged.flutter.unwrap().to_range_string(), ">=3.16.0");
+    }
+}
@@ -0,0 +1,337 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{
+        ListedVersion, MockResponse, MockServer, listing_json, sha256_hex, tar_gz,
+    };
+    use flutter_pub::downloader::PackageDownloader;
+    use flutter_pub::pubcache::PubCache;
+    use flutter_pub::pubclient::{PubClient, PubClientError};
+    use flutter_pub::pubspeclock::{PackageName, PackageVersion, Sha256};
+    use flutter_pub::server::{CacheRepository, Server};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, mpsc};
+    use std::thread;
+    use tempfile::TempDir;
+    use url::Url;
+
+    struct RunningServer {
+        server: Arc<Server>,
+        url: Url,
+    }
+
+    impl Drop for RunningServer {
+        fn drop(&mut self) {
+            self.server.unblock();
+        }
+    }
+
+    fn serve(cache_dir: &Path) -> RunningServer {
+        start(CacheRepository::new(
+            PubCache::new(cache_dir).unwrap(),
+            "pub.dev",
+        ))
+    }
+
+    fn proxy(cache_dir: &Path, upstream: &MockServer) -> RunningServer {
+        let client = PubClient::new(Url::parse(&upstream.url).unwrap());
+        start(
+            CacheRepository::new(PubCache::new(cache_dir).unwrap(), "pub.dev")
+                .with_upstream(client)
+                .unwrap(),
+        )
+    }
+
+    fn start(repository: CacheRepository) -> RunningServer {
+        let server = Arc::new(Server::bind("127.0.0.1:0", repository).unwrap());
+        let url = Url::parse(&format!("http://{}", server.local_addr().unwrap())).unwrap();
+
+        let s = server.clone();
+        thread::spawn(move || s.run());
+
+        RunningServer { server, url }
+    }
+
+    /// Caches an archive downloaded from `host`, and extracts its pubspec as an install would
+    fn cache_archive(cache: &PubCache, host: &str, name: &str, version: &str) -> Vec<u8> {
+        let (name, version) = (PackageName::new(name), PackageVersion::new(version));
+        let pubspec = format!("name: {}\nversion: {}\n", name, version);
+        let archive = tar_gz(&[("pubspec.yaml", &pubspec), ("lib/lib.dart", "")]);
+        let path = cache.archive_path(host, &name, &version);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, &archive).unwrap();
+        let extracted = cache.hosted_package_path(host, &name, &version);
+        fs::create_dir_all(&extracted).unwrap();
+        fs::write(extracted.join("pubspec.yaml"), pubspec).unwrap();
+        archive
+    }
+
+    #[test]
+    fn test_listing_of_cached_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(temp_dir.path()).unwrap();
+        let archive = cache_archive(&cache, "pub.dev", "foo", "1.0.0");
+        cache_archive(&cache, "pub.dev", "foo", "0.9.0");
+        cache_archive(&cache, "pub.dev", "foo", "1.1.0-dev.1");
+        cache_archive(&cache, "pub.dev", "foo_bar", "2.0.0");
+
+        let running = serve(temp_dir.path());
+        let listing = PubClient::new(running.url.clone())
+            .listing(&PackageName::new("foo"))
+            .unwrap();
+
+        let versions: Vec<_> = listing
+            .versions
+            .iter()
+            .map(|v| v.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["0.9.0", "1.0.0", "1.1.0-dev.1"]);
+        assert_eq!(listing.latest.version, PackageVersion::new("1.0.0"));
+
+        let latest = listing.version(&PackageVersion::new("1.0.0")).unwrap();
+        assert_eq!(
+            latest.archive_sha256,
+            Some(Sha256::new(sha256_hex(&archive)))
+        );
+        assert_eq!(
+            latest.archive_url,
+            format!("{}packages/foo/versions/1.0.0.tar.gz", running.url)
+        );
+        assert_eq!(latest.parse_pubspec().unwrap().unwrap().name, "foo");
+
+        // The hash is now recorded in hosted-hashes, like one from a download
+        assert_eq!(
+            cache
+                .read_package_hash(
+                    "pub.dev",
+                    &PackageName::new("foo"),
+                    &PackageVersion::new("1.0.0")
+                )
+                .unwrap(),
+            Some(Sha256::new(sha256_hex(&archive)))
+        );
+    }
+
+    #[test]
+    fn test_listing_leaves_out_other_hosts() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(temp_dir.path()).unwrap();
+        cache_archive(&cache, "pub.dev", "foo", "1.0.0");
+        cache_archive(&cache, "example.com", "foo", "2.0.0");
+        let running = serve(temp_dir.path());
+
+        let listing = PubClient::new(running.url.clone())
+            .listing(&PackageName::new("foo"))
+            .unwrap();
+        let versions: Vec<_> = listing
+            .versions
+            .iter()
+            .map(|v| v.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["1.0.0"]);
+
+        let archive_url = running
+            .url
+            .join("packages/foo/versions/2.0.0.tar.gz")
+            .unwrap();
+        let response = ureq::get(archive_url.as_str()).call();
+        assert!(matches!(response, Err(ureq::Error::Status(404, _))));
+    }
+
+    #[test]
+    fn test_prefers_extracted_pubspec_and_recorded_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = PubCache::new(temp_dir.path()).unwrap();
+        let name = PackageName::new("foo");
+        let version = PackageVersion::new("1.0.0");
+        cache_archive(&cache, "pub.dev", "foo", "1.0.0");
+
+        let extracted = cache.hosted_package_path("pub.dev", &name, &version);
+        fs::create_dir_all(&extracted).unwrap();
+        fs::write(
+            extracted.join("pubspec.yaml"),
+            "name: foo\nversion: 1.0.0\ndescription: extracted\n",
+        )
+        .unwrap();
+        cache
+            .write_package_hash("pub.dev", &name, &version, &Sha256::new("abc123"))
+            .unwrap();
+
+        let running = serve(temp_dir.path());
+        let listing = PubClient::new(running.url.clone()).listing(&name).unwrap();
+
+        assert_eq!(listing.latest.archive_sha256, Some(Sha256::new("abc123")));
+        assert_eq!(
+            listing
+                .latest
+                .parse_pubspec()
+                .unwrap()
+                .unwrap()
+                .description
+                .as_deref(),
+            Some("extracted")
+        );
+    }
+
+    #[test]
+    fn test_download_from_served_cache() {
+        let source_dir = TempDir::new().unwrap();
+        let archive = cache_archive(
+            &PubCache::new(source_dir.path()).unwrap(),
+            "pub.dev",
+            "foo",
+            "1.0.0",
+        );
+        let running = serve(source_dir.path());
+
+        let target_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(target_dir.path())
+            .unwrap()
+            .with_client(PubClient::new(running.url.clone()));
+        let (tx, _rx) = mpsc::channel();
+
+        let path = downloader
+            .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+            .unwrap();
+
+        assert_eq!(fs::read(path).unwrap(), archive);
+    }
+
+    #[test]
+    fn test_unknown_package_not_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let running = serve(temp_dir.path());
+
+        let result = PubClient::new(running.url.clone()).listing(&PackageName::new("nope"));
+        assert!(matches!(
+            result,
+            Err(PubClientError::PackageNotFound { .. })
+        ));
+
+        let archive_url = running
+            .url
+            .join("packages/nope/versions/1.0.0.tar.gz")
+            .unwrap();
+        let response = ureq::get(archive_url.as_str()).call();
+        assert!(matches!(response, Err(ureq::Error::Status(404, _))));
+    }
+
+    /// An upstream with foo 1.0.0, whose listing has the archive's hash unless `sha256` is given
+    fn upstream_with_foo(sha256: Option<&str>) -> (MockServer, Vec<u8>) {
+        let upstream = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\nversion: 1.0.0\n")]);
+        let archive_url = format!("{}/blobs/foo-1.0.0.tar.gz", upstream.url);
+        let archive_sha256 = sha256.map(String::from).unwrap_or(sha256_hex(&archive));
+        upstream.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, &archive_sha256)],
+            ),
+        );
+        upstream.bytes("/blobs/foo-1.0.0.tar.gz", archive.clone());
+        (upstream, archive)
+    }
+
+    #[test]
+    fn test_proxy_rewrites_archive_urls() {
+        let (upstream, archive) = upstream_with_foo(None);
+        let temp_dir = TempDir::new().unwrap();
+        let running = proxy(temp_dir.path(), &upstream);
+
+        let listing = PubClient::new(running.url.clone())
+            .listing(&PackageName::new("foo"))
+            .unwrap();
+
+        let expected = format!("{}packages/foo/versions/1.0.0.tar.gz", running.url);
+        assert_eq!(listing.latest.archive_url, expected);
+        assert_eq!(listing.versions[0].archive_url, expected);
+        assert_eq!(
+            listing.latest.archive_sha256,
+            Some(Sha256::new(sha256_hex(&archive)))
+        );
+    }
+
+    #[test]
+    fn test_proxy_caches_archives() {
+        let (upstream, archive) = upstream_with_foo(None);
+        let proxy_dir = TempDir::new().unwrap();
+        let running = proxy(proxy_dir.path(), &upstream);
+
+        for _ in 0..2 {
+            let client_dir = TempDir::new().unwrap();
+            let downloader = PackageDownloader::new(client_dir.path())
+                .unwrap()
+                .with_client(PubClient::new(running.url.clone()));
+            let (tx, _rx) = mpsc::channel();
+            let path = downloader
+                .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+                .unwrap();
+            assert_eq!(fs::read(path).unwrap(), archive);
+        }
+
+        let cache = PubCache::new(proxy_dir.path()).unwrap();
+        let cached = cache.archive_path(
+            "pub.dev",
+            &PackageName::new("foo"),
+            &PackageVersion::new("1.0.0"),
+        );
+        assert_eq!(fs::read(cached).unwrap(), archive);
+
+        let archive_fetches = upstream
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/blobs/"))
+            .count();
+        assert_eq!(archive_fetches, 1);
+    }
+
+    #[test]
+    fn test_proxy_rejects_archive_with_wrong_hash() {
+        let (upstream, _) = upstream_with_foo(Some(&"0".repeat(64)));
+        let proxy_dir = TempDir::new().unwrap();
+        let running = proxy(proxy_dir.path(), &upstream);
+
+        let archive_url = running
+            .url
+            .join("packages/foo/versions/1.0.0.tar.gz")
+            .unwrap();
+        let response = ureq::get(archive_url.as_str()).call();
+        assert!(matches!(response, Err(ureq::Error::Status(502, _))));
+
+        let cache = PubCache::new(proxy_dir.path()).unwrap();
+        assert!(
+            !cache
+                .archive_path(
+                    "pub.dev",
+                    &PackageName::new("foo"),
+                    &PackageVersion::new("1.0.0")
+                )
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_proxy_serves_cache_when_upstream_fails() {
+        let upstream = MockServer::start();
+        upstream.route("/api/packages/foo", MockResponse::Status(503));
+        let proxy_dir = TempDir::new().unwrap();
+        cache_archive(
+            &PubCache::new(proxy_dir.path()).unwrap(),
+            "pub.dev",
+            "foo",
+            "1.0.0",
+        );
+        let running = proxy(proxy_dir.path(), &upstream);
+
+        let listing = PubClient::new(running.url.clone())
+            .listing(&PackageName::new("foo"))
+            .unwrap();
+        assert_eq!(listing.latest.version, PackageVersion::new("1.0.0"));
+
+        let result = PubClient::new(running.url.clone()).listing(&PackageName::new("bar"));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,256 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{
+        ListedVersion, MockResponse, MockServer, listing_json, sha256_hex, tar_gz,
+    };
+    use flutter_pub::downloader::{DownloadError, PackageDownloader};
+    use flutter_pub::pubclient::{self, PubClient, PubClientError};
+    use flutter_pub::pubspeclock::{PackageName, PackageVersion};
+    use flutter_pub::tokens::{Credential, TokenError, TokenStore};
+    use std::fs;
+    use std::sync::mpsc;
+    use tempfile::TempDir;
+    use url::Url;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_load_dart_tokens_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("pub-tokens.json");
+        fs::write(
+            &path,
+            r#"{
+                "version": 1,
+                "hosted": [
+                    {"url": "https://pub.example.com", "token": "secret"},
+                    {"url": "https://other.example.com/repo", "env": "OTHER_TOKEN"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let tokens = TokenStore::load(&path).unwrap();
+        assert_eq!(tokens.credentials().len(), 2);
+        assert_eq!(tokens.credentials()[0].token.as_deref(), Some("secret"));
+        assert_eq!(tokens.credentials()[1].env.as_deref(), Some("OTHER_TOKEN"));
+    }
+
+    #[test]
+    fn test_missing_tokens_file_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let tokens = TokenStore::load(temp_dir.path().join("pub-tokens.json")).unwrap();
+        assert!(tokens.credentials().is_empty());
+    }
+
+    #[test]
+    fn test_add_remove_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("dart").join("pub-tokens.json");
+
+        let mut tokens = TokenStore::load(&path).unwrap();
+        tokens
+            .add(Credential::with_token(
+                url("https://pub.example.com/"),
+                "one",
+            ))
+            .unwrap();
+        tokens
+            .add(Credential::with_token(
+                url("https://pub.example.com"),
+                "two",
+            ))
+            .unwrap();
+        tokens
+            .add(Credential::with_env_var(
+                url("https://other.example.com"),
+                "OTHER",
+            ))
+            .unwrap();
+        tokens.save().unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["hosted"][0]["url"], "https://pub.example.com");
+        assert_eq!(json["hosted"][0]["token"], "two");
+        assert_eq!(json["hosted"][1]["env"], "OTHER");
+        assert!(json["hosted"][1].get("token").is_none());
+
+        let mut tokens = TokenStore::load(&path).unwrap();
+        assert_eq!(tokens.credentials().len(), 2);
+        assert!(tokens.remove(&url("https://pub.example.com/")).unwrap());
+        assert!(!tokens.remove(&url("https://pub.example.com")).unwrap());
+        assert_eq!(tokens.credentials().len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_insecure_urls() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tokens = TokenStore::load(temp_dir.path().join("pub-tokens.json")).unwrap();
+
+        let result = tokens.add(Credential::with_token(url("http://pub.example.com"), "x"));
+        assert!(matches!(result, Err(TokenError::InsecureUrl(_))));
+
+        tokens
+            .add(Credential::with_token(url("http://localhost:8080"), "x"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_matches_hosted_url_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tokens = TokenStore::load(temp_dir.path().join("pub-tokens.json")).unwrap();
+        tokens
+            .add(Credential::with_token(url("https://example.com"), "root"))
+            .unwrap();
+        tokens
+            .add(Credential::with_token(
+                url("https://example.com/repo"),
+                "repo",
+            ))
+            .unwrap();
+
+        let token = |u: &str| tokens.find(&url(u)).and_then(Credential::token);
+
+        assert_eq!(
+            token("https://example.com/api/packages/foo").as_deref(),
+            Some("root")
+        );
+        assert_eq!(
+            token("https://example.com/repo/api/packages/foo").as_deref(),
+            Some("repo")
+        );
+        assert_eq!(
+            token("https://example.com/repository/x").as_deref(),
+            Some("root")
+        );
+        assert_eq!(token("https://example.org/api/packages/foo"), None);
+    }
+
+    #[test]
+    fn test_env_var_token() {
+        let credential =
+            Credential::with_env_var(url("https://example.com"), "FLUTTER_PUB_TEST_TOKEN_UNSET");
+        assert_eq!(credential.token(), None);
+
+        let credential = Credential::with_env_var(url("https://example.com"), "PATH");
+        assert_eq!(credential.token(), std::env::var("PATH").ok());
+    }
+
+    fn authenticated(server: &MockServer, temp_dir: &TempDir, token: &str) -> ureq::Agent {
+        let mut tokens = TokenStore::load(temp_dir.path().join("pub-tokens.json")).unwrap();
+        tokens
+            .add(Credential::with_token(url(&server.url), token))
+            .unwrap();
+        pubclient::authenticated_agent(tokens)
+    }
+
+    #[test]
+    fn test_token_sent_with_listing_and_archive() {
+        let server = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let archive_url = format!("{}/archives/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, &sha256_hex(&archive))],
+            ),
+        );
+        server.bytes("/archives/foo-1.0.0.tar.gz", archive);
+
+        let temp_dir = TempDir::new().unwrap();
+        let agent = authenticated(&server, &temp_dir, "sekrit");
+        let downloader = PackageDownloader::new(temp_dir.path().join("downloads"))
+            .unwrap()
+            .with_client(PubClient::with_agent(url(&server.url), agent));
+        let (tx, _rx) = mpsc::channel();
+
+        downloader
+            .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert_eq!(request.authorization.as_deref(), Some("Bearer sekrit"));
+        }
+    }
+
+    #[test]
+    fn test_token_not_sent_to_other_hosts() {
+        let server = MockServer::start();
+        let other = MockServer::start();
+        let archive = tar_gz(&[("pubspec.yaml", "name: foo\n")]);
+        let archive_url = format!("{}/archives/foo-1.0.0.tar.gz", server.url);
+        server.json(
+            "/api/packages/foo",
+            listing_json(
+                "foo",
+                &[ListedVersion::new("1.0.0").with_archive(&archive_url, &sha256_hex(&archive))],
+            ),
+        );
+        // Same machine, but a different host as far as the redirect is concerned
+        let other_url = other.url.replace("127.0.0.1", "localhost");
+        server.route(
+            "/archives/foo-1.0.0.tar.gz",
+            MockResponse::Redirect(format!("{}/blob", other_url)),
+        );
+        other.bytes("/blob", archive);
+
+        let temp_dir = TempDir::new().unwrap();
+        let agent = authenticated(&server, &temp_dir, "sekrit");
+        let downloader = PackageDownloader::new(temp_dir.path().join("downloads"))
+            .unwrap()
+            .with_client(PubClient::with_agent(url(&server.url), agent));
+        let (tx, _rx) = mpsc::channel();
+
+        downloader
+            .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+            .unwrap();
+
+        let requests = other.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].authorization, None);
+    }
+
+    #[test]
+    fn test_unauthorized_listing() {
+        let server = MockServer::start();
+        server.route("/api/packages/foo", MockResponse::Status(401));
+
+        let client = PubClient::new(url(&server.url));
+        let result = client.listing(&PackageName::new("foo"));
+        assert!(matches!(
+            result,
+            Err(PubClientError::AuthenticationFailed { status: 401, .. })
+        ));
+    }
+
+    #[test]
+    fn test_forbidden_download_explains_token() {
+        let server = MockServer::start();
+        server.route("/api/packages/foo", MockResponse::Status(403));
+
+        let temp_dir = TempDir::new().unwrap();
+        let downloader = PackageDownloader::new(temp_dir.path())
+            .unwrap()
+            .with_client(PubClient::new(url(&server.url)));
+        let (tx, _rx) = mpsc::channel();
+
+        let error = downloader
+            .download_package(&PackageName::new("foo"), &PackageVersion::new("1.0.0"), &tx)
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            DownloadError::AuthenticationFailed { status: 403, .. }
+        ));
+        assert!(error.to_string().contains("flutter-pub token add"));
+    }
+}
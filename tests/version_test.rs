@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests {
+    use flutter_pub::version::{VersionConstraint, VersionError, parse_version};
+
+    fn v(version: &str) -> semver::Version {
+        parse_version(version).unwrap()
+    }
+
+    fn c(constraint: &str) -> VersionConstraint {
+        VersionConstraint::parse(constraint).unwrap()
+    }
+
+    #[test]
+    fn test_caret() {
+        let constraint = c("^1.2.3");
+        assert!(constraint.allows(&v("1.2.3")));
+        assert!(constraint.allows(&v("1.9.0")));
+        assert!(!constraint.allows(&v("1.2.2")));
+        assert!(!constraint.allows(&v("2.0.0")));
+
+        let zero = c("^0.2.3");
+        assert!(zero.allows(&v("0.2.9")));
+        assert!(!zero.allows(&v("0.3.0")));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let constraint = c(">=1.0.0 <2.0.0");
+        assert!(constraint.allows(&v("1.0.0")));
+        assert!(!constraint.allows(&v("2.0.0")));
+        assert_eq!(constraint, c("^1.0.0"));
+        assert_eq!(c(">= 1.0.0 < 2.0.0"), constraint);
+        assert_eq!(c(">=1.0.0<2.0.0"), constraint);
+
+        assert!(c(">1.0.0").allows(&v("1.0.1")));
+        assert!(!c(">1.0.0").allows(&v("1.0.0")));
+        assert!(c("<=1.0.0").allows(&v("1.0.0")));
+        assert!(c("any").allows(&v("0.0.1")));
+        assert!(c("1.2.3").allows(&v("1.2.3")));
+        assert!(!c("1.2.3").allows(&v("1.2.4")));
+    }
+
+    #[test]
+    fn test_prereleases_below_max_are_excluded() {
+        let constraint = c(">=1.0.0 <2.0.0");
+        assert!(!constraint.allows(&v("2.0.0-dev.1")));
+        assert!(constraint.allows(&v("1.5.0-dev.1")));
+        assert!(c(">=2.0.0-dev.1 <2.0.0").allows(&v("2.0.0-dev.2")));
+    }
+
+    #[test]
+    fn test_intersect() {
+        assert_eq!(c("^1.0.0").intersect(&c(">=1.2.0")), c(">=1.2.0 <2.0.0"));
+        assert!(c("^1.0.0").intersect(&c("^2.0.0")).is_empty());
+        assert!(c("<1.0.0").intersect(&c(">=1.0.0")).is_empty());
+        assert!(c("any").intersect(&c("any")).is_any());
+        assert!(c("^1.0.0").allows_all(&c("^1.2.0")));
+        assert!(!c("^1.2.0").allows_all(&c("^1.0.0")));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(c("any").to_string(), "any");
+        assert_eq!(c(">=1.2.3 <2.0.0").to_string(), "^1.2.3");
+        assert_eq!(c(">=1.2.3 <1.5.0").to_string(), ">=1.2.3 <1.5.0");
+        assert_eq!(c("1.2.3").to_string(), "1.2.3");
+        assert_eq!(c(">=3.0.0").to_string(), ">=3.0.0");
+    }
+
+    #[test]
+    fn test_tightened_to() {
+        assert_eq!(c("^1.0.0").tightened_to(&v("1.2.3")), Some(c("^1.2.3")));
+        assert_eq!(
+            c(">=1.0.0 <3.0.0").tightened_to(&v("2.1.0")),
+            Some(c(">=2.1.0 <3.0.0"))
+        );
+        assert_eq!(c("any").tightened_to(&v("0.4.1")), Some(c("^0.4.1")));
+        assert_eq!(c("^1.2.3").tightened_to(&v("1.2.3")), None);
+        assert_eq!(c("^1.0.0").tightened_to(&v("2.0.0")), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            VersionConstraint::parse("banana"),
+            Err(VersionError::InvalidConstraint(_))
+        ));
+        assert!(VersionConstraint::parse("1.0.0 2.0.0").is_err());
+        assert!(VersionConstraint::parse("").is_err());
+        assert!(matches!(
+            parse_version("1.0"),
+            Err(VersionError::InvalidVersion { .. })
+        ));
+    }
+}